@@ -0,0 +1,84 @@
+use crate::CliError;
+use mvola::Msisdn;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// The settings read from the config file.
+///
+/// The command-line flags and the `MVOLA_*` environment variables take
+/// precedence over the file:
+///
+/// ```toml
+/// consumer_key = "..."
+/// consumer_secret = "..."
+/// merchant = "0343500004"
+/// partner_name = "TestMVola"
+/// user_language = "FR"
+/// production = false
+/// ```
+#[derive(Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+  pub consumer_key: Option<String>,
+  pub consumer_secret: Option<String>,
+  pub merchant: Option<Msisdn>,
+  pub partner_name: Option<String>,
+  pub user_language: Option<String>,
+  #[serde(default)]
+  pub production: bool,
+  pub base_url: Option<String>,
+}
+
+impl Config {
+  /// Read the given file, or the default file if it exists.
+  pub(crate) fn load(path: Option<&Path>) -> Result<Self, CliError> {
+    let path = match path {
+      Some(path) => PathBuf::from(path),
+      None => match default_path().filter(|path| path.is_file()) {
+        Some(path) => path,
+        None => return Ok(Self::default()),
+      },
+    };
+    let content =
+      std::fs::read_to_string(&path).map_err(|e| CliError::Config(path.clone(), e.to_string()))?;
+    Self::parse(&content).map_err(|e| CliError::Config(path, e.to_string()))
+  }
+
+  fn parse(content: &str) -> Result<Self, toml::de::Error> {
+    toml::from_str(content)
+  }
+}
+
+/// `$XDG_CONFIG_HOME/mvola/config.toml`, or `~/.config/mvola/config.toml`.
+fn default_path() -> Option<PathBuf> {
+  let config_home = std::env::var_os("XDG_CONFIG_HOME")
+    .map(PathBuf::from)
+    .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+  Some(config_home.join("mvola").join("config.toml"))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::Config;
+  use mvola::Msisdn;
+
+  #[test]
+  fn test_parse() {
+    let config = Config::parse(
+      r#"
+        consumer_key = "consumer_key"
+        consumer_secret = "consumer_secret"
+        merchant = "+261 34 35 000 04"
+        production = true
+      "#,
+    )
+    .unwrap();
+    assert_eq!(config.consumer_key.as_deref(), Some("consumer_key"));
+    assert_eq!(config.merchant, Some(Msisdn::new("0343500004").unwrap()));
+    assert!(config.production);
+    assert_eq!(config.partner_name, None);
+
+    assert!(Config::parse("consumer_keys = \"typo\"").is_err());
+    assert!(Config::parse("merchant = \"0321234567\"").is_err());
+  }
+}
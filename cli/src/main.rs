@@ -0,0 +1,394 @@
+//! `mvola`, a command-line client for the MVola API.
+//!
+//! ```sh
+//! export MVOLA_CONSUMER_KEY=... MVOLA_CONSUMER_SECRET=... MVOLA_MERCHANT=0343500004
+//! mvola pay --customer 0343500003 --amount 1000 --description "Order 42"
+//! mvola wait 421a22a2-ef1d-42bc-9452-f4939a3d5cdf
+//! mvola --json details 636042511
+//! ```
+
+mod config;
+mod output;
+
+use clap::{Parser, Subcommand};
+use config::Config;
+use mvola::types::TransactionRequest;
+use mvola::{Environment, MVola, MVolaError, Money, Msisdn, PollPolicy};
+use output::{render, WaitOutcome};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(
+  name = "mvola",
+  version,
+  about = "Command-line client for the MVola API"
+)]
+struct Cli {
+  /// The config file [default: ~/.config/mvola/config.toml]
+  #[arg(long, global = true, env = "MVOLA_CONFIG")]
+  config: Option<PathBuf>,
+  /// Send the requests to the production API instead of the sandbox
+  #[arg(long, global = true)]
+  production: bool,
+  /// Send the requests to a custom base URL, e.g. a simulator
+  #[arg(
+    long,
+    global = true,
+    env = "MVOLA_BASE_URL",
+    conflicts_with = "production"
+  )]
+  base_url: Option<String>,
+  #[arg(
+    long,
+    global = true,
+    env = "MVOLA_CONSUMER_KEY",
+    hide_env_values = true
+  )]
+  consumer_key: Option<String>,
+  #[arg(
+    long,
+    global = true,
+    env = "MVOLA_CONSUMER_SECRET",
+    hide_env_values = true
+  )]
+  consumer_secret: Option<String>,
+  /// The merchant account, e.g. 0343500004
+  #[arg(long, global = true, env = "MVOLA_MERCHANT")]
+  merchant: Option<Msisdn>,
+  /// Print JSON instead of tables
+  #[arg(long, global = true)]
+  json: bool,
+  #[command(subcommand)]
+  command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+  /// Generate an access token
+  Token,
+  /// Request a payment from a customer to the merchant
+  Pay {
+    /// The customer paying, e.g. 0343500003
+    #[arg(long)]
+    customer: Msisdn,
+    /// The amount in Ariary, e.g. 1000
+    #[arg(long)]
+    amount: Money,
+    #[arg(long)]
+    description: String,
+    /// The reference of the payment [default: a random UUID]
+    #[arg(long)]
+    reference: Option<String>,
+    /// The URL MVola notifies when the payment completes
+    #[arg(long)]
+    callback_url: Option<String>,
+  },
+  /// Get the status of a payment
+  Status { server_correlation_id: String },
+  /// Get the details of a completed transaction
+  Details { transaction_id: String },
+  /// Poll the status of a payment until it completes or fails
+  ///
+  /// Exits with 2 if the payment failed.
+  Wait {
+    server_correlation_id: String,
+    /// Seconds between two status requests
+    #[arg(long, default_value_t = 2)]
+    interval: u64,
+    /// Seconds before giving up
+    #[arg(long, default_value_t = 180)]
+    timeout: u64,
+  },
+}
+
+#[derive(Debug, thiserror::Error)]
+enum CliError {
+  #[error("cannot read {}: {}", .0.display(), .1)]
+  Config(PathBuf, String),
+  #[error(transparent)]
+  MVola(#[from] MVolaError),
+}
+
+/// The command-line settings, completed with the config file.
+struct Settings {
+  cli: Cli,
+  config: Config,
+}
+
+impl Settings {
+  fn consumer_credentials(&self) -> Result<(String, String), MVolaError> {
+    let consumer_key = self
+      .cli
+      .consumer_key
+      .clone()
+      .or(self.config.consumer_key.clone());
+    let consumer_secret = self
+      .cli
+      .consumer_secret
+      .clone()
+      .or(self.config.consumer_secret.clone());
+    match (consumer_key, consumer_secret) {
+      (Some(consumer_key), Some(consumer_secret)) => Ok((consumer_key, consumer_secret)),
+      (consumer_key, consumer_secret) => {
+        let missing = [
+          ("consumer_key", consumer_key),
+          ("consumer_secret", consumer_secret),
+        ]
+        .into_iter()
+        .filter(|(_, value)| value.is_none())
+        .map(|(name, _)| name)
+        .collect();
+        Err(MVolaError::MissingConfiguration(missing))
+      }
+    }
+  }
+
+  fn merchant(&self) -> Result<&Msisdn, MVolaError> {
+    self
+      .cli
+      .merchant
+      .as_ref()
+      .or(self.config.merchant.as_ref())
+      .ok_or_else(|| MVolaError::MissingConfiguration(vec!["merchant"]))
+  }
+
+  /// The flags win over the config file: `--production` sends to the
+  /// production API even if the file sets a `base_url`.
+  fn base_url(&self) -> String {
+    if let Some(base_url) = &self.cli.base_url {
+      return base_url.clone();
+    }
+    if self.cli.production {
+      return String::from(Environment::Production.base_url());
+    }
+    if let Some(base_url) = &self.config.base_url {
+      return base_url.clone();
+    }
+    let environment = if self.config.production {
+      Environment::Production
+    } else {
+      Environment::Sandbox
+    };
+    String::from(environment.base_url())
+  }
+
+  /// A client sending the requests of the merchant.
+  fn client(&self, callback_url: Option<&str>) -> Result<MVola, MVolaError> {
+    let (consumer_key, consumer_secret) = self.consumer_credentials()?;
+    let mut builder = MVola::builder()
+      .base_url(&self.base_url())
+      .credentials(&consumer_key, &consumer_secret)
+      .user_account_identifier(&self.merchant()?.account_identifier())
+      .user_agent(concat!("mvola-cli/", env!("CARGO_PKG_VERSION")));
+    if let Some(user_language) = &self.config.user_language {
+      builder = builder.user_language(user_language);
+    }
+    if let Some(partner_name) = &self.config.partner_name {
+      builder = builder.partner_name(partner_name);
+    }
+    if let Some(callback_url) = callback_url {
+      builder = builder.callback_url(callback_url);
+    }
+    builder.build()
+  }
+}
+
+/// Run a command, returning its output and exit code.
+async fn run(cli: Cli) -> Result<(String, ExitCode), CliError> {
+  let config = Config::load(cli.config.as_deref())?;
+  let settings = Settings { cli, config };
+  let json = settings.cli.json;
+
+  let output = match &settings.cli.command {
+    Command::Token => {
+      let (consumer_key, consumer_secret) = settings.consumer_credentials()?;
+      let client = MVola::builder().base_url(&settings.base_url()).build()?;
+      let token = client
+        .auth
+        .generate_token(&consumer_key, &consumer_secret)
+        .await?;
+      render(&token, json)
+    }
+    Command::Pay {
+      customer,
+      amount,
+      description,
+      reference,
+      callback_url,
+    } => {
+      let client = settings.client(callback_url.as_deref())?;
+      let mut tx = TransactionRequest::builder()
+        .amount(*amount)
+        .debit(customer.clone())
+        .credit(settings.merchant()?.clone())
+        .description(description);
+      if let Some(partner_name) = &settings.config.partner_name {
+        tx = tx.partner_name(partner_name);
+      }
+      if let Some(reference) = reference {
+        tx = tx.reference(reference);
+      }
+      let response = client.transaction.send_payment(tx.build()?).await?;
+      render(&response, json)
+    }
+    Command::Status {
+      server_correlation_id,
+    } => {
+      let client = settings.client(None)?;
+      let status = client
+        .transaction
+        .get_transaction_status(server_correlation_id)
+        .await?;
+      render(&status, json)
+    }
+    Command::Details { transaction_id } => {
+      let client = settings.client(None)?;
+      let details = client.transaction.get_transaction(transaction_id).await?;
+      render(&details, json)
+    }
+    Command::Wait {
+      server_correlation_id,
+      interval,
+      timeout,
+    } => {
+      let client = settings.client(None)?;
+      let policy = PollPolicy::new()
+        .interval(Duration::from_secs(*interval))
+        .deadline(Duration::from_secs(*timeout));
+      let completion = client
+        .transaction
+        .wait_for_completion(server_correlation_id, &policy)
+        .await?;
+      let outcome = WaitOutcome::from(completion);
+      let code = if outcome.status.is_success() {
+        ExitCode::SUCCESS
+      } else {
+        ExitCode::from(2)
+      };
+      return Ok((render(&outcome, json), code));
+    }
+  };
+  Ok((output, ExitCode::SUCCESS))
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+  match run(Cli::parse()).await {
+    Ok((output, code)) => {
+      print!("{}", output);
+      code
+    }
+    Err(err) => {
+      eprintln!("error: {}", err);
+      ExitCode::FAILURE
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::config::Config;
+  use crate::{run, Cli, Settings};
+  use clap::Parser;
+  use mvola::testing::Simulator;
+  use mvola::{Environment, PaymentStatus};
+  use std::time::Duration;
+
+  fn cli(simulator: &Simulator, args: &[&str]) -> Cli {
+    let mut argv = vec![
+      "mvola",
+      "--base-url",
+      simulator.base_url(),
+      "--consumer-key",
+      "consumer_key",
+      "--consumer-secret",
+      "consumer_secret",
+      "--merchant",
+      "0343500004",
+    ];
+    argv.extend_from_slice(args);
+    Cli::try_parse_from(argv).unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_pay_and_wait() {
+    let simulator = Simulator::builder()
+      .confirmation_delay(Duration::ZERO)
+      .start()
+      .await
+      .unwrap();
+
+    let args = [
+      "--json",
+      "pay",
+      "--customer",
+      "0343500003",
+      "--amount",
+      "1000",
+      "--description",
+      "test",
+    ];
+    let (output, _) = run(cli(&simulator, &args)).await.unwrap();
+    let response: serde_json::Value = serde_json::from_str(&output).unwrap();
+    let id = response["serverCorrelationId"]
+      .as_str()
+      .unwrap()
+      .to_string();
+    assert_eq!(response["status"], "pending");
+
+    simulator.advance(Duration::from_secs(1)).await;
+    assert_eq!(
+      simulator.payment_status(&id),
+      Some(PaymentStatus::Completed)
+    );
+    let (output, _) = run(cli(&simulator, &["status", &id])).await.unwrap();
+    assert!(output.starts_with("Status"), "{}", output);
+    assert!(output.contains("completed"), "{}", output);
+
+    let args = ["wait", &id, "--interval", "1", "--timeout", "5"];
+    let (output, _) = run(cli(&simulator, &args)).await.unwrap();
+    assert!(output.contains("1000 Ar"), "{}", output);
+  }
+
+  #[tokio::test]
+  async fn test_invalid_arguments() {
+    let cli =
+      Cli::try_parse_from(["mvola", "--config", "/nonexistent/mvola.toml", "token"]).unwrap();
+    let err = run(cli).await.unwrap_err();
+    assert!(
+      err.to_string().contains("/nonexistent/mvola.toml"),
+      "{}",
+      err
+    );
+
+    let cli = Cli::try_parse_from([
+      "mvola",
+      "--production",
+      "--base-url",
+      "http://localhost",
+      "token",
+    ]);
+    assert!(cli.is_err());
+  }
+
+  #[test]
+  fn test_flags_win_over_config() {
+    let config = || Config {
+      base_url: Some(String::from("http://localhost:8080")),
+      ..Config::default()
+    };
+    let settings = Settings {
+      cli: Cli::try_parse_from(["mvola", "--production", "token"]).unwrap(),
+      config: config(),
+    };
+    assert_eq!(settings.base_url(), Environment::Production.base_url());
+
+    let settings = Settings {
+      cli: Cli::try_parse_from(["mvola", "token"]).unwrap(),
+      config: config(),
+    };
+    assert_eq!(settings.base_url(), "http://localhost:8080");
+  }
+}
@@ -0,0 +1,180 @@
+use mvola::types::{
+  AuthResponse, KeyValue, TransactionDetails, TransactionResponse, TransactionStatus,
+};
+use mvola::{Completion, PaymentStatus};
+use serde::Serialize;
+use std::fmt::{self, Display};
+
+/// A two-column table, one field per row.
+#[derive(Default)]
+pub(crate) struct Table {
+  rows: Vec<(&'static str, String)>,
+}
+
+impl Table {
+  pub(crate) fn row<V: Display>(mut self, name: &'static str, value: V) -> Self {
+    self.rows.push((name, value.to_string()));
+    self
+  }
+}
+
+impl Display for Table {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let width = self
+      .rows
+      .iter()
+      .map(|(name, _)| name.len())
+      .max()
+      .unwrap_or(0);
+    for (name, value) in &self.rows {
+      writeln!(f, "{:width$}  {}", name, value, width = width)?;
+    }
+    Ok(())
+  }
+}
+
+/// A value printed as a table, or as JSON with `--json`.
+pub(crate) trait Render: Serialize {
+  fn table(&self) -> Table;
+}
+
+impl Render for AuthResponse {
+  fn table(&self) -> Table {
+    Table::default()
+      .row("Access token", &self.access_token)
+      .row("Token type", &self.token_type)
+      .row("Expires in", format!("{}s", self.expires_in))
+      .row("Scope", &self.scope)
+  }
+}
+
+impl Render for TransactionResponse {
+  fn table(&self) -> Table {
+    Table::default()
+      .row("Status", &self.status)
+      .row("Server correlation id", &self.server_correlation_id)
+      .row("Notification method", &self.notification_method)
+  }
+}
+
+impl Render for TransactionStatus {
+  fn table(&self) -> Table {
+    Table::default()
+      .row("Status", &self.status)
+      .row("Server correlation id", &self.server_correlation_id)
+      .row("Notification method", &self.notification_method)
+      .row("Transaction id", or_dash(&self.object_reference))
+  }
+}
+
+impl Render for TransactionDetails {
+  fn table(&self) -> Table {
+    Table::default()
+      .row("Transaction id", &self.transaction_reference)
+      .row("Status", &self.transaction_status)
+      .row("Amount", format!("{} {}", self.amount, self.currency))
+      .row("Fees", format!("{} {}", self.total_fees(), self.currency))
+      .row("Debit", key_values(&self.debit_party.to_key_values()))
+      .row("Credit", key_values(&self.credit_party.to_key_values()))
+      .row("Request date", &self.request_date)
+      .row("Creation date", &self.creation_date)
+      .row("Metadata", key_values(&self.metadata.to_key_values()))
+  }
+}
+
+/// The outcome of `mvola wait`.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct WaitOutcome {
+  pub status: PaymentStatus,
+  pub server_correlation_id: String,
+  pub object_reference: String,
+  pub details: Option<TransactionDetails>,
+}
+
+impl From<Completion> for WaitOutcome {
+  fn from(completion: Completion) -> Self {
+    Self {
+      status: completion.status,
+      server_correlation_id: completion.server_correlation_id,
+      object_reference: completion.object_reference,
+      details: completion.details,
+    }
+  }
+}
+
+impl Render for WaitOutcome {
+  fn table(&self) -> Table {
+    let table = Table::default()
+      .row("Status", &self.status)
+      .row("Server correlation id", &self.server_correlation_id)
+      .row("Transaction id", or_dash(&self.object_reference));
+    match &self.details {
+      Some(details) => table
+        .row("Amount", format!("{} {}", details.amount, details.currency))
+        .row(
+          "Fees",
+          format!("{} {}", details.total_fees(), details.currency),
+        )
+        .row("Debit", key_values(&details.debit_party.to_key_values()))
+        .row("Credit", key_values(&details.credit_party.to_key_values())),
+      None => table,
+    }
+  }
+}
+
+/// Render a value as a table, or as pretty-printed JSON.
+pub(crate) fn render<T: Render>(value: &T, json: bool) -> String {
+  if json {
+    let mut json = serde_json::to_string_pretty(value).unwrap_or_default();
+    json.push('\n');
+    json
+  } else {
+    value.table().to_string()
+  }
+}
+
+fn key_values(key_values: &[KeyValue]) -> String {
+  let key_values: Vec<String> = key_values
+    .iter()
+    .map(|kv| format!("{}={}", kv.key, kv.value))
+    .collect();
+  or_dash(&key_values.join(", ")).to_string()
+}
+
+fn or_dash(value: &str) -> &str {
+  if value.is_empty() {
+    "-"
+  } else {
+    value
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::output::{render, Table};
+  use mvola::types::TransactionStatus;
+  use mvola::{NotificationMethod, PaymentStatus};
+
+  #[test]
+  fn test_render() {
+    let table = Table::default()
+      .row("Status", "pending")
+      .row("Transaction id", "-");
+    assert_eq!(
+      table.to_string(),
+      "Status          pending\nTransaction id  -\n"
+    );
+
+    let status = TransactionStatus {
+      status: PaymentStatus::Completed,
+      server_correlation_id: String::from("421a22a2-ef1d-42bc-9452-f4939a3d5cdf"),
+      notification_method: NotificationMethod::Polling,
+      object_reference: String::from("636042511"),
+    };
+    assert!(render(&status, false).contains("Transaction id         636042511"));
+    let json: serde_json::Value = serde_json::from_str(&render(&status, true)).unwrap();
+    assert_eq!(json["status"], "completed");
+    assert_eq!(json["objectReference"], "636042511");
+  }
+}
@@ -0,0 +1,24 @@
+use mvola::blocking::MVola;
+use mvola::SANDBOX_URL;
+use std::env;
+use std::time::Duration;
+
+fn main() {
+  let client = MVola::builder()
+    .base_url(SANDBOX_URL)
+    .credentials(
+      &env::var("CONSUMER_KEY").unwrap(),
+      &env::var("CONSUMER_SECRET").unwrap(),
+    )
+    .user_language("FR")
+    .user_account_identifier("msisdn;0343500003")
+    .partner_name("TestMVola")
+    .build_blocking()
+    .unwrap();
+  let response = client.transaction.wait_for_status(
+    "2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+    Duration::from_secs(2),
+    Duration::from_secs(60),
+  );
+  println!("{:#?}", response);
+}
@@ -3,7 +3,7 @@ use std::env;
 
 #[tokio::main]
 async fn main() {
-  let client = MVola::new(SANDBOX_URL);
+  let client = MVola::new(SANDBOX_URL).unwrap();
   let response = client
     .auth
     .generate_token(
@@ -1,25 +1,35 @@
+use crate::error::Result;
+use crate::http::{default_transport, recv_json, HttpRequest, HttpTransport, TransportConfig};
+use crate::routes::Route;
 use crate::types::{AuthRequest, AuthResponse};
-use std::str::FromStr;
-use std::time::Duration;
-use surf::http::auth::BasicAuth;
-use surf::http::{Method, Mime};
-use surf::{Client, Config, Url};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use std::sync::Arc;
+use url::Url;
 
+#[derive(Clone)]
 pub struct AuthService {
-  client: Client,
+  transport: Arc<dyn HttpTransport>,
   base_url: String,
 }
 
 impl AuthService {
-  pub fn new(base_url: &str) -> Self {
-    let client = Config::new()
-      .set_timeout(Some(Duration::from_secs(5)))
-      .try_into()
-      .unwrap();
-    Self {
-      client,
-      base_url: String::from(base_url),
-    }
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  /// * `MVolaError::MissingConfiguration` - If no transport feature is enabled
+  pub fn new(base_url: &str) -> Result<Self> {
+    Self::with_transport(base_url, default_transport(&TransportConfig::default())?)
+  }
+
+  /// Send the requests with the given transport.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub fn with_transport(base_url: &str, transport: Arc<dyn HttpTransport>) -> Result<Self> {
+    Url::parse(base_url)?;
+    Ok(Self {
+      transport,
+      base_url: String::from(base_url.trim_end_matches('/')),
+    })
   }
 
   /// Generate a token for the given consumer key and consumer secret.
@@ -34,12 +44,13 @@ impl AuthService {
   /// # Returns
   /// * `AuthResponse` - The object containing the access token
   /// # Errors
-  /// * `surf::Error` - If the request fails
+  /// * `MVolaError::Authentication` - If the consumer key or secret is rejected
+  /// * `MVolaError` - If the request fails
   /// # Example
   /// ```no_run
   ///#[tokio::main]
   /// async fn main() {
-  /// let client = MVola::new(SANDBOX_URL);
+  /// let client = MVola::new(SANDBOX_URL).unwrap();
   /// let response = client
   ///  .auth
   /// .generate_token(
@@ -55,29 +66,34 @@ impl AuthService {
     &self,
     consumer_key: &str,
     consumer_secret: &str,
-  ) -> Result<AuthResponse, surf::Error> {
-    let params = AuthRequest {
-      grant_type: "client_credentials".to_string(),
-      scope: "EXT_INT_MVOLA_SCOPE".to_string(),
-    };
-    let path = format!("{}/token", &self.base_url);
-    let url = Url::parse(&path).unwrap();
-    let mut req = surf::Request::new(Method::Post, url.clone());
-    req.set_header(
-      "Authorization",
-      BasicAuth::new(consumer_key, consumer_secret).value(),
-    );
-    req.set_header("Accept", "application/json");
-    req.set_content_type(Mime::from_str("application/x-www-form-urlencoded").unwrap());
-    req.body_form(&params);
-    let res: AuthResponse = self.client.recv_json(req).await?;
-    Ok(res)
+  ) -> Result<AuthResponse> {
+    let req = token_request(&self.base_url, consumer_key, consumer_secret)?;
+    recv_json(self.transport.as_ref(), req).await
   }
 }
 
+pub(crate) fn token_request(
+  base_url: &str,
+  consumer_key: &str,
+  consumer_secret: &str,
+) -> Result<HttpRequest> {
+  let params = AuthRequest {
+    grant_type: "client_credentials".to_string(),
+    scope: "EXT_INT_MVOLA_SCOPE".to_string(),
+  };
+  let route = Route::Token;
+  let mut req = HttpRequest::new(route.method(), route.url(base_url)?);
+  let credentials = STANDARD.encode(format!("{}:{}", consumer_key, consumer_secret));
+  req.set_header("Authorization", &format!("Basic {}", credentials));
+  req.set_header("Accept", "application/json");
+  req.set_form(&params)?;
+  Ok(req)
+}
+
 #[cfg(test)]
 mod tests {
   use crate::auth::AuthService;
+  use crate::error::MVolaError;
   use mockito::{mock, SERVER_URL};
 
   #[tokio::test]
@@ -95,7 +111,7 @@ mod tests {
       )
       .create();
 
-    let client = AuthService::new(SERVER_URL);
+    let client = AuthService::new(SERVER_URL).unwrap();
     let response = client
       .generate_token("consumer_key", "consumer_secret")
       .await
@@ -106,4 +122,43 @@ mod tests {
     assert_eq!(response.token_type, "Bearer");
     assert_eq!(response.scope, "EXT_INT_MVOLA_SCOPE");
   }
+
+  #[tokio::test]
+  async fn test_generate_token_invalid_credentials() {
+    let _m = mock("POST", "/invalid/token")
+      .with_status(401)
+      .with_header("Content-Type", "application/json")
+      .with_body(
+        r#"{
+            "error_description": "A valid OAuth client could not be found for client_id: consumer_key",
+            "error": "invalid_client"
+        }"#,
+      )
+      .create();
+
+    let client = AuthService::new(&format!("{}/invalid", SERVER_URL)).unwrap();
+    let err = client
+      .generate_token("consumer_key", "consumer_secret")
+      .await
+      .unwrap_err();
+
+    match err {
+      MVolaError::Authentication { status, message } => {
+        assert_eq!(status, 401);
+        assert_eq!(
+          message,
+          "A valid OAuth client could not be found for client_id: consumer_key"
+        );
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[test]
+  fn test_invalid_base_url() {
+    assert!(matches!(
+      AuthService::new("devapi.mvola.mg"),
+      Err(MVolaError::InvalidUrl(_))
+    ));
+  }
 }
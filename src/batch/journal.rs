@@ -0,0 +1,144 @@
+//! The journal of a batch, one JSON line per change of state of a payout.
+//!
+//! ```text
+//! {"reference":"agent-42","state":"sending"}
+//! {"reference":"agent-42","state":"sent","server_correlation_id":"2ba1d66a-..."}
+//! {"reference":"agent-42","state":"completed","server_correlation_id":"2ba1d66a-...","fees":"0"}
+//! ```
+//!
+//! Each line is synced to disk before the batch goes on, so that a payout is
+//! always journaled as `sending` before its request leaves the process. The
+//! file is locked while the batch runs, so that two batches cannot pay from
+//! the same journal.
+
+use crate::batch::BatchError;
+use crate::money::Money;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// The last known state of a payout.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub(crate) enum PayoutState {
+  /// The request is about to be sent, its outcome is unknown until `Sent`.
+  Sending,
+  /// MVola accepted the request.
+  Sent {
+    server_correlation_id: String,
+  },
+  /// MVola rejected the request, nothing was paid.
+  Rejected {
+    error: String,
+  },
+  Completed {
+    server_correlation_id: String,
+    fees: Option<Money>,
+  },
+  Failed {
+    server_correlation_id: String,
+  },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+  reference: String,
+  #[serde(flatten)]
+  state: PayoutState,
+}
+
+/// The states of the payouts, backed by an append-only file or kept in memory.
+pub(crate) struct Journal {
+  file: Option<Arc<Mutex<File>>>,
+  states: Mutex<HashMap<String, PayoutState>>,
+}
+
+impl Journal {
+  pub(crate) fn in_memory() -> Self {
+    Self {
+      file: None,
+      states: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Open or create the journal file, lock it and load the states it records.
+  ///
+  /// A last line without its newline was cut by a crash while it was written:
+  /// the request it announced was not sent, so the line is dropped.
+  pub(crate) async fn open(path: &Path) -> Result<Self, BatchError> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || Self::load(path))
+      .await
+      .map_err(io::Error::other)?
+  }
+
+  fn load(path: PathBuf) -> Result<Self, BatchError> {
+    let mut file = OpenOptions::new()
+      .read(true)
+      .create(true)
+      .append(true)
+      .open(&path)?;
+    match file.try_lock() {
+      Ok(()) => {}
+      Err(TryLockError::WouldBlock) => return Err(BatchError::JournalLocked(path)),
+      Err(TryLockError::Error(e)) => return Err(e.into()),
+    }
+    let mut content = String::new();
+    file.read_to_string(&mut content)?;
+
+    let complete = content.rfind('\n').map_or(0, |end| end + 1);
+    if complete < content.len() {
+      file.set_len(complete as u64)?;
+      file.seek(SeekFrom::End(0))?;
+    }
+
+    let mut states = HashMap::new();
+    for (index, line) in content[..complete].lines().enumerate() {
+      if line.trim().is_empty() {
+        continue;
+      }
+      let entry: Entry = serde_json::from_str(line).map_err(|source| BatchError::Journal {
+        line: index + 1,
+        source,
+      })?;
+      states.insert(entry.reference, entry.state);
+    }
+    Ok(Self {
+      file: Some(Arc::new(Mutex::new(file))),
+      states: Mutex::new(states),
+    })
+  }
+
+  pub(crate) fn state(&self, reference: &str) -> Option<PayoutState> {
+    let states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+    states.get(reference).cloned()
+  }
+
+  /// Record the new state of a payout, and sync it to disk before returning.
+  ///
+  /// The file is written on the blocking thread pool, not on the runtime.
+  pub(crate) async fn record(&self, reference: &str, state: PayoutState) -> Result<(), BatchError> {
+    if let Some(file) = &self.file {
+      let entry = Entry {
+        reference: String::from(reference),
+        state: state.clone(),
+      };
+      let mut line = serde_json::to_string(&entry)?;
+      line.push('\n');
+      let file = file.clone();
+      tokio::task::spawn_blocking(move || {
+        let mut file = file.lock().unwrap_or_else(PoisonError::into_inner);
+        file.write_all(line.as_bytes())?;
+        file.sync_data()
+      })
+      .await
+      .map_err(io::Error::other)??;
+    }
+    let mut states = self.states.lock().unwrap_or_else(PoisonError::into_inner);
+    states.insert(String::from(reference), state);
+    Ok(())
+  }
+}
@@ -0,0 +1,727 @@
+//! Bulk payouts.
+//!
+//! A `Batch` pays a list of payouts from the merchant account as disbursement
+//! transactions, with at most `concurrency` payouts in flight and a minimum
+//! interval between two payment requests, polls each payout to a final status
+//! and returns a `BatchReport`:
+//!
+//! ```no_run
+//! let payouts = Payout::from_csv_file("payouts.csv")?;
+//! let report = Batch::new(client.transaction.clone(), merchant)
+//!   .concurrency(8)
+//!   .rate_limit(5, Duration::from_secs(1))
+//!   .journal("payouts.journal")
+//!   .run(payouts)
+//!   .await?;
+//! report.write_csv_file("report.csv")?;
+//! ```
+//!
+//! The journal records each payout before its request is sent and each time
+//! its state changes. Running the batch again with the same journal skips the
+//! settled payouts, polls the sent ones and sends the others, so that a batch
+//! stopped by a crash is resumed without paying anyone twice. A payout whose
+//! request may have been executed without MVola answering, e.g. on a timeout
+//! or a crash, is reported as `Unknown` and is never sent again: check it on
+//! the MVola portal.
+
+mod journal;
+
+use crate::error::MVolaError;
+use crate::money::Money;
+use crate::msisdn::Msisdn;
+use crate::poll::PollPolicy;
+use crate::refund::is_rejected;
+use crate::routes::TransactionType;
+use crate::transaction::TransactionService;
+use crate::types::{DisbursementRequest, TransactionRequest};
+use crate::RequestContext;
+use futures::stream::{self, StreamExt, TryStreamExt};
+use journal::{Journal, PayoutState};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::time::Instant;
+
+/// Errors that stop a batch.
+///
+/// The failures of single payouts do not stop the batch, they are reported
+/// in the `BatchReport`.
+#[derive(Debug, thiserror::Error)]
+pub enum BatchError {
+  #[error("cannot read or write the batch files: {0}")]
+  Io(#[from] io::Error),
+  #[error("invalid CSV: {0}")]
+  Csv(#[from] csv::Error),
+  #[error("invalid journal entry: {0}")]
+  Json(#[from] serde_json::Error),
+  /// A line of the journal cannot be parsed.
+  #[error("invalid journal line {line}: {source}")]
+  Journal {
+    line: usize,
+    source: serde_json::Error,
+  },
+  /// Two payouts have the same reference.
+  #[error("duplicate payout reference: {0}")]
+  DuplicateReference(String),
+  /// Another batch is running with the same journal.
+  #[error("journal {0} is locked by another batch")]
+  JournalLocked(PathBuf),
+}
+
+/// A payment from the merchant account to a customer.
+///
+/// The CSV files have the header `reference,msisdn,amount,description`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Payout {
+  /// The reference of the payout, unique in the batch and kept across runs.
+  /// It is sent as `requestingOrganisationTransactionReference`.
+  pub reference: String,
+  pub msisdn: Msisdn,
+  #[serde(deserialize_with = "amount")]
+  pub amount: Money,
+  pub description: String,
+}
+
+impl Payout {
+  pub fn new(reference: &str, msisdn: Msisdn, amount: Money, description: &str) -> Self {
+    Self {
+      reference: String::from(reference),
+      msisdn,
+      amount,
+      description: String::from(description),
+    }
+  }
+
+  /// Read the payouts of a CSV file.
+  pub fn from_csv_file<P: AsRef<Path>>(path: P) -> Result<Vec<Self>, BatchError> {
+    Self::from_csv_reader(std::fs::File::open(path)?)
+  }
+
+  /// Read the payouts of CSV data.
+  pub fn from_csv_reader<R: io::Read>(reader: R) -> Result<Vec<Self>, BatchError> {
+    let mut reader = csv::ReaderBuilder::new()
+      .trim(csv::Trim::All)
+      .from_reader(reader);
+    let payouts = reader.deserialize().collect::<Result<_, _>>()?;
+    Ok(payouts)
+  }
+
+  fn request(&self, merchant: &Msisdn) -> crate::error::Result<TransactionRequest> {
+    TransactionRequest::builder()
+      .amount(self.amount)
+      .debit(merchant.clone())
+      .credit(self.msisdn.clone())
+      .description(&self.description)
+      .reference(&self.reference)
+      .build()
+  }
+}
+
+/// Parse the amounts as strings: CSV fields such as "1000.50" would
+/// otherwise be read as floats.
+fn amount<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Money, D::Error> {
+  let amount = String::deserialize(deserializer)?;
+  amount.parse().map_err(serde::de::Error::custom)
+}
+
+/// The outcome of a payout.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayoutStatus {
+  /// The customer was paid.
+  Completed,
+  /// MVola could not execute the payout, e.g. because of the balance.
+  Failed,
+  /// MVola rejected the request and nothing was paid. The payout is sent
+  /// again by the next run.
+  Rejected,
+  /// The payout was not final before the poll deadline. The next run polls
+  /// it again.
+  Pending,
+  /// The request may have been executed. It is never sent again.
+  Unknown,
+}
+
+/// A line of the `BatchReport`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PayoutResult {
+  pub reference: String,
+  /// The id of the payment, `None` if MVola did not accept the request.
+  pub server_correlation_id: Option<String>,
+  pub status: PayoutStatus,
+  /// The fees of a completed payout.
+  pub fees: Option<Money>,
+  pub error: Option<String>,
+}
+
+impl PayoutResult {
+  fn new(reference: &str, server_correlation_id: Option<&str>, status: PayoutStatus) -> Self {
+    Self {
+      reference: String::from(reference),
+      server_correlation_id: server_correlation_id.map(String::from),
+      status,
+      fees: None,
+      error: None,
+    }
+  }
+
+  fn error(mut self, error: impl ToString) -> Self {
+    self.error = Some(error.to_string());
+    self
+  }
+}
+
+/// The results of a batch, in the order of the payouts.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BatchReport {
+  pub results: Vec<PayoutResult>,
+}
+
+impl BatchReport {
+  /// The number of payouts with the given status.
+  pub fn count(&self, status: PayoutStatus) -> usize {
+    self
+      .results
+      .iter()
+      .filter(|result| result.status == status)
+      .count()
+  }
+
+  /// The sum of the fees of the completed payouts.
+  pub fn total_fees(&self) -> Money {
+    self.results.iter().filter_map(|result| result.fees).sum()
+  }
+
+  /// Write the report as CSV, with the header
+  /// `reference,server_correlation_id,status,fees,error`.
+  pub fn write_csv<W: io::Write>(&self, writer: W) -> Result<(), BatchError> {
+    let mut writer = csv::Writer::from_writer(writer);
+    for result in &self.results {
+      writer.serialize(result)?;
+    }
+    writer.flush()?;
+    Ok(())
+  }
+
+  pub fn write_csv_file<P: AsRef<Path>>(&self, path: P) -> Result<(), BatchError> {
+    self.write_csv(std::fs::File::create(path)?)
+  }
+}
+
+/// Pays a list of payouts, see the module documentation.
+#[derive(Clone)]
+pub struct Batch {
+  service: TransactionService,
+  merchant: Msisdn,
+  concurrency: usize,
+  interval: Duration,
+  policy: PollPolicy,
+  journal: Option<PathBuf>,
+}
+
+impl Batch {
+  /// A batch paying from `merchant`, one payout at a time, without rate
+  /// limit nor journal.
+  /// # Arguments
+  /// * `service` - The transaction service, configured for the merchant account
+  /// * `merchant` - The debit party of the payouts
+  pub fn new(service: TransactionService, merchant: Msisdn) -> Self {
+    Self {
+      service,
+      merchant,
+      concurrency: 1,
+      interval: Duration::ZERO,
+      policy: PollPolicy::default(),
+      journal: None,
+    }
+  }
+
+  /// The maximum number of payouts sent or polled at the same time.
+  pub fn concurrency(mut self, concurrency: usize) -> Self {
+    self.concurrency = concurrency.max(1);
+    self
+  }
+
+  /// Send at most `requests` payment requests per `period`.
+  ///
+  /// Only the payment requests are limited, the status requests follow the
+  /// poll policy.
+  pub fn rate_limit(mut self, requests: u32, period: Duration) -> Self {
+    self.interval = period / requests.max(1);
+    self
+  }
+
+  /// How to poll each payout until it is final.
+  pub fn poll_policy(mut self, policy: PollPolicy) -> Self {
+    self.policy = policy;
+    self
+  }
+
+  /// The journal file that makes the batch resumable, created if missing.
+  pub fn journal<P: AsRef<Path>>(mut self, path: P) -> Self {
+    self.journal = Some(path.as_ref().to_path_buf());
+    self
+  }
+
+  /// Pay the payouts and poll them to a final status.
+  /// # Arguments
+  /// * `payouts` - The payouts, with unique references
+  /// # Returns
+  /// * `BatchReport` - The result of each payout, in the order of `payouts`
+  /// # Errors
+  /// * `BatchError::DuplicateReference` - If two payouts have the same reference
+  /// * `BatchError::Journal` - If the journal cannot be parsed
+  /// * `BatchError::JournalLocked` - If another batch is running with the same journal
+  /// * `BatchError::Io` - If the journal cannot be written, in which case no
+  ///   more payouts are sent
+  pub async fn run(&self, payouts: Vec<Payout>) -> Result<BatchReport, BatchError> {
+    let mut references = HashSet::new();
+    if let Some(payout) = payouts
+      .iter()
+      .find(|payout| !references.insert(payout.reference.as_str()))
+    {
+      return Err(BatchError::DuplicateReference(payout.reference.clone()));
+    }
+
+    let journal = match &self.journal {
+      Some(path) => Journal::open(path).await?,
+      None => Journal::in_memory(),
+    };
+    let limiter = RateLimiter::new(self.interval);
+    let mut results: Vec<(usize, PayoutResult)> = stream::iter(payouts.iter().enumerate())
+      .map(|(index, payout)| {
+        let journal = &journal;
+        let limiter = &limiter;
+        async move { Ok::<_, BatchError>((index, self.pay(payout, journal, limiter).await?)) }
+      })
+      .buffer_unordered(self.concurrency)
+      .try_collect()
+      .await?;
+    results.sort_by_key(|(index, _)| *index);
+    Ok(BatchReport {
+      results: results.into_iter().map(|(_, result)| result).collect(),
+    })
+  }
+
+  async fn pay(
+    &self,
+    payout: &Payout,
+    journal: &Journal,
+    limiter: &RateLimiter,
+  ) -> Result<PayoutResult, BatchError> {
+    let reference = payout.reference.as_str();
+    let server_correlation_id = match journal.state(reference) {
+      Some(PayoutState::Completed {
+        server_correlation_id,
+        fees,
+      }) => {
+        let mut result = PayoutResult::new(
+          reference,
+          Some(&server_correlation_id),
+          PayoutStatus::Completed,
+        );
+        result.fees = fees;
+        return Ok(result);
+      }
+      Some(PayoutState::Failed {
+        server_correlation_id,
+      }) => {
+        return Ok(PayoutResult::new(
+          reference,
+          Some(&server_correlation_id),
+          PayoutStatus::Failed,
+        ))
+      }
+      Some(PayoutState::Sending) => {
+        return Ok(
+          PayoutResult::new(reference, None, PayoutStatus::Unknown)
+            .error("the request was sent but not answered before the batch stopped"),
+        )
+      }
+      Some(PayoutState::Sent {
+        server_correlation_id,
+      }) => server_correlation_id,
+      Some(PayoutState::Rejected { .. }) | None => {
+        let tx = match payout.request(&self.merchant) {
+          Ok(tx) => tx,
+          Err(e) => return self.reject(payout, journal, e).await,
+        };
+        limiter.wait().await;
+        journal.record(reference, PayoutState::Sending).await?;
+        match self.service.send_transaction(DisbursementRequest(tx)).await {
+          Ok(response) => {
+            journal
+              .record(
+                reference,
+                PayoutState::Sent {
+                  server_correlation_id: response.server_correlation_id.clone(),
+                },
+              )
+              .await?;
+            response.server_correlation_id
+          }
+          Err(e) if is_rejected(&e) => return self.reject(payout, journal, e).await,
+          Err(e) => return Ok(PayoutResult::new(reference, None, PayoutStatus::Unknown).error(e)),
+        }
+      }
+    };
+
+    let completion = match self
+      .service
+      .with_context(RequestContext::new().transaction_type(TransactionType::Disbursement))
+      .wait_for_completion(&server_correlation_id, &self.policy)
+      .await
+    {
+      Ok(completion) => completion,
+      Err(e) => {
+        return Ok(
+          PayoutResult::new(
+            reference,
+            Some(&server_correlation_id),
+            PayoutStatus::Pending,
+          )
+          .error(e),
+        )
+      }
+    };
+    if completion.status.is_success() {
+      let fees = completion
+        .details
+        .as_ref()
+        .map(|details| details.total_fees());
+      journal
+        .record(
+          reference,
+          PayoutState::Completed {
+            server_correlation_id: server_correlation_id.clone(),
+            fees,
+          },
+        )
+        .await?;
+      let mut result = PayoutResult::new(
+        reference,
+        Some(&server_correlation_id),
+        PayoutStatus::Completed,
+      );
+      result.fees = fees;
+      Ok(result)
+    } else {
+      journal
+        .record(
+          reference,
+          PayoutState::Failed {
+            server_correlation_id: server_correlation_id.clone(),
+          },
+        )
+        .await?;
+      Ok(PayoutResult::new(
+        reference,
+        Some(&server_correlation_id),
+        PayoutStatus::Failed,
+      ))
+    }
+  }
+
+  async fn reject(
+    &self,
+    payout: &Payout,
+    journal: &Journal,
+    error: MVolaError,
+  ) -> Result<PayoutResult, BatchError> {
+    let error = error.to_string();
+    journal
+      .record(
+        &payout.reference,
+        PayoutState::Rejected {
+          error: error.clone(),
+        },
+      )
+      .await?;
+    Ok(PayoutResult::new(&payout.reference, None, PayoutStatus::Rejected).error(error))
+  }
+}
+
+/// Spaces the payment requests by a minimum interval.
+struct RateLimiter {
+  interval: Duration,
+  next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+  fn new(interval: Duration) -> Self {
+    Self {
+      interval,
+      next: Mutex::new(Instant::now()),
+    }
+  }
+
+  /// Wait for the next free slot. The lock is held while waiting, so that
+  /// the requests take the slots in turn.
+  async fn wait(&self) {
+    if self.interval.is_zero() {
+      return;
+    }
+    let mut next = self.next.lock().await;
+    tokio::time::sleep_until(*next).await;
+    *next = Instant::now() + self.interval;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::batch::journal::Journal;
+  use crate::batch::{Batch, BatchError, BatchReport, Payout, PayoutResult, PayoutStatus};
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::transaction::TransactionService;
+  use mockito::SERVER_URL;
+
+  #[test]
+  fn test_read_payouts() {
+    let payouts = Payout::from_csv_file("tests/fixtures/payouts.csv").unwrap();
+    assert_eq!(
+      payouts,
+      vec![
+        Payout::new(
+          "agent-1",
+          Msisdn::new("0340000011").unwrap(),
+          Money::ariary(25000),
+          "Commission semaine 42"
+        ),
+        Payout::new(
+          "agent-2",
+          Msisdn::new("0340000012").unwrap(),
+          Money::ariary(30000),
+          "Commission semaine 42"
+        ),
+      ]
+    );
+
+    let invalid = "reference,msisdn,amount,description\nagent-1,0321234567,1000,test\n";
+    assert!(matches!(
+      Payout::from_csv_reader(invalid.as_bytes()),
+      Err(BatchError::Csv(_))
+    ));
+  }
+
+  #[test]
+  fn test_write_report() {
+    let report = BatchReport {
+      results: vec![
+        PayoutResult {
+          reference: String::from("agent-1"),
+          server_correlation_id: Some(String::from("2ba1d66a")),
+          status: PayoutStatus::Completed,
+          fees: Some(Money::ariary(100)),
+          error: None,
+        },
+        PayoutResult {
+          reference: String::from("agent-2"),
+          server_correlation_id: None,
+          status: PayoutStatus::Rejected,
+          fees: None,
+          error: Some(String::from("unknown account, 0340000012")),
+        },
+      ],
+    };
+    assert_eq!(report.count(PayoutStatus::Rejected), 1);
+    assert_eq!(report.total_fees(), Money::ariary(100));
+
+    let mut csv = vec![];
+    report.write_csv(&mut csv).unwrap();
+    assert_eq!(
+      String::from_utf8(csv).unwrap(),
+      "reference,server_correlation_id,status,fees,error\n\
+       agent-1,2ba1d66a,completed,100,\n\
+       agent-2,,rejected,,\"unknown account, 0340000012\"\n"
+    );
+  }
+
+  #[tokio::test]
+  async fn test_duplicate_reference() {
+    let merchant = Msisdn::new("0343500004").unwrap();
+    let batch = Batch::new(TransactionService::new(SERVER_URL).unwrap(), merchant);
+    let payout = Payout::new(
+      "agent-1",
+      Msisdn::new("0340000011").unwrap(),
+      Money::ariary(1000),
+      "test",
+    );
+    assert!(matches!(
+      batch.run(vec![payout.clone(), payout]).await,
+      Err(BatchError::DuplicateReference(reference)) if reference == "agent-1"
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_locked_journal() {
+    let path = std::env::temp_dir().join(format!("mvola-batch-{}.journal", uuid::Uuid::new_v4()));
+    let running = Journal::open(&path).await.unwrap();
+    let merchant = Msisdn::new("0343500004").unwrap();
+    let batch = Batch::new(TransactionService::new(SERVER_URL).unwrap(), merchant).journal(&path);
+    let payout = Payout::new(
+      "agent-1",
+      Msisdn::new("0340000011").unwrap(),
+      Money::ariary(1000),
+      "test",
+    );
+    assert!(matches!(
+      batch.run(vec![payout.clone()]).await,
+      Err(BatchError::JournalLocked(locked)) if locked == path
+    ));
+
+    drop(running);
+    Journal::open(&path).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+  }
+
+  #[cfg(feature = "testing")]
+  #[tokio::test]
+  async fn test_resume_without_paying_twice() {
+    use crate::poll::PollPolicy;
+    use crate::testing::Simulator;
+    use crate::types::{DisbursementRequest, TransactionRequest};
+    use std::time::Duration;
+
+    let agent = |number: &str| Msisdn::new(number).unwrap();
+    let merchant = agent("0343500004");
+    let simulator = Simulator::builder()
+      .account(agent("0340000011"), Money::ZERO)
+      .account(agent("0340000013"), Money::ZERO)
+      .account(agent("0340000014"), Money::ZERO)
+      .account(agent("0340000015"), Money::ZERO)
+      .account(agent("0340000016"), Money::ZERO)
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    // agent-6 was sent by a run that stopped while polling it.
+    let tx = TransactionRequest::builder()
+      .amount(Money::ariary(6000))
+      .debit(merchant.clone())
+      .credit(agent("0340000016"))
+      .description("Commission")
+      .reference("agent-6")
+      .build()
+      .unwrap();
+    let sent = client
+      .transaction
+      .send_transaction(DisbursementRequest(tx))
+      .await
+      .unwrap();
+    let journal =
+      std::env::temp_dir().join(format!("mvola-batch-{}.journal", uuid::Uuid::new_v4()));
+    std::fs::write(
+      &journal,
+      format!(
+        "{}\n{}\n{}\n{}",
+        r#"{"reference":"agent-3","state":"sending"}"#,
+        r#"{"reference":"agent-6","state":"sending"}"#,
+        format_args!(
+          r#"{{"reference":"agent-6","state":"sent","server_correlation_id":"{}"}}"#,
+          sent.server_correlation_id
+        ),
+        r#"{"reference":"agent-4","sta"#,
+      ),
+    )
+    .unwrap();
+
+    let payouts = vec![
+      Payout::new(
+        "agent-1",
+        agent("0340000011"),
+        Money::ariary(1000),
+        "Commission",
+      ),
+      // Not a simulator account
+      Payout::new(
+        "agent-2",
+        agent("0340000012"),
+        Money::ariary(2000),
+        "Commission",
+      ),
+      Payout::new(
+        "agent-3",
+        agent("0340000013"),
+        Money::ariary(3000),
+        "Commission",
+      ),
+      Payout::new(
+        "agent-4",
+        agent("0340000014"),
+        Money::ariary(4000),
+        "Commission",
+      ),
+      // Above the balance of the merchant
+      Payout::new(
+        "agent-5",
+        agent("0340000015"),
+        Money::ariary(5_000_000),
+        "Commission",
+      ),
+      Payout::new(
+        "agent-6",
+        agent("0340000016"),
+        Money::ariary(6000),
+        "Commission",
+      ),
+    ];
+    let batch = Batch::new(client.transaction.clone(), merchant)
+      .concurrency(3)
+      .rate_limit(100, Duration::from_secs(1))
+      .poll_policy(PollPolicy::fixed(
+        Duration::from_millis(20),
+        Duration::from_secs(10),
+      ))
+      .journal(&journal);
+    let run = || async {
+      let run = batch.run(payouts.clone());
+      tokio::pin!(run);
+      loop {
+        tokio::select! {
+          report = &mut run => return report.unwrap(),
+          _ = tokio::time::sleep(Duration::from_millis(20)) => {
+            simulator.advance(Duration::from_secs(5)).await
+          }
+        }
+      }
+    };
+
+    let report = run().await;
+    let statuses: Vec<PayoutStatus> = report.results.iter().map(|result| result.status).collect();
+    assert_eq!(
+      statuses,
+      vec![
+        PayoutStatus::Completed,
+        PayoutStatus::Rejected,
+        PayoutStatus::Unknown,
+        PayoutStatus::Completed,
+        PayoutStatus::Failed,
+        PayoutStatus::Completed,
+      ]
+    );
+    assert_eq!(
+      report.results[5].server_correlation_id,
+      Some(sent.server_correlation_id)
+    );
+    assert_eq!(report.results[0].fees, Some(Money::ZERO));
+    assert!(report.results[1].error.is_some());
+    assert_eq!(simulator.balance(&agent("0340000013")), Some(Money::ZERO));
+
+    let again = run().await;
+    assert_eq!(again.results[0], report.results[0]);
+    assert_eq!(again.count(PayoutStatus::Completed), 3);
+    for (number, paid) in [
+      ("0340000011", 1000),
+      ("0340000014", 4000),
+      ("0340000016", 6000),
+    ] {
+      assert_eq!(simulator.balance(&agent(number)), Some(Money::ariary(paid)));
+    }
+    std::fs::remove_file(&journal).unwrap();
+  }
+}
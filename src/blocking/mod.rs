@@ -0,0 +1,737 @@
+//! Blocking client, for programs that do not run an async runtime.
+//!
+//! It mirrors the async API and shares its types and errors:
+//!
+//! ```no_run
+//! let client = mvola::blocking::MVola::with_credentials(
+//!   SANDBOX_URL,
+//!   &env::var("CONSUMER_KEY").unwrap(),
+//!   &env::var("CONSUMER_SECRET").unwrap(),
+//! )
+//! .unwrap();
+//! let response = client.transaction.get_transaction("636042511");
+//! ```
+
+mod transport;
+
+pub use transport::{BlockingTransport, UreqTransport};
+
+use crate::auth::token_request;
+use crate::callback::CallbackSigner;
+use crate::context::RequestContext;
+use crate::error::{MVolaError, Result};
+use crate::http::{recv_json_blocking, TransportConfig};
+use crate::idempotency::{self, IdempotencyStore};
+use crate::money::Money;
+use crate::poll::{Completion, PollPolicy, Poller};
+use crate::refund::RefundLedger;
+use crate::routes::{TransactionApi, TransactionType};
+use crate::service::{bearer, Authorization, Call, Core, Prepared};
+use crate::token::TokenCache;
+use crate::types::{
+  AuthResponse, Options, Service, TransactionDetails, TransactionRequest, TransactionResponse,
+  TransactionStatus, TypedRequest,
+};
+use crate::MVolaBuilder;
+use serde::de::DeserializeOwned;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use url::Url;
+
+#[derive(Clone)]
+pub struct AuthService {
+  transport: Arc<dyn BlockingTransport>,
+  base_url: String,
+}
+
+impl AuthService {
+  /// Send the requests with the given transport.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub fn with_transport(base_url: &str, transport: Arc<dyn BlockingTransport>) -> Result<Self> {
+    Url::parse(base_url)?;
+    Ok(Self {
+      transport,
+      base_url: String::from(base_url.trim_end_matches('/')),
+    })
+  }
+
+  /// Generate a token for the given consumer key and consumer secret.
+  ///
+  /// See `mvola::auth::AuthService::generate_token`.
+  pub fn generate_token(&self, consumer_key: &str, consumer_secret: &str) -> Result<AuthResponse> {
+    let req = token_request(&self.base_url, consumer_key, consumer_secret)?;
+    recv_json_blocking(self.transport.as_ref(), req)
+  }
+}
+
+/// Fetches an access token lazily and keeps it until shortly before it expires.
+///
+/// See `mvola::token::TokenManager`.
+pub struct TokenManager {
+  auth: AuthService,
+  consumer_key: String,
+  consumer_secret: String,
+  token: Mutex<TokenCache>,
+}
+
+impl TokenManager {
+  pub fn new(auth: AuthService, consumer_key: &str, consumer_secret: &str) -> Self {
+    Self {
+      auth,
+      consumer_key: String::from(consumer_key),
+      consumer_secret: String::from(consumer_secret),
+      token: Mutex::new(TokenCache::default()),
+    }
+  }
+
+  /// Get a valid access token, generating a new one if the cached token is
+  /// missing or about to expire.
+  pub fn access_token(&self) -> Result<String> {
+    let mut token = self.token.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(access_token) = token.fresh() {
+      return Ok(access_token);
+    }
+
+    let requested_at = Instant::now();
+    let res = self
+      .auth
+      .generate_token(&self.consumer_key, &self.consumer_secret)?;
+    Ok(token.store(res, requested_at))
+  }
+
+  /// Drop the cached token if it is the one that was rejected.
+  pub fn invalidate(&self, rejected: &str) {
+    let mut token = self.token.lock().unwrap_or_else(|e| e.into_inner());
+    token.invalidate(rejected);
+  }
+}
+
+/// Sends the transaction requests.
+///
+/// See `mvola::transaction::TransactionService`.
+#[derive(Clone)]
+pub struct TransactionService {
+  inner: Arc<Core<dyn BlockingTransport, TokenManager>>,
+}
+
+impl Service for TransactionService {
+  fn set_authorization(&self, token: &str) {
+    self
+      .inner
+      .update(|settings| settings.authorization = Some(bearer(token)));
+  }
+
+  fn set_options(&self, options: Options) {
+    self.inner.update(|settings| settings.options = options);
+  }
+}
+
+impl TransactionService {
+  /// Send the requests with the given transport.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub fn with_transport(base_url: &str, transport: Arc<dyn BlockingTransport>) -> Result<Self> {
+    Ok(Self {
+      inner: Arc::new(Core::new(base_url, transport)?),
+    })
+  }
+
+  /// Use the given token manager to authorize the requests.
+  ///
+  /// A request rejected with 401 is retried once with a new token.
+  pub fn set_token_manager(&self, token_manager: Arc<TokenManager>) {
+    self
+      .inner
+      .update(|settings| settings.token_manager = Some(token_manager));
+  }
+
+  /// Sign the callback URL of each payment with the given signer.
+  ///
+  /// See `mvola::transaction::TransactionService::set_callback_signer`.
+  pub fn set_callback_signer(&self, callback_signer: Arc<CallbackSigner>) {
+    self
+      .inner
+      .update(|settings| settings.callback_signer = Some(callback_signer));
+  }
+
+  /// Record the payments in the given store.
+  ///
+  /// See `mvola::transaction::TransactionService::set_idempotency_store`.
+  pub fn set_idempotency_store(&self, idempotency_store: Arc<dyn IdempotencyStore>) {
+    self
+      .inner
+      .update(|settings| settings.idempotency_store = Some(idempotency_store));
+  }
+
+  /// The default transaction type and API version of the requests.
+  ///
+  /// See `mvola::transaction::TransactionService::set_transaction_api`.
+  pub fn set_transaction_api(&self, api: TransactionApi) {
+    self.inner.update(|settings| settings.api = api);
+  }
+
+  /// The refunds sent by the service and its clones.
+  pub fn refunds(&self) -> &RefundLedger {
+    &self.inner.refunds
+  }
+
+  /// A view of the service sending its requests with the given context.
+  ///
+  /// See `mvola::transaction::TransactionService::with_context`.
+  pub fn with_context(&self, context: RequestContext) -> WithContext<'_> {
+    WithContext {
+      service: self,
+      context,
+    }
+  }
+
+  /// Get the details of a transaction.
+  ///
+  /// See `mvola::transaction::TransactionService::get_transaction`.
+  pub fn get_transaction(&self, id: &str) -> Result<TransactionDetails> {
+    self.with_context(RequestContext::new()).get_transaction(id)
+  }
+
+  /// Get the status of a transaction.
+  ///
+  /// See `mvola::transaction::TransactionService::get_transaction_status`.
+  pub fn get_transaction_status(&self, server_correlation_id: &str) -> Result<TransactionStatus> {
+    self
+      .with_context(RequestContext::new())
+      .get_transaction_status(server_correlation_id)
+  }
+
+  /// Send a transaction.
+  ///
+  /// See `mvola::transaction::TransactionService::send_payment`.
+  pub fn send_payment(&self, tx: TransactionRequest) -> Result<TransactionResponse> {
+    self.with_context(RequestContext::new()).send_payment(tx)
+  }
+
+  /// Send a transaction to the endpoint of its type.
+  ///
+  /// See `mvola::transaction::TransactionService::send_transaction`.
+  pub fn send_transaction<T: TypedRequest>(&self, tx: T) -> Result<TransactionResponse> {
+    self
+      .with_context(RequestContext::new())
+      .send_transaction(tx)
+  }
+
+  /// Refund part or all of a completed transaction.
+  ///
+  /// See `mvola::transaction::TransactionService::refund`.
+  pub fn refund(
+    &self,
+    original: &TransactionDetails,
+    amount: Money,
+  ) -> Result<TransactionResponse> {
+    self
+      .with_context(RequestContext::new())
+      .refund(original, amount)
+  }
+
+  /// Poll the status of a transaction every `interval` until it is completed
+  /// or failed.
+  /// # Arguments
+  /// * `server_correlation_id` - The id returned by `send_payment`
+  /// * `interval` - The time between two status requests
+  /// * `timeout` - The maximum time to wait
+  /// # Errors
+  /// * `MVolaError::DeadlineExceeded` - If the transaction is still pending after `timeout`,
+  ///   with the last status received
+  pub fn wait_for_status(
+    &self,
+    server_correlation_id: &str,
+    interval: Duration,
+    timeout: Duration,
+  ) -> Result<TransactionStatus> {
+    self.with_context(RequestContext::new()).wait_for_status(
+      server_correlation_id,
+      interval,
+      timeout,
+    )
+  }
+
+  /// Poll the status of a transaction until it is completed or failed.
+  ///
+  /// See `mvola::transaction::TransactionService::wait_for_completion`.
+  pub fn wait_for_completion(
+    &self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> Result<Completion> {
+    self
+      .with_context(RequestContext::new())
+      .wait_for_completion(server_correlation_id, policy)
+  }
+
+  /// Send a call with the current authorization.
+  ///
+  /// See `mvola::transaction::TransactionService`.
+  fn execute<T: DeserializeOwned>(&self, call: &Prepared<'_, TokenManager>) -> Result<T> {
+    let transport = self.inner.transport.as_ref();
+    let base_url = &self.inner.base_url;
+    let token_manager = match call.authorization()? {
+      Authorization::Fixed(authorization) => {
+        return recv_json_blocking(transport, call.request(base_url, authorization)?)
+      }
+      Authorization::Managed(token_manager) => token_manager,
+    };
+
+    let token = token_manager.access_token()?;
+    match recv_json_blocking(transport, call.request(base_url, &bearer(&token))?) {
+      Err(MVolaError::Authentication { .. }) => {
+        token_manager.invalidate(&token);
+        let token = token_manager.access_token()?;
+        recv_json_blocking(transport, call.request(base_url, &bearer(&token))?)
+      }
+      res => res,
+    }
+  }
+}
+
+/// A blocking `TransactionService` sending its requests with a `RequestContext`.
+///
+/// See `TransactionService::with_context`.
+pub struct WithContext<'a> {
+  service: &'a TransactionService,
+  context: RequestContext,
+}
+
+impl WithContext<'_> {
+  /// Get the details of a transaction.
+  ///
+  /// See `TransactionService::get_transaction`.
+  pub fn get_transaction(&self, id: &str) -> Result<TransactionDetails> {
+    let settings = self.service.inner.settings();
+    let call = Prepared::new(&settings, &self.context, None, Call::Details(id));
+    self.service.execute(&call)
+  }
+
+  /// Get the status of a transaction.
+  ///
+  /// See `TransactionService::get_transaction_status`.
+  pub fn get_transaction_status(&self, server_correlation_id: &str) -> Result<TransactionStatus> {
+    let settings = self.service.inner.settings();
+    let call = Prepared::new(
+      &settings,
+      &self.context,
+      None,
+      Call::Status(server_correlation_id),
+    );
+    self.service.execute(&call)
+  }
+
+  /// Send a transaction.
+  ///
+  /// See `TransactionService::send_payment`.
+  pub fn send_payment(&self, tx: TransactionRequest) -> Result<TransactionResponse> {
+    self.send(None, &tx)
+  }
+
+  /// Send a transaction to the endpoint of its type.
+  ///
+  /// See `TransactionService::send_transaction`.
+  pub fn send_transaction<T: TypedRequest>(&self, tx: T) -> Result<TransactionResponse> {
+    self.send(Some(tx.transaction_type()), tx.request())
+  }
+
+  /// Refund part or all of a completed transaction.
+  ///
+  /// See `TransactionService::refund`.
+  pub fn refund(
+    &self,
+    original: &TransactionDetails,
+    amount: Money,
+  ) -> Result<TransactionResponse> {
+    let refunds = &self.service.inner.refunds;
+    let tx = refunds.begin_refund(original, amount)?;
+    let res = self.send(None, &tx);
+    refunds.finish_refund(original, amount, &res);
+    res
+  }
+
+  /// Send a transaction, unless its idempotency key was already sent.
+  fn send(
+    &self,
+    transaction_type: Option<TransactionType>,
+    tx: &TransactionRequest,
+  ) -> Result<TransactionResponse> {
+    let settings = self.service.inner.settings();
+    let context = &self.context;
+    let payment = Prepared::new(
+      &settings,
+      context,
+      transaction_type.clone(),
+      Call::Payment(tx),
+    );
+    let Some(store) = &settings.idempotency_store else {
+      return self.service.execute(&payment);
+    };
+
+    let idempotency_key = context.idempotency_key_of(tx);
+    let store = store.as_ref();
+    if let Some(server_correlation_id) = idempotency::begin_payment(store, &idempotency_key)? {
+      let call = Call::Status(&server_correlation_id);
+      let status: TransactionStatus =
+        self
+          .service
+          .execute(&Prepared::new(&settings, context, transaction_type, call))?;
+      return Ok(TransactionResponse::from(status));
+    }
+    let res = self.service.execute(&payment);
+    idempotency::finish_payment(store, &idempotency_key, &res);
+    res
+  }
+
+  /// Poll the status of a transaction until it is completed or failed.
+  ///
+  /// See `TransactionService::wait_for_status`.
+  pub fn wait_for_status(
+    &self,
+    server_correlation_id: &str,
+    interval: Duration,
+    timeout: Duration,
+  ) -> Result<TransactionStatus> {
+    let mut poller = PollPolicy::fixed(interval, timeout).start();
+    self.poll(server_correlation_id, &mut poller)
+  }
+
+  /// Poll the status of a transaction until it is completed or failed.
+  ///
+  /// See `TransactionService::wait_for_completion`.
+  pub fn wait_for_completion(
+    &self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> Result<Completion> {
+    let status = self.poll(server_correlation_id, &mut policy.start())?;
+    let details = if status.status.is_success() {
+      Some(self.get_transaction(&status.object_reference)?)
+    } else {
+      None
+    };
+    Ok(Completion::new(status, details))
+  }
+
+  fn poll(&self, server_correlation_id: &str, poller: &mut Poller) -> Result<TransactionStatus> {
+    loop {
+      let status = self.get_transaction_status(server_correlation_id)?;
+      match poller.after(server_correlation_id, &status.status)? {
+        Some(delay) => thread::sleep(delay),
+        None => return Ok(status),
+      }
+    }
+  }
+}
+
+/// Blocking MVola client.
+///
+/// Cheap to clone: the clones share the same connection pool and token.
+#[derive(Clone)]
+pub struct MVola {
+  pub transaction: TransactionService,
+  pub auth: AuthService,
+  token_manager: Option<Arc<TokenManager>>,
+}
+
+impl MVola {
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub fn new(base_url: &str) -> Result<Self> {
+    MVolaBuilder::new().base_url(base_url).build_blocking()
+  }
+
+  /// Create a client that generates its access token from the consumer key
+  /// and consumer secret, and refreshes it before it expires.
+  pub fn with_credentials(
+    base_url: &str,
+    consumer_key: &str,
+    consumer_secret: &str,
+  ) -> Result<Self> {
+    MVolaBuilder::new()
+      .base_url(base_url)
+      .credentials(consumer_key, consumer_secret)
+      .build_blocking()
+  }
+
+  /// The builder of the async client: finish it with `build_blocking`.
+  pub fn builder() -> MVolaBuilder {
+    MVolaBuilder::new()
+  }
+
+  /// The token manager of a client built with credentials.
+  pub fn token_manager(&self) -> Option<&TokenManager> {
+    self.token_manager.as_deref()
+  }
+
+  pub(crate) fn from_parts(
+    base_url: &str,
+    transport: Arc<dyn BlockingTransport>,
+    options: Options,
+    api: TransactionApi,
+    credentials: Option<(String, String)>,
+    callback_signer: Option<Arc<CallbackSigner>>,
+  ) -> Result<Self> {
+    let auth = AuthService::with_transport(base_url, transport.clone())?;
+    let transaction = TransactionService::with_transport(base_url, transport)?;
+    transaction.set_options(options);
+    transaction.set_transaction_api(api);
+    if let Some(callback_signer) = callback_signer {
+      transaction.set_callback_signer(callback_signer);
+    }
+
+    let token_manager = credentials.map(|(consumer_key, consumer_secret)| {
+      Arc::new(TokenManager::new(
+        auth.clone(),
+        &consumer_key,
+        &consumer_secret,
+      ))
+    });
+    if let Some(token_manager) = &token_manager {
+      transaction.set_token_manager(token_manager.clone());
+    }
+
+    Ok(Self {
+      transaction,
+      auth,
+      token_manager,
+    })
+  }
+}
+
+pub(crate) fn default_transport(config: &TransportConfig) -> Result<Arc<dyn BlockingTransport>> {
+  Ok(Arc::new(UreqTransport::new(config)?))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::blocking::MVola;
+  use crate::error::MVolaError;
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::party::TransactionMetadata;
+  use crate::status::PaymentStatus;
+  use crate::types::TransactionRequest;
+  use mockito::{mock, SERVER_URL};
+  use std::time::Duration;
+
+  const TOKEN_RESPONSE: &str = r#"{
+    "access_token": "access_token",
+    "expires_in": 3600,
+    "token_type": "Bearer",
+    "scope": "EXT_INT_MVOLA_SCOPE"
+  }"#;
+
+  fn client(prefix: &str) -> MVola {
+    MVola::builder()
+      .base_url(&format!("{}/{}", SERVER_URL, prefix))
+      .credentials("consumer_key", "consumer_secret")
+      .user_account_identifier("msisdn;0343500003")
+      .partner_name("TestMVola")
+      .build_blocking()
+      .unwrap()
+  }
+
+  #[test]
+  fn test_send_payment() {
+    let _token = mock("POST", "/blocking-payment/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .create();
+    let _m = mock(
+      "POST",
+      "/blocking-payment/mvola/mm/transactions/type/merchantpay/1.0.0/",
+    )
+    .match_header("Authorization", "Bearer access_token")
+    .match_header("PartnerName", "TestMVola")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_response.json")
+    .create();
+
+    let tx = TransactionRequest {
+      amount: Money::ariary(1000),
+      currency: String::from("Ar"),
+      description_text: String::from("test"),
+      request_date: String::from("2022-04-23T10:12:05.147Z"),
+      debit_party: Msisdn::new("0343500003").unwrap().to_party(),
+      credit_party: Msisdn::new("0343500004").unwrap().to_party(),
+      metadata: TransactionMetadata::default(),
+      requesting_organisation_transaction_reference: String::from("ref"),
+      original_transaction_reference: String::from("ref"),
+    };
+    let response = client("blocking-payment")
+      .transaction
+      .send_payment(tx)
+      .unwrap();
+
+    assert_eq!(response.status, PaymentStatus::Pending);
+    assert_eq!(
+      response.server_correlation_id,
+      "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3"
+    );
+  }
+
+  #[test]
+  fn test_get_transaction() {
+    let _token = mock("POST", "/blocking-details/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .create();
+    let _m = mock(
+      "GET",
+      "/blocking-details/mvola/mm/transactions/type/merchantpay/1.0.0/636042511",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_details.json")
+    .create();
+
+    let response = client("blocking-details")
+      .transaction
+      .get_transaction("636042511")
+      .unwrap();
+
+    assert_eq!(response.amount, Money::ariary(10000));
+  }
+
+  #[test]
+  fn test_wait_for_status() {
+    let _token = mock("POST", "/blocking-status/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .create();
+    let _m = mock(
+      "GET",
+      "/blocking-status/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+
+    let status = client("blocking-status")
+      .transaction
+      .wait_for_status(
+        "2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+        Duration::from_millis(10),
+        Duration::from_secs(1),
+      )
+      .unwrap();
+
+    assert_eq!(status.status, PaymentStatus::Completed);
+    assert_eq!(status.object_reference, "636042511");
+  }
+
+  #[test]
+  fn test_wait_for_status_unknown() {
+    let _token = mock("POST", "/blocking-unknown/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .create();
+    let _m = mock(
+      "GET",
+      "/blocking-unknown/mvola/mm/transactions/type/merchantpay/1.0.0/status/a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      r#"{
+          "status": "processing",
+          "serverCorrelationId": "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+          "notificationMethod": "polling"
+      }"#,
+    )
+    .create();
+
+    // An unknown status is not final: the polling goes on until the deadline.
+    let err = client("blocking-unknown")
+      .transaction
+      .wait_for_status(
+        "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+        Duration::from_millis(10),
+        Duration::from_millis(50),
+      )
+      .unwrap_err();
+    match err {
+      MVolaError::DeadlineExceeded { status, .. } => {
+        assert_eq!(status, PaymentStatus::Unknown(String::from("processing")))
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[test]
+  fn test_wait_for_status_deadline() {
+    let _token = mock("POST", "/blocking-deadline/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .create();
+    let _m = mock(
+      "GET",
+      "/blocking-deadline/mvola/mm/transactions/type/merchantpay/1.0.0/status/a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      r#"{
+          "status": "pending",
+          "serverCorrelationId": "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+          "notificationMethod": "polling"
+      }"#,
+    )
+    .create();
+
+    let err = client("blocking-deadline")
+      .transaction
+      .wait_for_status(
+        "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+        Duration::from_millis(10),
+        Duration::from_millis(100),
+      )
+      .unwrap_err();
+
+    match err {
+      MVolaError::DeadlineExceeded {
+        server_correlation_id,
+        status,
+      } => {
+        assert_eq!(
+          server_correlation_id,
+          "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3"
+        );
+        assert_eq!(status, PaymentStatus::Pending);
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[test]
+  fn test_api_error() {
+    let _token = mock("POST", "/blocking-error/token")
+      .with_status(401)
+      .with_header("Content-Type", "application/json")
+      .with_body(
+        r#"{"error_description":"Client Authentication failed.","error":"invalid_client"}"#,
+      )
+      .create();
+
+    let err = client("blocking-error")
+      .transaction
+      .get_transaction("636042511")
+      .unwrap_err();
+
+    assert!(matches!(
+      err,
+      MVolaError::Authentication { status: 401, .. }
+    ));
+  }
+}
@@ -0,0 +1,100 @@
+use crate::error::{MVolaError, Result};
+use crate::http::{HttpRequest, HttpResponse, Method, TransportConfig};
+use std::io::{self, Read};
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Sends the HTTP requests of the blocking services.
+///
+/// The blocking counterpart of `mvola::http::HttpTransport`.
+pub trait BlockingTransport: Send + Sync {
+  fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+/// Transport backed by ureq.
+#[derive(Clone)]
+pub struct UreqTransport {
+  agent: ureq::Agent,
+}
+
+impl UreqTransport {
+  /// # Errors
+  /// * `MVolaError::Transport` - If the proxy is not valid
+  pub fn new(config: &TransportConfig) -> Result<Self> {
+    let mut builder = ureq::AgentBuilder::new().timeout(config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    if let Some(connect_timeout) = config.connect_timeout {
+      builder = builder.timeout_connect(connect_timeout);
+    }
+    if let Some(proxy) = &config.proxy {
+      builder =
+        builder.proxy(ureq::Proxy::new(proxy).map_err(|e| MVolaError::Transport(Box::new(e)))?);
+    }
+    if let Some(user_agent) = &config.user_agent {
+      builder = builder.user_agent(user_agent);
+    }
+    Ok(Self {
+      agent: builder.build(),
+    })
+  }
+
+  /// Use an existing ureq agent.
+  pub fn from_agent(agent: ureq::Agent) -> Self {
+    Self { agent }
+  }
+}
+
+impl BlockingTransport for UreqTransport {
+  fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+    let mut req = self
+      .agent
+      .request(request.method.as_str(), request.url.as_str());
+    for (name, value) in &request.headers {
+      req = req.set(name, value);
+    }
+    let res = match (request.method, request.body) {
+      (_, Some(body)) => req.send_bytes(&body),
+      (Method::Get, None) => req.call(),
+      (_, None) => req.send_bytes(&[]),
+    };
+    let res = match res {
+      Ok(res) => res,
+      Err(ureq::Error::Status(_, res)) => res,
+      Err(ureq::Error::Transport(err)) => return Err(transport_error(err)),
+    };
+
+    let status = res.status();
+    let headers = res
+      .headers_names()
+      .into_iter()
+      .filter_map(|name| {
+        let value = res.header(&name)?.to_string();
+        Some((name, value))
+      })
+      .collect();
+    let mut body = vec![];
+    res.into_reader().read_to_end(&mut body).map_err(io_error)?;
+    Ok(HttpResponse {
+      status,
+      headers,
+      body,
+    })
+  }
+}
+
+fn transport_error(err: ureq::Transport) -> MVolaError {
+  let timed_out = std::error::Error::source(&err)
+    .and_then(|source| source.downcast_ref::<io::Error>())
+    .is_some_and(|e| e.kind() == io::ErrorKind::TimedOut);
+  if timed_out {
+    return MVolaError::Timeout;
+  }
+  MVolaError::Transport(Box::new(err))
+}
+
+fn io_error(err: io::Error) -> MVolaError {
+  match err.kind() {
+    io::ErrorKind::TimedOut => MVolaError::Timeout,
+    _ => MVolaError::Transport(Box::new(err)),
+  }
+}
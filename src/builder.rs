@@ -0,0 +1,413 @@
+use crate::auth::AuthService;
+#[cfg(feature = "blocking")]
+use crate::blocking;
+use crate::callback::CallbackSigner;
+use crate::error::{MVolaError, Result};
+use crate::http::{default_transport, HttpTransport, TransportConfig};
+use crate::idempotency::IdempotencyStore;
+use crate::routes::{ApiVersion, TransactionApi, TransactionType};
+use crate::token::TokenManager;
+use crate::transaction::TransactionService;
+use crate::types::{Options, Service};
+use crate::{Environment, MVola};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Builder for a `MVola` client.
+///
+/// # Example
+/// ```no_run
+/// let client = MVola::builder()
+///   .environment(Environment::Sandbox)
+///   .credentials(
+///     &env::var("CONSUMER_KEY").unwrap(),
+///     &env::var("CONSUMER_SECRET").unwrap(),
+///   )
+///   .partner_name("TestMVola")
+///   .user_language("FR")
+///   .user_account_identifier("msisdn;0343500003")
+///   .timeout(Duration::from_secs(10))
+///   .build()
+///   .unwrap();
+/// ```
+#[derive(Default)]
+pub struct MVolaBuilder {
+  base_url: Option<String>,
+  credentials: Option<(String, String)>,
+  partner_name: Option<String>,
+  user_language: Option<String>,
+  user_account_identifier: Option<String>,
+  callback_url: Option<String>,
+  callback_secret: Option<String>,
+  callback_ttl: Option<Duration>,
+  transaction_api: TransactionApi,
+  idempotency_store: Option<Arc<dyn IdempotencyStore>>,
+  transport_config: TransportConfig,
+  transport: Option<Arc<dyn HttpTransport>>,
+  #[cfg(feature = "blocking")]
+  blocking_transport: Option<Arc<dyn blocking::BlockingTransport>>,
+}
+
+impl MVolaBuilder {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Send the requests to the sandbox or production API. Defaults to the sandbox.
+  pub fn environment(mut self, environment: Environment) -> Self {
+    self.base_url = Some(String::from(environment.base_url()));
+    self
+  }
+
+  /// Send the requests to a custom base URL, e.g. a mock server.
+  pub fn base_url(mut self, base_url: &str) -> Self {
+    self.base_url = Some(String::from(base_url));
+    self
+  }
+
+  /// The consumer key and consumer secret used to generate access tokens.
+  pub fn credentials(mut self, consumer_key: &str, consumer_secret: &str) -> Self {
+    self.credentials = Some((String::from(consumer_key), String::from(consumer_secret)));
+    self
+  }
+
+  /// The `PartnerName` header sent with the transactions.
+  pub fn partner_name(mut self, partner_name: &str) -> Self {
+    self.partner_name = Some(String::from(partner_name));
+    self
+  }
+
+  /// The default `UserLanguage` header, e.g. "FR" or "MG".
+  pub fn user_language(mut self, user_language: &str) -> Self {
+    self.user_language = Some(String::from(user_language));
+    self
+  }
+
+  /// The merchant account, e.g. "msisdn;0343500003".
+  pub fn user_account_identifier(mut self, user_account_identifier: &str) -> Self {
+    self.user_account_identifier = Some(String::from(user_account_identifier));
+    self
+  }
+
+  /// The URL MVola notifies when a transaction completes.
+  pub fn callback_url(mut self, callback_url: &str) -> Self {
+    self.callback_url = Some(String::from(callback_url));
+    self
+  }
+
+  /// Sign the callback URL of each payment with this merchant secret.
+  ///
+  /// See `mvola::callback::CallbackSigner`. Requires a callback URL.
+  pub fn callback_secret(mut self, secret: &str) -> Self {
+    self.callback_secret = Some(String::from(secret));
+    self
+  }
+
+  /// How long a signed callback URL stays valid. Defaults to 24 hours.
+  pub fn callback_ttl(mut self, ttl: Duration) -> Self {
+    self.callback_ttl = Some(ttl);
+    self
+  }
+
+  /// The default transaction type of the status and details requests, and
+  /// of `send_payment`. Defaults to merchant pay.
+  pub fn transaction_type(mut self, transaction_type: TransactionType) -> Self {
+    self.transaction_api.transaction_type = transaction_type;
+    self
+  }
+
+  /// The version in the path of the transaction endpoints. Defaults to 1.0.0.
+  pub fn api_version(mut self, api_version: ApiVersion) -> Self {
+    self.transaction_api.version = api_version;
+    self
+  }
+
+  /// Record the payments in the given store, see `mvola::idempotency`.
+  pub fn idempotency_store(mut self, idempotency_store: Arc<dyn IdempotencyStore>) -> Self {
+    self.idempotency_store = Some(idempotency_store);
+    self
+  }
+
+  /// The maximum time to establish a connection.
+  pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+    self.transport_config.connect_timeout = Some(timeout);
+    self
+  }
+
+  /// The maximum time for a whole request. Defaults to 5 seconds.
+  pub fn timeout(mut self, timeout: Duration) -> Self {
+    self.transport_config.timeout = Some(timeout);
+    self
+  }
+
+  /// Send the requests through the given HTTP proxy, e.g. "http://proxy:3128".
+  pub fn proxy(mut self, proxy: &str) -> Self {
+    self.transport_config.proxy = Some(String::from(proxy));
+    self
+  }
+
+  /// The `User-Agent` header sent with every request.
+  pub fn user_agent(mut self, user_agent: &str) -> Self {
+    self.transport_config.user_agent = Some(String::from(user_agent));
+    self
+  }
+
+  /// Send the requests with a custom transport.
+  ///
+  /// The timeouts, proxy and user agent settings are then ignored.
+  pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+    self.transport = Some(transport);
+    self
+  }
+
+  /// Send the requests of the blocking client with a custom transport.
+  #[cfg(feature = "blocking")]
+  pub fn blocking_transport(mut self, transport: Arc<dyn blocking::BlockingTransport>) -> Self {
+    self.blocking_transport = Some(transport);
+    self
+  }
+
+  /// Build the client.
+  ///
+  /// The auth and transaction services share the same connection pool.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If the base URL or the proxy is not a valid URL
+  /// * `MVolaError::MissingConfiguration` - If a credential is empty, if a callback
+  ///   secret is set without a callback URL, or if there is no transport
+  pub fn build(self) -> Result<MVola> {
+    let base_url = self.resolved_base_url();
+    let options = self.options();
+    let credentials = self.consumer_credentials()?;
+    let callback_signer = self.callback_signer()?;
+    let transport = match self.transport {
+      Some(transport) => transport,
+      None => default_transport(&self.transport_config)?,
+    };
+
+    let auth = AuthService::with_transport(&base_url, transport.clone())?;
+    let transaction = TransactionService::with_transport(&base_url, transport)?;
+    transaction.set_options(options);
+    transaction.set_transaction_api(self.transaction_api.clone());
+    if let Some(callback_signer) = callback_signer {
+      transaction.set_callback_signer(callback_signer);
+    }
+    if let Some(idempotency_store) = self.idempotency_store {
+      transaction.set_idempotency_store(idempotency_store);
+    }
+
+    let token_manager = credentials.map(|(consumer_key, consumer_secret)| {
+      Arc::new(TokenManager::new(
+        auth.clone(),
+        &consumer_key,
+        &consumer_secret,
+      ))
+    });
+    if let Some(token_manager) = &token_manager {
+      transaction.set_token_manager(token_manager.clone());
+    }
+
+    Ok(MVola {
+      transaction,
+      auth,
+      token_manager,
+    })
+  }
+
+  /// Build a blocking client with the same settings.
+  ///
+  /// A custom transport must be set with `blocking_transport`.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If the base URL is not a valid URL
+  /// * `MVolaError::MissingConfiguration` - If a credential is empty, or if a callback
+  ///   secret is set without a callback URL
+  #[cfg(feature = "blocking")]
+  pub fn build_blocking(self) -> Result<blocking::MVola> {
+    let base_url = self.resolved_base_url();
+    let options = self.options();
+    let credentials = self.consumer_credentials()?;
+    let callback_signer = self.callback_signer()?;
+    let transport = match self.blocking_transport {
+      Some(transport) => transport,
+      None => blocking::default_transport(&self.transport_config)?,
+    };
+    let client = blocking::MVola::from_parts(
+      &base_url,
+      transport,
+      options,
+      self.transaction_api.clone(),
+      credentials,
+      callback_signer,
+    )?;
+    if let Some(idempotency_store) = self.idempotency_store {
+      client.transaction.set_idempotency_store(idempotency_store);
+    }
+    Ok(client)
+  }
+
+  fn resolved_base_url(&self) -> String {
+    self
+      .base_url
+      .clone()
+      .unwrap_or_else(|| String::from(Environment::Sandbox.base_url()))
+  }
+
+  fn options(&self) -> Options {
+    Options {
+      version: String::from("1.0"),
+      user_language: self.user_language.clone(),
+      user_account_identifier: self.user_account_identifier.clone().unwrap_or_default(),
+      partner_name: self.partner_name.clone(),
+      callback_url: self.callback_url.clone(),
+    }
+  }
+
+  fn consumer_credentials(&self) -> Result<Option<(String, String)>> {
+    let Some((consumer_key, consumer_secret)) = &self.credentials else {
+      return Ok(None);
+    };
+    let mut missing = vec![];
+    if consumer_key.is_empty() {
+      missing.push("consumer_key");
+    }
+    if consumer_secret.is_empty() {
+      missing.push("consumer_secret");
+    }
+    if !missing.is_empty() {
+      return Err(MVolaError::MissingConfiguration(missing));
+    }
+    Ok(self.credentials.clone())
+  }
+
+  fn callback_signer(&self) -> Result<Option<Arc<CallbackSigner>>> {
+    let Some(secret) = &self.callback_secret else {
+      return Ok(None);
+    };
+    if self.callback_url.is_none() {
+      return Err(MVolaError::MissingConfiguration(vec!["callback_url"]));
+    }
+    let mut signer = CallbackSigner::new(secret.as_bytes());
+    if let Some(ttl) = self.callback_ttl {
+      signer = signer.with_ttl(ttl);
+    }
+    Ok(Some(Arc::new(signer)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::MVolaError;
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::status::PaymentStatus;
+  use crate::types::{Service, TransactionRequest};
+  use crate::{Environment, MVola, PRODUCTION_URL, SANDBOX_URL};
+  use mockito::{mock, Matcher, SERVER_URL};
+
+  #[test]
+  fn test_environment_base_url() {
+    assert_eq!(Environment::Sandbox.base_url(), SANDBOX_URL);
+    assert_eq!(Environment::Production.base_url(), PRODUCTION_URL);
+  }
+
+  #[test]
+  fn test_missing_consumer_secret() {
+    let builder = MVola::builder().credentials("consumer_key", "");
+
+    match builder.build() {
+      Err(MVolaError::MissingConfiguration(fields)) => assert_eq!(fields, vec!["consumer_secret"]),
+      _ => panic!("expected a missing configuration error"),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_build() {
+    let _token = mock("POST", "/builder/token")
+      .match_header("User-Agent", "mvola-test")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(
+        r#"{
+            "access_token": "access_token",
+            "expires_in": 3600,
+            "token_type": "Bearer",
+            "scope": "EXT_INT_MVOLA_SCOPE"
+        }"#,
+      )
+      .create();
+    let status = mock(
+      "GET",
+      "/builder/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+    )
+    .match_header("Authorization", "Bearer access_token")
+    .match_header("User-Agent", "mvola-test")
+    .match_header("UserLanguage", "MG")
+    .match_header("PartnerName", "TestMVola")
+    .match_header("UserAccountIdentifier", "msisdn;0343500003")
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+
+    let client = MVola::builder()
+      .base_url(&format!("{}/builder", SERVER_URL))
+      .credentials("consumer_key", "consumer_secret")
+      .partner_name("TestMVola")
+      .user_language("MG")
+      .user_account_identifier("msisdn;0343500003")
+      .user_agent("mvola-test")
+      .build()
+      .unwrap();
+
+    let response = client
+      .transaction
+      .get_transaction_status("2ba1d66a-25cf-4c12-8a6f-4cb01255148e")
+      .await
+      .unwrap();
+
+    assert_eq!(response.status, PaymentStatus::Completed);
+    status.assert();
+  }
+
+  #[tokio::test]
+  async fn test_signed_callback_url() {
+    let payment = mock(
+      "POST",
+      "/signed-callback/mvola/mm/transactions/type/merchantpay/1.0.0/",
+    )
+    .match_header(
+      "X-Callback-URL",
+      Matcher::Regex(String::from(
+        r"^https://shop\.mg/callback\?cid=[0-9a-f-]{36}&ref=order-42&exp=\d+&sig=[0-9a-f]{64}$",
+      )),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_response.json")
+    .create();
+
+    let client = MVola::builder()
+      .base_url(&format!("{}/signed-callback", SERVER_URL))
+      .user_account_identifier("msisdn;0343500004")
+      .callback_url("https://shop.mg/callback")
+      .callback_secret("merchant secret")
+      .build()
+      .unwrap();
+    client.transaction.set_authorization("access token");
+
+    let tx = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0343500003").unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test")
+      .reference("order-42")
+      .build()
+      .unwrap();
+    client.transaction.send_payment(tx).await.unwrap();
+    payment.assert();
+
+    let err = MVola::builder().callback_secret("merchant secret").build();
+    assert!(
+      matches!(err, Err(MVolaError::MissingConfiguration(fields)) if fields == vec!["callback_url"])
+    );
+  }
+}
@@ -0,0 +1,521 @@
+//! Notifications sent by MVola to the `X-Callback-URL` of a transaction.
+//!
+//! Once the customer confirms or rejects a payment, MVola sends a `PUT`
+//! request with a JSON body to the callback URL. `parse_callback` turns the
+//! headers and body of that request into a `CallbackNotification`, whatever
+//! web framework receives it.
+//!
+//! MVola does not sign its callbacks. With a `CallbackSigner`, each payment is
+//! sent with its own callback URL carrying a token signed with a merchant
+//! secret, and `parse_signed_callback` only trusts requests to such URLs.
+
+use crate::error::Result as MVolaResult;
+use crate::money::Money;
+use crate::party::{Party, TransactionMetadata};
+use crate::status::PaymentStatus;
+use crate::types::Fee;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use url::Url;
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// The body of a callback request.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallbackNotification {
+  pub transaction_status: PaymentStatus,
+  /// The id returned by `send_payment`.
+  pub server_correlation_id: String,
+  /// The reference of the transaction, only sent for completed transactions.
+  #[serde(default)]
+  pub transaction_reference: Option<String>,
+  #[serde(default)]
+  pub request_date: Option<String>,
+  #[serde(default)]
+  pub debit_party: Party,
+  #[serde(default)]
+  pub credit_party: Party,
+  #[serde(default)]
+  pub fees: Vec<Fee>,
+  #[serde(default)]
+  pub metadata: TransactionMetadata,
+  /// The `X-CorrelationID` header of the callback request.
+  #[serde(skip)]
+  pub correlation_id: Option<String>,
+}
+
+impl CallbackNotification {
+  /// The sum of the fees of the transaction.
+  pub fn total_fees(&self) -> Money {
+    self.fees.iter().map(|fee| fee.fee_amount).sum()
+  }
+
+  /// The value of a metadata entry, e.g. "partnerName".
+  pub fn metadata(&self, key: &str) -> Option<&str> {
+    self.metadata.get(key)
+  }
+}
+
+/// Errors returned by `parse_callback` and `parse_signed_callback`.
+#[derive(Debug, thiserror::Error)]
+pub enum CallbackError {
+  /// The request is not a JSON request.
+  #[error("unsupported content type: {0}")]
+  UnsupportedContentType(String),
+  /// The request has no body.
+  #[error("empty callback body")]
+  EmptyBody,
+  /// The body is not a valid notification.
+  #[error("invalid callback body: {0}")]
+  InvalidBody(#[from] serde_json::Error),
+  /// The callback URL has no token, or the token was not signed with our secret.
+  #[error("invalid callback token: {0}")]
+  InvalidToken(&'static str),
+  /// The token of the callback URL has expired.
+  #[error("expired callback token")]
+  ExpiredToken,
+  /// The token of the callback URL was already used with the same status.
+  #[error("replayed callback token")]
+  ReplayedToken,
+}
+
+/// Signs and verifies per-payment callback URLs.
+///
+/// The token is an HMAC-SHA256, under the merchant secret, of the correlation
+/// id, the reference of the transaction and an expiry time. It is added to the
+/// query string of the callback URL as `cid`, `ref`, `exp` and `sig`.
+pub struct CallbackSigner {
+  secret: Vec<u8>,
+  ttl: Duration,
+}
+
+impl CallbackSigner {
+  /// A signer whose tokens are valid for 24 hours.
+  pub fn new(secret: &[u8]) -> Self {
+    Self {
+      secret: secret.to_vec(),
+      ttl: Duration::from_secs(24 * 60 * 60),
+    }
+  }
+
+  /// How long a callback URL stays valid after the payment is sent.
+  pub fn with_ttl(mut self, ttl: Duration) -> Self {
+    self.ttl = ttl;
+    self
+  }
+
+  /// Add a signed token to the callback URL of a payment.
+  /// # Arguments
+  /// * `callback_url` - The base callback URL, e.g. "https://shop.mg/mvola/callback"
+  /// * `correlation_id` - The `X-CorrelationID` of the payment request
+  /// * `reference` - The `requestingOrganisationTransactionReference` of the payment
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `callback_url` is not a valid URL
+  pub fn callback_url(
+    &self,
+    callback_url: &str,
+    correlation_id: &str,
+    reference: &str,
+  ) -> MVolaResult<String> {
+    let mut url = Url::parse(callback_url)?;
+    let expires_at = unix_time(SystemTime::now() + self.ttl);
+    let signature = hex::encode(self.sign(correlation_id, reference, expires_at));
+    url
+      .query_pairs_mut()
+      .append_pair("cid", correlation_id)
+      .append_pair("ref", reference)
+      .append_pair("exp", &expires_at.to_string())
+      .append_pair("sig", &signature);
+    Ok(url.into())
+  }
+
+  /// Check the token of a callback URL.
+  /// # Arguments
+  /// * `url` - The URL of the callback request, absolute or as a path and query
+  /// # Errors
+  /// * `CallbackError::InvalidToken` - If the token is missing or its signature is wrong
+  /// * `CallbackError::ExpiredToken` - If the token has expired
+  pub fn verify(&self, url: &str) -> Result<CallbackToken, CallbackError> {
+    let base = Url::parse("http://localhost/").expect("valid base URL");
+    let url = base
+      .join(url)
+      .map_err(|_| CallbackError::InvalidToken("malformed url"))?;
+    let param = |name: &str| {
+      url
+        .query_pairs()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.into_owned())
+        .ok_or(CallbackError::InvalidToken("missing parameter"))
+    };
+    let correlation_id = param("cid")?;
+    let reference = param("ref")?;
+    let expires_at: u64 = param("exp")?
+      .parse()
+      .map_err(|_| CallbackError::InvalidToken("malformed expiry"))?;
+    let signature =
+      hex::decode(param("sig")?).map_err(|_| CallbackError::InvalidToken("malformed signature"))?;
+
+    self
+      .mac(&correlation_id, &reference, expires_at)
+      .verify_slice(&signature)
+      .map_err(|_| CallbackError::InvalidToken("bad signature"))?;
+    if expires_at <= unix_time(SystemTime::now()) {
+      return Err(CallbackError::ExpiredToken);
+    }
+    Ok(CallbackToken {
+      correlation_id,
+      reference,
+      expires_at,
+      signature,
+    })
+  }
+
+  fn sign(&self, correlation_id: &str, reference: &str, expires_at: u64) -> Vec<u8> {
+    self
+      .mac(correlation_id, reference, expires_at)
+      .finalize()
+      .into_bytes()
+      .to_vec()
+  }
+
+  fn mac(&self, correlation_id: &str, reference: &str, expires_at: u64) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(&self.secret).expect("HMAC accepts any key length");
+    // Length prefixes keep "a|b" + "c" and "a" + "b|c" apart.
+    let message = format!(
+      "{}:{}|{}:{}|{}",
+      correlation_id.len(),
+      correlation_id,
+      reference.len(),
+      reference,
+      expires_at
+    );
+    mac.update(message.as_bytes());
+    mac
+  }
+}
+
+/// A verified callback token.
+///
+/// Its fields are signed with the merchant secret, unlike the body of the
+/// notification: `reference` is the key to trust to find the payment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CallbackToken {
+  /// The `X-CorrelationID` of the payment request.
+  pub correlation_id: String,
+  /// The reference of the transaction on the merchant side.
+  pub reference: String,
+  /// The expiry, in seconds since the Unix epoch.
+  pub expires_at: u64,
+  signature: Vec<u8>,
+}
+
+/// Remembers the tokens already used until they expire.
+///
+/// A payment is notified once per status, e.g. pending then completed, on
+/// the same callback URL: a token is accepted once for each status.
+#[derive(Default)]
+pub struct ReplayGuard {
+  seen: Mutex<HashMap<(Vec<u8>, PaymentStatus), u64>>,
+}
+
+impl ReplayGuard {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Accept a token once for the given status.
+  /// # Errors
+  /// * `CallbackError::ReplayedToken` - If the token was already accepted with this status
+  pub fn check(&self, token: &CallbackToken, status: &PaymentStatus) -> Result<(), CallbackError> {
+    let now = unix_time(SystemTime::now());
+    let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+    seen.retain(|_, expires_at| *expires_at > now);
+    let key = (token.signature.clone(), status.clone());
+    if seen.contains_key(&key) {
+      return Err(CallbackError::ReplayedToken);
+    }
+    seen.insert(key, token.expires_at);
+    Ok(())
+  }
+
+  /// Accept a token again with the given status, e.g. when the notification
+  /// it carried could not be handled and MVola will retry it.
+  pub fn release(&self, token: &CallbackToken, status: &PaymentStatus) {
+    let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+    seen.remove(&(token.signature.clone(), status.clone()));
+  }
+}
+
+/// Verify the token of a callback request, then parse it.
+///
+/// The token is only marked as used once the body is valid, so that MVola can
+/// retry a request that failed to parse.
+///
+/// Only the token is authenticated: the ids in the body, such as
+/// `server_correlation_id`, are not signed and may name another payment. Look
+/// the payment up by `token.reference`, and only trust the body once its
+/// `server_correlation_id` matches the one recorded for that payment.
+/// # Arguments
+/// * `signer` - The signer used to send the payment
+/// * `guard` - The tokens already used
+/// * `url` - The URL of the callback request, absolute or as a path and query
+/// * `headers` - The headers of the request, as name and value pairs
+/// * `body` - The raw body of the request
+/// # Errors
+/// * `CallbackError` - If the token is invalid, expired or replayed, or if the request is invalid
+pub fn parse_signed_callback<I, K, V>(
+  signer: &CallbackSigner,
+  guard: &ReplayGuard,
+  url: &str,
+  headers: I,
+  body: &[u8],
+) -> Result<(CallbackToken, CallbackNotification), CallbackError>
+where
+  I: IntoIterator<Item = (K, V)>,
+  K: AsRef<str>,
+  V: AsRef<str>,
+{
+  let token = signer.verify(url)?;
+  let notification = parse_callback(headers, body)?;
+  guard.check(&token, &notification.transaction_status)?;
+  Ok((token, notification))
+}
+
+fn unix_time(time: SystemTime) -> u64 {
+  time
+    .duration_since(UNIX_EPOCH)
+    .map(|d| d.as_secs())
+    .unwrap_or(0)
+}
+
+/// Parse a callback request received from MVola.
+/// # Arguments
+/// * `headers` - The headers of the request, as name and value pairs
+/// * `body` - The raw body of the request
+/// # Errors
+/// * `CallbackError::UnsupportedContentType` - If the `Content-Type` is not JSON
+/// * `CallbackError::EmptyBody` - If the body is empty
+/// * `CallbackError::InvalidBody` - If the body is not a valid notification
+/// # Example
+/// ```no_run
+/// let headers = vec![("Content-Type", "application/json")];
+/// let notification = parse_callback(headers, body)?;
+/// if notification.transaction_status.is_success() {
+///   println!("{} paid", notification.server_correlation_id);
+/// }
+/// ```
+pub fn parse_callback<I, K, V>(
+  headers: I,
+  body: &[u8],
+) -> Result<CallbackNotification, CallbackError>
+where
+  I: IntoIterator<Item = (K, V)>,
+  K: AsRef<str>,
+  V: AsRef<str>,
+{
+  let mut correlation_id = None;
+  for (name, value) in headers {
+    let (name, value) = (name.as_ref(), value.as_ref().trim());
+    if name.eq_ignore_ascii_case("Content-Type") && !is_json(value) {
+      return Err(CallbackError::UnsupportedContentType(String::from(value)));
+    }
+    if name.eq_ignore_ascii_case("X-CorrelationID") {
+      correlation_id = Some(String::from(value));
+    }
+  }
+
+  if body.iter().all(u8::is_ascii_whitespace) {
+    return Err(CallbackError::EmptyBody);
+  }
+  let mut notification: CallbackNotification = serde_json::from_slice(body)?;
+  notification.correlation_id = correlation_id;
+  Ok(notification)
+}
+
+fn is_json(content_type: &str) -> bool {
+  let mime = content_type.split(';').next().unwrap_or("").trim();
+  mime.eq_ignore_ascii_case("application/json") || mime.to_ascii_lowercase().ends_with("+json")
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::callback::{
+    parse_callback, parse_signed_callback, CallbackError, CallbackSigner, ReplayGuard,
+  };
+  use crate::money::Money;
+  use crate::status::PaymentStatus;
+  use std::time::Duration;
+
+  const JSON: [(&str, &str); 2] = [
+    ("Content-Type", "application/json; charset=utf-8"),
+    ("X-CorrelationID", "c8c80b63-df41-46e5-a04a-db9515acc51a"),
+  ];
+
+  #[test]
+  fn test_parse_completed() {
+    let body = include_bytes!("../tests/fixtures/callback_completed.json");
+    let notification = parse_callback(JSON, body).unwrap();
+
+    assert_eq!(notification.transaction_status, PaymentStatus::Completed);
+    assert_eq!(
+      notification.server_correlation_id,
+      "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3"
+    );
+    assert_eq!(
+      notification.transaction_reference.as_deref(),
+      Some("636085941")
+    );
+    assert_eq!(notification.total_fees(), Money::ariary(150));
+    assert_eq!(notification.metadata("partnerName"), Some("TestMVola"));
+    assert_eq!(
+      notification.correlation_id.as_deref(),
+      Some("c8c80b63-df41-46e5-a04a-db9515acc51a")
+    );
+  }
+
+  #[test]
+  fn test_parse_failed() {
+    let body = include_bytes!("../tests/fixtures/callback_failed.json");
+    let notification = parse_callback(Vec::<(String, String)>::new(), body).unwrap();
+
+    assert_eq!(notification.transaction_status, PaymentStatus::Failed);
+    assert_eq!(notification.transaction_reference, None);
+    assert_eq!(notification.total_fees(), Money::ZERO);
+    assert_eq!(notification.correlation_id, None);
+  }
+
+  #[test]
+  fn test_parse_errors() {
+    let body = include_bytes!("../tests/fixtures/callback_completed.json");
+    assert!(matches!(
+      parse_callback([("content-type", "text/plain")], body),
+      Err(CallbackError::UnsupportedContentType(_))
+    ));
+    assert!(matches!(
+      parse_callback(JSON, b" "),
+      Err(CallbackError::EmptyBody)
+    ));
+    assert!(matches!(
+      parse_callback(JSON, br#"{"transactionStatus":"completed"}"#),
+      Err(CallbackError::InvalidBody(_))
+    ));
+  }
+
+  #[test]
+  fn test_signed_callback_url() {
+    let signer = CallbackSigner::new(b"merchant secret");
+    let url = signer
+      .callback_url("https://shop.mg/mvola/callback?shop=1", "cid-1", "order 42")
+      .unwrap();
+    assert!(url.starts_with("https://shop.mg/mvola/callback?shop=1&cid=cid-1&ref=order+42&exp="));
+
+    let token = signer.verify(&url).unwrap();
+    assert_eq!(token.correlation_id, "cid-1");
+    assert_eq!(token.reference, "order 42");
+
+    let path = &url["https://shop.mg".len()..];
+    assert_eq!(signer.verify(path).unwrap(), token);
+  }
+
+  #[test]
+  fn test_forged_callback_url() {
+    let signer = CallbackSigner::new(b"merchant secret");
+    let url = signer
+      .callback_url("https://shop.mg/callback", "cid-1", "order-42")
+      .unwrap();
+
+    let forged = url.replace("ref=order-42", "ref=order-43");
+    assert!(matches!(
+      signer.verify(&forged),
+      Err(CallbackError::InvalidToken(_))
+    ));
+    let other = CallbackSigner::new(b"other secret");
+    assert!(matches!(
+      other.verify(&url),
+      Err(CallbackError::InvalidToken(_))
+    ));
+    assert!(matches!(
+      signer.verify("https://shop.mg/callback"),
+      Err(CallbackError::InvalidToken(_))
+    ));
+
+    let expired = CallbackSigner::new(b"merchant secret").with_ttl(Duration::ZERO);
+    let url = expired
+      .callback_url("https://shop.mg/callback", "cid-1", "order-42")
+      .unwrap();
+    assert!(matches!(
+      expired.verify(&url),
+      Err(CallbackError::ExpiredToken)
+    ));
+  }
+
+  #[test]
+  fn test_replayed_callback() {
+    let signer = CallbackSigner::new(b"merchant secret");
+    let guard = ReplayGuard::new();
+    let url = signer
+      .callback_url("https://shop.mg/callback", "cid-1", "order-42")
+      .unwrap();
+    let body = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    assert!(matches!(
+      parse_signed_callback(&signer, &guard, &url, JSON, b"{}"),
+      Err(CallbackError::InvalidBody(_))
+    ));
+    let (token, notification) = parse_signed_callback(&signer, &guard, &url, JSON, body).unwrap();
+    assert_eq!(token.reference, "order-42");
+    assert_eq!(notification.transaction_status, PaymentStatus::Completed);
+    assert!(matches!(
+      parse_signed_callback(&signer, &guard, &url, JSON, body),
+      Err(CallbackError::ReplayedToken)
+    ));
+  }
+
+  #[test]
+  fn test_one_callback_per_status() {
+    let signer = CallbackSigner::new(b"merchant secret");
+    let guard = ReplayGuard::new();
+    let url = signer
+      .callback_url("https://shop.mg/callback", "cid-1", "order-42")
+      .unwrap();
+    let pending = include_bytes!("../tests/fixtures/callback_pending.json");
+    let completed = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    parse_signed_callback(&signer, &guard, &url, JSON, pending).unwrap();
+    let (_, notification) = parse_signed_callback(&signer, &guard, &url, JSON, completed).unwrap();
+    assert_eq!(notification.transaction_status, PaymentStatus::Completed);
+    assert!(matches!(
+      parse_signed_callback(&signer, &guard, &url, JSON, pending),
+      Err(CallbackError::ReplayedToken)
+    ));
+  }
+
+  #[test]
+  fn test_swapped_server_correlation_id() {
+    let signer = CallbackSigner::new(b"merchant secret");
+    let guard = ReplayGuard::new();
+    let url = signer
+      .callback_url("https://shop.mg/callback", "cid-1", "order-42")
+      .unwrap();
+    let body = String::from_utf8_lossy(include_bytes!("../tests/fixtures/callback_completed.json"))
+      .replace(
+        "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+        "2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+      );
+
+    // The body is not signed: the token still names the payment it was issued for.
+    let (token, notification) =
+      parse_signed_callback(&signer, &guard, &url, JSON, body.as_bytes()).unwrap();
+    assert_eq!(token.reference, "order-42");
+    assert_eq!(token.correlation_id, "cid-1");
+    assert_eq!(
+      notification.server_correlation_id,
+      "2ba1d66a-25cf-4c12-8a6f-4cb01255148e"
+    );
+  }
+}
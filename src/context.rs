@@ -0,0 +1,169 @@
+use crate::routes::{ApiVersion, TransactionApi, TransactionType};
+use crate::types::{Options, TransactionRequest};
+use uuid::Uuid;
+
+/// The headers and endpoints of one call, overriding the defaults of the service.
+///
+/// Unset fields fall back to the `Options` and `TransactionApi` of the
+/// service. Unless a correlation id is set, each request gets a new
+/// `X-CorrelationID`.
+///
+/// # Example
+/// ```no_run
+/// let context = RequestContext::new()
+///   .user_language("MG")
+///   .user_account_identifier("msisdn;0343500004")
+///   .callback_url("https://shop.mg/mvola/callback");
+/// let response = client.transaction.with_context(context).send_payment(tx).await;
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RequestContext {
+  correlation_id: Option<String>,
+  user_language: Option<String>,
+  user_account_identifier: Option<String>,
+  callback_url: Option<String>,
+  transaction_type: Option<TransactionType>,
+  api_version: Option<ApiVersion>,
+  idempotency_key: Option<String>,
+}
+
+impl RequestContext {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Send this `X-CorrelationID` with every request of the call instead of a new one.
+  pub fn correlation_id(mut self, correlation_id: &str) -> Self {
+    self.correlation_id = Some(String::from(correlation_id));
+    self
+  }
+
+  /// The `UserLanguage` header, e.g. "FR" or "MG".
+  pub fn user_language(mut self, user_language: &str) -> Self {
+    self.user_language = Some(String::from(user_language));
+    self
+  }
+
+  /// The account sending the request, e.g. "msisdn;0343500004".
+  pub fn user_account_identifier(mut self, user_account_identifier: &str) -> Self {
+    self.user_account_identifier = Some(String::from(user_account_identifier));
+    self
+  }
+
+  /// The URL MVola notifies when the transaction completes.
+  pub fn callback_url(mut self, callback_url: &str) -> Self {
+    self.callback_url = Some(String::from(callback_url));
+    self
+  }
+
+  /// The type of the transactions whose status or details are requested.
+  pub fn transaction_type(mut self, transaction_type: TransactionType) -> Self {
+    self.transaction_type = Some(transaction_type);
+    self
+  }
+
+  /// The version of the API, e.g. `ApiVersion::new(1, 0, 0)`.
+  pub fn api_version(mut self, api_version: ApiVersion) -> Self {
+    self.api_version = Some(api_version);
+    self
+  }
+
+  /// The key of the payment in the idempotency store of the service.
+  ///
+  /// Defaults to the `requestingOrganisationTransactionReference` of the request.
+  pub fn idempotency_key(mut self, idempotency_key: &str) -> Self {
+    self.idempotency_key = Some(String::from(idempotency_key));
+    self
+  }
+
+  /// The idempotency key of a payment.
+  pub(crate) fn idempotency_key_of(&self, tx: &TransactionRequest) -> String {
+    self
+      .idempotency_key
+      .clone()
+      .unwrap_or_else(|| tx.requesting_organisation_transaction_reference.clone())
+  }
+
+  /// The endpoints of the call, completed with the defaults of the service.
+  pub(crate) fn api(&self, defaults: &TransactionApi) -> TransactionApi {
+    TransactionApi {
+      transaction_type: self
+        .transaction_type
+        .clone()
+        .unwrap_or_else(|| defaults.transaction_type.clone()),
+      version: self.api_version.unwrap_or(defaults.version),
+    }
+  }
+
+  /// The headers of the next request, completed with the defaults of the service.
+  pub(crate) fn headers(&self, defaults: &Options) -> Headers {
+    Headers {
+      version: defaults.version.clone(),
+      correlation_id: self
+        .correlation_id
+        .clone()
+        .unwrap_or_else(|| Uuid::new_v4().to_string()),
+      user_language: self
+        .user_language
+        .clone()
+        .or_else(|| defaults.user_language.clone()),
+      user_account_identifier: self
+        .user_account_identifier
+        .clone()
+        .unwrap_or_else(|| defaults.user_account_identifier.clone()),
+      partner_name: defaults.partner_name.clone(),
+      callback_url: self
+        .callback_url
+        .clone()
+        .or_else(|| defaults.callback_url.clone()),
+    }
+  }
+}
+
+/// The headers of one request.
+pub(crate) struct Headers {
+  pub(crate) version: String,
+  pub(crate) correlation_id: String,
+  pub(crate) user_language: Option<String>,
+  pub(crate) user_account_identifier: String,
+  pub(crate) partner_name: Option<String>,
+  pub(crate) callback_url: Option<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::context::RequestContext;
+  use crate::types::Options;
+
+  #[test]
+  fn test_headers() {
+    let defaults = Options {
+      version: String::from("1.0"),
+      user_language: Some(String::from("FR")),
+      user_account_identifier: String::from("msisdn;0343500004"),
+      partner_name: Some(String::from("TestMVola")),
+      callback_url: Some(String::from("https://shop.mg/callback")),
+    };
+
+    let first = RequestContext::new().headers(&defaults);
+    let second = RequestContext::new().headers(&defaults);
+    assert_eq!(first.user_language.as_deref(), Some("FR"));
+    assert_eq!(first.user_account_identifier, "msisdn;0343500004");
+    assert_eq!(first.correlation_id.len(), 36);
+    assert_ne!(first.correlation_id, second.correlation_id);
+
+    let context = RequestContext::new()
+      .correlation_id("order-42")
+      .user_language("MG")
+      .user_account_identifier("msisdn;0343500005");
+    let headers = context.headers(&defaults);
+    assert_eq!(headers.correlation_id, "order-42");
+    assert_eq!(headers.user_language.as_deref(), Some("MG"));
+    assert_eq!(headers.user_account_identifier, "msisdn;0343500005");
+    assert_eq!(headers.partner_name.as_deref(), Some("TestMVola"));
+    assert_eq!(
+      headers.callback_url.as_deref(),
+      Some("https://shop.mg/callback")
+    );
+  }
+}
@@ -0,0 +1,109 @@
+use crate::money::Money;
+use crate::status::PaymentStatus;
+use crate::types::ErrorResponse;
+use std::fmt;
+
+pub type Result<T> = std::result::Result<T, MVolaError>;
+
+/// Errors returned by the MVola client.
+#[derive(Debug, thiserror::Error)]
+pub enum MVolaError {
+  /// The request could not be sent or the response could not be read.
+  #[error("transport error: {0}")]
+  Transport(#[source] Box<dyn std::error::Error + Send + Sync>),
+  /// The request took longer than the configured timeout.
+  #[error("request timed out")]
+  Timeout,
+  /// The transaction was not completed nor failed before the polling deadline.
+  #[error("transaction {server_correlation_id} is still {status} after the polling deadline")]
+  DeadlineExceeded {
+    server_correlation_id: String,
+    status: PaymentStatus,
+  },
+  /// The response body is not the JSON document we expected.
+  #[error("failed to decode response: {0}")]
+  Decode(#[from] serde_json::Error),
+  /// The credentials or the access token were rejected.
+  #[error("authentication failed ({status}): {message}")]
+  Authentication { status: u16, message: String },
+  /// A required setting is missing, e.g. the authorization token.
+  #[error("missing configuration: {}", .0.join(", "))]
+  MissingConfiguration(Vec<&'static str>),
+  /// A required field of a request is missing, e.g. the amount.
+  #[error("incomplete request, missing: {}", .0.join(", "))]
+  IncompleteRequest(Vec<&'static str>),
+  /// The amount cannot be parsed or cannot be sent in a transaction.
+  #[error("invalid amount: {0}")]
+  InvalidAmount(String),
+  /// The phone number is malformed or is not a MVola number.
+  #[error("invalid msisdn: {0}")]
+  InvalidMsisdn(String),
+  /// The base URL or a request URL is not valid.
+  #[error("invalid url: {0}")]
+  InvalidUrl(#[from] url::ParseError),
+  /// The original transaction of a refund is not completed.
+  #[error("transaction {transaction_reference} cannot be refunded while it is {status}")]
+  NotRefundable {
+    transaction_reference: String,
+    status: PaymentStatus,
+  },
+  /// The refund is above what is left to refund on the original transaction.
+  #[error(
+    "cannot refund {requested} on transaction {transaction_reference}: only {remaining} left"
+  )]
+  RefundExceedsOriginal {
+    transaction_reference: String,
+    requested: Money,
+    remaining: Money,
+  },
+  /// A payment with the same idempotency key is being sent, or was sent
+  /// without an answer: settle it with `IdempotencyStore::resolve`, see
+  /// `mvola::idempotency`.
+  #[error("payment {idempotency_key} was already sent and its outcome is unknown")]
+  IndeterminatePayment { idempotency_key: String },
+  /// The idempotency store could not read or record an attempt.
+  #[error("idempotency store error: {0}")]
+  IdempotencyStore(#[source] Box<dyn std::error::Error + Send + Sync>),
+  /// MVola answered with an error status.
+  #[error("{0}")]
+  Api(Box<ApiError>),
+}
+
+/// An error answered by the MVola API.
+#[derive(Debug)]
+pub struct ApiError {
+  /// The HTTP status of the response.
+  pub status: u16,
+  /// The `X-CorrelationID` of the failed request.
+  pub correlation_id: Option<String>,
+  /// The parsed error body, if MVola sent one.
+  pub error: Option<ErrorResponse>,
+  /// The raw response body.
+  pub body: String,
+}
+
+impl ApiError {
+  /// The human readable description sent by MVola, e.g. "Insufficient funds".
+  pub fn description(&self) -> Option<&str> {
+    self
+      .error
+      .as_ref()
+      .and_then(|e| e.error_description.as_deref())
+  }
+}
+
+impl fmt::Display for ApiError {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "MVola API error ({})", self.status)?;
+    if let Some(error) = &self.error {
+      write!(f, " {}/{}", error.error_category, error.error_code)?;
+      if let Some(description) = &error.error_description {
+        write!(f, ": {}", description)?;
+      }
+    }
+    if let Some(correlation_id) = &self.correlation_id {
+      write!(f, " [correlation id: {}]", correlation_id)?;
+    }
+    Ok(())
+  }
+}
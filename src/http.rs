@@ -0,0 +1,351 @@
+//! HTTP transport used by the services.
+//!
+//! The services only build `HttpRequest`s and read `HttpResponse`s: the I/O is
+//! done by an `HttpTransport`. The crate ships a transport for surf, reqwest and
+//! hyper behind the cargo features of the same name, and any other client can be
+//! plugged in with `MVolaBuilder::transport`.
+
+use crate::error::{ApiError, MVolaError, Result};
+use crate::types::ErrorResponse;
+use async_trait::async_trait;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+use url::Url;
+
+#[cfg(all(
+  feature = "reqwest",
+  not(any(feature = "rustls", feature = "native-tls"))
+))]
+compile_error!(
+  "the reqwest transport needs a TLS stack: enable the `rustls` or `native-tls` feature"
+);
+#[cfg(all(
+  feature = "hyper",
+  not(any(feature = "hyper-rustls", feature = "hyper-native-tls"))
+))]
+compile_error!(
+  "the hyper transport needs a TLS stack: enable the `hyper-rustls` or `hyper-native-tls` feature"
+);
+
+#[cfg(feature = "hyper")]
+mod hyper_transport;
+#[cfg(feature = "reqwest")]
+mod reqwest_transport;
+#[cfg(feature = "surf")]
+mod surf_transport;
+
+#[cfg(feature = "hyper")]
+pub use hyper_transport::HyperTransport;
+#[cfg(feature = "reqwest")]
+pub use reqwest_transport::ReqwestTransport;
+#[cfg(feature = "surf")]
+pub use surf_transport::SurfTransport;
+
+const CORRELATION_ID: &str = "X-CorrelationID";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+  Get,
+  Post,
+  Put,
+}
+
+impl Method {
+  pub fn as_str(&self) -> &'static str {
+    match self {
+      Method::Get => "GET",
+      Method::Post => "POST",
+      Method::Put => "PUT",
+    }
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpRequest {
+  pub method: Method,
+  pub url: Url,
+  pub headers: Vec<(String, String)>,
+  pub body: Option<Vec<u8>>,
+}
+
+impl HttpRequest {
+  pub fn new(method: Method, url: Url) -> Self {
+    Self {
+      method,
+      url,
+      headers: vec![],
+      body: None,
+    }
+  }
+
+  /// Set a header, replacing any previous value.
+  pub fn set_header(&mut self, name: &str, value: &str) {
+    self.headers.retain(|(n, _)| !n.eq_ignore_ascii_case(name));
+    self.headers.push((String::from(name), String::from(value)));
+  }
+
+  pub fn header(&self, name: &str) -> Option<&str> {
+    find_header(&self.headers, name)
+  }
+
+  /// Set a JSON body and the matching content type.
+  pub fn set_json<T: Serialize>(&mut self, body: &T) -> Result<()> {
+    self.body = Some(serde_json::to_vec(body)?);
+    self.set_header("Content-Type", "application/json");
+    Ok(())
+  }
+
+  /// Set a form encoded body and the matching content type.
+  pub fn set_form<T: Serialize>(&mut self, body: &T) -> Result<()> {
+    let body = serde_urlencoded::to_string(body).map_err(|e| MVolaError::Transport(Box::new(e)))?;
+    self.body = Some(body.into_bytes());
+    self.set_header("Content-Type", "application/x-www-form-urlencoded");
+    Ok(())
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+  pub status: u16,
+  pub headers: Vec<(String, String)>,
+  pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+  pub fn header(&self, name: &str) -> Option<&str> {
+    find_header(&self.headers, name)
+  }
+}
+
+fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+  headers
+    .iter()
+    .find(|(n, _)| n.eq_ignore_ascii_case(name))
+    .map(|(_, v)| v.as_str())
+}
+
+/// Sends the HTTP requests of the services.
+///
+/// Implementations should map their timeouts to `MVolaError::Timeout` and
+/// other I/O failures to `MVolaError::Transport`. Error statuses are not
+/// errors at this level: the response is returned as is.
+#[async_trait]
+pub trait HttpTransport: Send + Sync {
+  async fn send(&self, request: HttpRequest) -> Result<HttpResponse>;
+}
+
+/// Settings of the transports shipped with the crate.
+#[derive(Debug, Clone, Default)]
+pub struct TransportConfig {
+  /// The maximum time to establish a connection.
+  pub connect_timeout: Option<Duration>,
+  /// The maximum time for a whole request.
+  pub timeout: Option<Duration>,
+  /// The HTTP proxy, e.g. "http://proxy:3128".
+  pub proxy: Option<String>,
+  /// The `User-Agent` header sent with every request.
+  pub user_agent: Option<String>,
+}
+
+/// Build the transport of the enabled cargo feature.
+///
+/// When several are enabled, reqwest is preferred over hyper, and hyper over surf.
+/// # Errors
+/// * `MVolaError::MissingConfiguration` - If no transport feature is enabled
+#[allow(unreachable_code, unused_variables)]
+pub fn default_transport(config: &TransportConfig) -> Result<Arc<dyn HttpTransport>> {
+  #[cfg(feature = "reqwest")]
+  return Ok(Arc::new(ReqwestTransport::new(config)?));
+  #[cfg(feature = "hyper")]
+  return Ok(Arc::new(HyperTransport::new(config)?));
+  #[cfg(feature = "surf")]
+  return Ok(Arc::new(SurfTransport::new(config)?));
+  Err(MVolaError::MissingConfiguration(vec!["transport"]))
+}
+
+/// Send the request and decode the JSON body of a successful response.
+///
+/// Error responses are turned into `MVolaError::Authentication` or `MVolaError::Api`.
+pub(crate) async fn recv_json<T: DeserializeOwned>(
+  transport: &dyn HttpTransport,
+  req: HttpRequest,
+) -> Result<T> {
+  let request_correlation_id = req.header(CORRELATION_ID).map(String::from);
+  let res = transport.send(req).await?;
+  decode_response(res, request_correlation_id)
+}
+
+/// The blocking counterpart of `recv_json`.
+#[cfg(feature = "blocking")]
+pub(crate) fn recv_json_blocking<T: DeserializeOwned>(
+  transport: &dyn crate::blocking::BlockingTransport,
+  req: HttpRequest,
+) -> Result<T> {
+  let request_correlation_id = req.header(CORRELATION_ID).map(String::from);
+  let res = transport.send(req)?;
+  decode_response(res, request_correlation_id)
+}
+
+fn decode_response<T: DeserializeOwned>(
+  res: HttpResponse,
+  request_correlation_id: Option<String>,
+) -> Result<T> {
+  if (200..300).contains(&res.status) {
+    return Ok(serde_json::from_slice(&res.body)?);
+  }
+
+  let correlation_id = res
+    .header(CORRELATION_ID)
+    .map(String::from)
+    .or(request_correlation_id);
+  Err(error_from_response(res.status, correlation_id, &res.body))
+}
+
+pub(crate) fn error_from_response(
+  status: u16,
+  correlation_id: Option<String>,
+  body: &[u8],
+) -> MVolaError {
+  if status == 401 || status == 403 {
+    return MVolaError::Authentication {
+      status,
+      message: authentication_message(body).unwrap_or_else(|| String::from("unauthorized")),
+    };
+  }
+
+  MVolaError::Api(Box::new(ApiError {
+    status,
+    correlation_id,
+    error: serde_json::from_slice::<ErrorResponse>(body).ok(),
+    body: String::from_utf8_lossy(body).into_owned(),
+  }))
+}
+
+/// Extract a message from the OAuth2 (`error_description`), gateway (`fault`)
+/// or GSMA (`errorDescription`) error bodies.
+fn authentication_message(body: &[u8]) -> Option<String> {
+  let value: serde_json::Value = serde_json::from_slice(body).ok()?;
+  [
+    "/error_description",
+    "/errorDescription",
+    "/fault/description",
+    "/fault/message",
+    "/error",
+  ]
+  .iter()
+  .find_map(|pointer| value.pointer(pointer).and_then(|v| v.as_str()))
+  .map(String::from)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::{MVolaError, Result};
+  use crate::http::{HttpRequest, HttpResponse, HttpTransport, Method};
+  use crate::status::PaymentStatus;
+  use crate::types::Service;
+  use crate::MVola;
+  use async_trait::async_trait;
+  use std::sync::{Arc, Mutex};
+
+  /// Records the requests and answers with a canned response.
+  struct MockTransport {
+    requests: Mutex<Vec<HttpRequest>>,
+    status: u16,
+    body: &'static str,
+  }
+
+  #[async_trait]
+  impl HttpTransport for MockTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+      self.requests.lock().unwrap().push(request);
+      Ok(HttpResponse {
+        status: self.status,
+        headers: vec![(
+          String::from("content-type"),
+          String::from("application/json"),
+        )],
+        body: self.body.as_bytes().to_vec(),
+      })
+    }
+  }
+
+  #[tokio::test]
+  async fn test_custom_transport() {
+    let transport = Arc::new(MockTransport {
+      requests: Mutex::new(vec![]),
+      status: 200,
+      body: include_str!("../tests/fixtures/transaction_status.json"),
+    });
+    let client = MVola::builder()
+      .base_url("https://devapi.mvola.mg")
+      .user_account_identifier("msisdn;0343500003")
+      .transport(transport.clone())
+      .build()
+      .unwrap();
+    client.transaction.set_authorization("access token");
+
+    let response = client
+      .transaction
+      .get_transaction_status("2ba1d66a-25cf-4c12-8a6f-4cb01255148e")
+      .await
+      .unwrap();
+    assert_eq!(response.status, PaymentStatus::Completed);
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].method, Method::Get);
+    assert_eq!(
+      requests[0].url.as_str(),
+      "https://devapi.mvola.mg/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a-25cf-4c12-8a6f-4cb01255148e"
+    );
+    assert_eq!(
+      requests[0].header("authorization"),
+      Some("Bearer access token")
+    );
+    assert_eq!(
+      requests[0].header("UserAccountIdentifier"),
+      Some("msisdn;0343500003")
+    );
+  }
+
+  #[tokio::test]
+  async fn test_token_request() {
+    let transport = Arc::new(MockTransport {
+      requests: Mutex::new(vec![]),
+      status: 401,
+      body: r#"{"error_description":"Client Authentication failed.","error":"invalid_client"}"#,
+    });
+    let client = MVola::builder()
+      .transport(transport.clone())
+      .build()
+      .unwrap();
+
+    let err = client
+      .auth
+      .generate_token("consumer_key", "consumer_secret")
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      err,
+      MVolaError::Authentication { status: 401, .. }
+    ));
+
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests[0].method, Method::Post);
+    assert_eq!(requests[0].url.as_str(), "https://devapi.mvola.mg/token");
+    assert_eq!(
+      requests[0].header("Authorization"),
+      Some("Basic Y29uc3VtZXJfa2V5OmNvbnN1bWVyX3NlY3JldA==")
+    );
+    assert_eq!(
+      requests[0].header("Content-Type"),
+      Some("application/x-www-form-urlencoded")
+    );
+    assert_eq!(
+      requests[0].body.as_deref(),
+      Some(&b"grant_type=client_credentials&scope=EXT_INT_MVOLA_SCOPE"[..])
+    );
+  }
+}
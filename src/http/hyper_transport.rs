@@ -0,0 +1,146 @@
+use super::{HttpRequest, HttpResponse, HttpTransport, Method, TransportConfig};
+use crate::error::{MVolaError, Result};
+use async_trait::async_trait;
+use http_body_util::{BodyExt, Full};
+use hyper::body::Bytes;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::rt::TokioExecutor;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[cfg(feature = "hyper-rustls")]
+type Connector = hyper_rustls::HttpsConnector<HttpConnector>;
+#[cfg(all(feature = "hyper-native-tls", not(feature = "hyper-rustls")))]
+type Connector = hyper_tls::HttpsConnector<HttpConnector>;
+
+/// Transport backed by hyper, with the TLS stack of the `hyper-rustls` or
+/// `hyper-native-tls` feature. Proxies are not supported.
+#[derive(Clone)]
+pub struct HyperTransport {
+  client: Client<Connector, Full<Bytes>>,
+  timeout: Duration,
+  user_agent: Option<String>,
+}
+
+impl HyperTransport {
+  /// # Errors
+  /// * `MVolaError::Transport` - If a proxy is configured
+  pub fn new(config: &TransportConfig) -> Result<Self> {
+    if config.proxy.is_some() {
+      return Err(MVolaError::Transport(
+        "the hyper transport does not support proxies".into(),
+      ));
+    }
+
+    let mut http = HttpConnector::new();
+    http.enforce_http(false);
+    http.set_connect_timeout(config.connect_timeout);
+    let connector = connector(http);
+
+    Ok(Self {
+      client: Client::builder(TokioExecutor::new()).build(connector),
+      timeout: config.timeout.unwrap_or(DEFAULT_TIMEOUT),
+      user_agent: config.user_agent.clone(),
+    })
+  }
+
+  async fn send_request(&self, request: HttpRequest) -> Result<HttpResponse> {
+    let method = match request.method {
+      Method::Get => hyper::Method::GET,
+      Method::Post => hyper::Method::POST,
+      Method::Put => hyper::Method::PUT,
+    };
+    let mut req = hyper::Request::builder()
+      .method(method)
+      .uri(request.url.as_str());
+    if let Some(user_agent) = &self.user_agent {
+      req = req.header("User-Agent", user_agent.as_str());
+    }
+    for (name, value) in &request.headers {
+      req = req.header(name.as_str(), value.as_str());
+    }
+    let req = req
+      .body(Full::new(Bytes::from(request.body.unwrap_or_default())))
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+
+    let res = self
+      .client
+      .request(req)
+      .await
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+    let status = res.status().as_u16();
+    let headers = res
+      .headers()
+      .iter()
+      .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+      .collect();
+    let body = res
+      .into_body()
+      .collect()
+      .await
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?
+      .to_bytes()
+      .to_vec();
+    Ok(HttpResponse {
+      status,
+      headers,
+      body,
+    })
+  }
+}
+
+#[async_trait]
+impl HttpTransport for HyperTransport {
+  async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+    tokio::time::timeout(self.timeout, self.send_request(request))
+      .await
+      .map_err(|_| MVolaError::Timeout)?
+  }
+}
+
+#[cfg(feature = "hyper-rustls")]
+fn connector(http: HttpConnector) -> Connector {
+  hyper_rustls::HttpsConnectorBuilder::new()
+    .with_webpki_roots()
+    .https_or_http()
+    .enable_http1()
+    .wrap_connector(http)
+}
+
+#[cfg(all(feature = "hyper-native-tls", not(feature = "hyper-rustls")))]
+fn connector(http: HttpConnector) -> Connector {
+  hyper_tls::HttpsConnector::new_with_connector(http)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::http::{HttpRequest, HttpTransport, HyperTransport, Method, TransportConfig};
+  use mockito::{mock, SERVER_URL};
+
+  #[tokio::test]
+  async fn test_send() {
+    let _m = mock("POST", "/hyper/echo")
+      .match_header("X-CorrelationID", "hyper")
+      .match_header("Content-Type", "application/json")
+      .match_body(r#"{"amount":"1000"}"#)
+      .with_status(202)
+      .with_header("X-CorrelationID", "hyper")
+      .with_body(r#"{"status":"pending"}"#)
+      .create();
+
+    let transport = HyperTransport::new(&TransportConfig::default()).unwrap();
+    let url = format!("{}/hyper/echo", SERVER_URL).parse().unwrap();
+    let mut req = HttpRequest::new(Method::Post, url);
+    req.set_header("X-CorrelationID", "hyper");
+    req
+      .set_json(&serde_json::json!({ "amount": "1000" }))
+      .unwrap();
+
+    let res = transport.send(req).await.unwrap();
+    assert_eq!(res.status, 202);
+    assert_eq!(res.header("x-correlationid"), Some("hyper"));
+    assert_eq!(res.body, br#"{"status":"pending"}"#);
+  }
+}
@@ -0,0 +1,108 @@
+use super::{HttpRequest, HttpResponse, HttpTransport, Method, TransportConfig};
+use crate::error::{MVolaError, Result};
+use async_trait::async_trait;
+use reqwest::Client;
+use std::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transport backed by reqwest.
+#[derive(Clone)]
+pub struct ReqwestTransport {
+  client: Client,
+}
+
+impl ReqwestTransport {
+  /// # Errors
+  /// * `MVolaError::Transport` - If the proxy is not valid or the client cannot be built
+  pub fn new(config: &TransportConfig) -> Result<Self> {
+    let mut builder = Client::builder().timeout(config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    if let Some(connect_timeout) = config.connect_timeout {
+      builder = builder.connect_timeout(connect_timeout);
+    }
+    if let Some(proxy) = &config.proxy {
+      builder = builder.proxy(reqwest::Proxy::all(proxy.as_str()).map_err(transport_error)?);
+    }
+    if let Some(user_agent) = &config.user_agent {
+      builder = builder.user_agent(user_agent.as_str());
+    }
+    Ok(Self {
+      client: builder.build().map_err(transport_error)?,
+    })
+  }
+
+  /// Use an existing reqwest client.
+  pub fn from_client(client: Client) -> Self {
+    Self { client }
+  }
+}
+
+#[async_trait]
+impl HttpTransport for ReqwestTransport {
+  async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+    let method = match request.method {
+      Method::Get => reqwest::Method::GET,
+      Method::Post => reqwest::Method::POST,
+      Method::Put => reqwest::Method::PUT,
+    };
+    let mut req = self.client.request(method, request.url);
+    for (name, value) in &request.headers {
+      req = req.header(name.as_str(), value.as_str());
+    }
+    if let Some(body) = request.body {
+      req = req.body(body);
+    }
+
+    let res = req.send().await.map_err(transport_error)?;
+    let status = res.status().as_u16();
+    let headers = res
+      .headers()
+      .iter()
+      .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+      .collect();
+    let body = res.bytes().await.map_err(transport_error)?.to_vec();
+    Ok(HttpResponse {
+      status,
+      headers,
+      body,
+    })
+  }
+}
+
+fn transport_error(err: reqwest::Error) -> MVolaError {
+  if err.is_timeout() {
+    return MVolaError::Timeout;
+  }
+  MVolaError::Transport(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::http::{HttpRequest, HttpTransport, Method, ReqwestTransport, TransportConfig};
+  use mockito::{mock, SERVER_URL};
+
+  #[tokio::test]
+  async fn test_send() {
+    let _m = mock("POST", "/reqwest/echo")
+      .match_header("X-CorrelationID", "reqwest")
+      .match_header("Content-Type", "application/json")
+      .match_body(r#"{"amount":"1000"}"#)
+      .with_status(202)
+      .with_header("X-CorrelationID", "reqwest")
+      .with_body(r#"{"status":"pending"}"#)
+      .create();
+
+    let transport = ReqwestTransport::new(&TransportConfig::default()).unwrap();
+    let url = format!("{}/reqwest/echo", SERVER_URL).parse().unwrap();
+    let mut req = HttpRequest::new(Method::Post, url);
+    req.set_header("X-CorrelationID", "reqwest");
+    req
+      .set_json(&serde_json::json!({ "amount": "1000" }))
+      .unwrap();
+
+    let res = transport.send(req).await.unwrap();
+    assert_eq!(res.status, 202);
+    assert_eq!(res.header("x-correlationid"), Some("reqwest"));
+    assert_eq!(res.body, br#"{"status":"pending"}"#);
+  }
+}
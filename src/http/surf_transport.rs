@@ -0,0 +1,128 @@
+use super::{HttpRequest, HttpResponse, HttpTransport, Method, TransportConfig};
+use crate::error::{MVolaError, Result};
+use async_trait::async_trait;
+use http_client::isahc::IsahcClient;
+use isahc::config::Configurable;
+use isahc::http::Uri;
+use std::time::Duration;
+use surf::{Client, Config};
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Transport backed by surf and its curl client.
+#[derive(Clone)]
+pub struct SurfTransport {
+  client: Client,
+}
+
+impl SurfTransport {
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If the proxy is not a valid URL
+  pub fn new(config: &TransportConfig) -> Result<Self> {
+    let mut builder =
+      isahc::HttpClient::builder().timeout(config.timeout.unwrap_or(DEFAULT_TIMEOUT));
+    if let Some(connect_timeout) = config.connect_timeout {
+      builder = builder.connect_timeout(connect_timeout);
+    }
+    if let Some(proxy) = &config.proxy {
+      let proxy: Uri = url::Url::parse(proxy)?
+        .as_str()
+        .parse()
+        .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+      builder = builder.proxy(proxy);
+    }
+    if let Some(user_agent) = &config.user_agent {
+      builder = builder.default_header("User-Agent", user_agent.as_str());
+    }
+    let http_client = builder
+      .build()
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+
+    let client = Config::new()
+      .set_http_client(IsahcClient::from_client(http_client))
+      .try_into()
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+    Ok(Self { client })
+  }
+
+  /// Use an existing surf client.
+  pub fn from_client(client: Client) -> Self {
+    Self { client }
+  }
+}
+
+#[async_trait]
+impl HttpTransport for SurfTransport {
+  async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+    let method = match request.method {
+      Method::Get => surf::http::Method::Get,
+      Method::Post => surf::http::Method::Post,
+      Method::Put => surf::http::Method::Put,
+    };
+    let mut req = surf::Request::new(method, request.url);
+    for (name, value) in &request.headers {
+      req.set_header(name.as_str(), value.as_str());
+    }
+    if let Some(body) = request.body {
+      let content_type = req.content_type();
+      req.set_body(body);
+      if let Some(content_type) = content_type {
+        req.set_content_type(content_type);
+      }
+    }
+
+    let mut res = self.client.send(req).await.map_err(transport_error)?;
+    let body = res.body_bytes().await.map_err(transport_error)?;
+    let headers = res
+      .iter()
+      .flat_map(|(name, values)| {
+        values
+          .iter()
+          .map(move |value| (name.to_string(), value.to_string()))
+      })
+      .collect();
+    Ok(HttpResponse {
+      status: res.status().into(),
+      headers,
+      body,
+    })
+  }
+}
+
+fn transport_error(err: surf::Error) -> MVolaError {
+  if let Some(isahc::Error::Timeout) = err.downcast_ref::<isahc::Error>() {
+    return MVolaError::Timeout;
+  }
+  MVolaError::Transport(err.into())
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::http::{HttpRequest, HttpTransport, Method, SurfTransport, TransportConfig};
+  use mockito::{mock, SERVER_URL};
+
+  #[tokio::test]
+  async fn test_send() {
+    let _m = mock("POST", "/surf/echo")
+      .match_header("X-CorrelationID", "surf")
+      .match_header("Content-Type", "application/json")
+      .match_body(r#"{"amount":"1000"}"#)
+      .with_status(202)
+      .with_header("X-CorrelationID", "surf")
+      .with_body(r#"{"status":"pending"}"#)
+      .create();
+
+    let transport = SurfTransport::new(&TransportConfig::default()).unwrap();
+    let url = format!("{}/surf/echo", SERVER_URL).parse().unwrap();
+    let mut req = HttpRequest::new(Method::Post, url);
+    req.set_header("X-CorrelationID", "surf");
+    req
+      .set_json(&serde_json::json!({ "amount": "1000" }))
+      .unwrap();
+
+    let res = transport.send(req).await.unwrap();
+    assert_eq!(res.status, 202);
+    assert_eq!(res.header("x-correlationid"), Some("surf"));
+    assert_eq!(res.body, br#"{"status":"pending"}"#);
+  }
+}
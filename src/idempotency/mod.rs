@@ -0,0 +1,189 @@
+//! Idempotent payments.
+//!
+//! When a payment request times out, there is no telling whether MVola
+//! executed it, and sending it again may charge the customer twice. With an
+//! `IdempotencyStore`, the transaction service records each attempt under its
+//! idempotency key before sending it:
+//!
+//! * a new key is recorded as `InFlight`, then `Accepted` with the server
+//!   correlation id of the response, or removed if MVola rejected the request;
+//! * an `Accepted` key is not sent again: its status is requested instead;
+//! * an `InFlight` key is either being sent, or its outcome is unknown, and
+//!   `MVolaError::IndeterminatePayment` is returned until the application
+//!   settles it with `IdempotencyStore::resolve`.
+//!
+//! A failure of the store after MVola answered does not hide the answer: it
+//! is logged, and the key is left to be resolved.
+//!
+//! The key defaults to the `requestingOrganisationTransactionReference` of
+//! the request, and can be set with `RequestContext::idempotency_key`.
+//!
+//! ```no_run
+//! let client = MVola::builder()
+//!   .idempotency_store(Arc::new(InMemoryIdempotencyStore::new()))
+//!   .build()?;
+//! ```
+
+#[cfg(feature = "sqlite")]
+mod sqlite;
+
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteIdempotencyStore;
+
+use crate::error::{MVolaError, Result};
+use crate::refund::is_rejected;
+use crate::types::TransactionResponse;
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+
+/// An attempt recorded in an `IdempotencyStore`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attempt {
+  /// The payment is being sent, or was sent without an answer.
+  InFlight,
+  /// MVola accepted the payment.
+  Accepted { server_correlation_id: String },
+}
+
+/// Records the payment attempts by idempotency key.
+///
+/// The methods are called around each payment request, from async and
+/// blocking clients alike.
+pub trait IdempotencyStore: Send + Sync {
+  /// Record a new attempt as `InFlight`, unless the key is already recorded.
+  ///
+  /// Checking and recording must be atomic, so that two clients sending the
+  /// same key at once do not both send the payment.
+  /// # Returns
+  /// * `None` - If the attempt was recorded and the payment can be sent
+  /// * `Some(Attempt)` - The earlier attempt, if the key is already recorded
+  fn begin(&self, key: &str) -> Result<Option<Attempt>>;
+
+  /// Record that MVola accepted the attempt.
+  fn accept(&self, key: &str, server_correlation_id: &str) -> Result<()>;
+
+  /// Forget an attempt, so that the payment can be sent again.
+  ///
+  /// Called when MVola rejected the request.
+  fn remove(&self, key: &str) -> Result<()>;
+
+  /// Settle an attempt whose outcome was unknown, after
+  /// `MVolaError::IndeterminatePayment`.
+  ///
+  /// MVola cannot be asked for a payment by its reference, so the application
+  /// settles it from the callback or the merchant statement:
+  /// * `Some(server_correlation_id)` - MVola received the payment, which is
+  ///   accepted: sending it again returns its status
+  /// * `None` - MVola did not execute the payment, which is forgotten: it is
+  ///   sent again on the next attempt
+  fn resolve(&self, key: &str, server_correlation_id: Option<&str>) -> Result<()> {
+    match server_correlation_id {
+      Some(server_correlation_id) => self.accept(key, server_correlation_id),
+      None => self.remove(key),
+    }
+  }
+}
+
+/// An `IdempotencyStore` kept in memory, shared by the clones of a client.
+///
+/// The attempts are lost when the process stops, use a persistent store such
+/// as `SqliteIdempotencyStore` to keep them across restarts.
+#[derive(Debug, Default)]
+pub struct InMemoryIdempotencyStore {
+  attempts: Mutex<HashMap<String, Attempt>>,
+}
+
+impl InMemoryIdempotencyStore {
+  pub fn new() -> Self {
+    Self::default()
+  }
+}
+
+impl IdempotencyStore for InMemoryIdempotencyStore {
+  fn begin(&self, key: &str) -> Result<Option<Attempt>> {
+    let mut attempts = self.attempts.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(attempt) = attempts.get(key) {
+      return Ok(Some(attempt.clone()));
+    }
+    attempts.insert(String::from(key), Attempt::InFlight);
+    Ok(None)
+  }
+
+  fn accept(&self, key: &str, server_correlation_id: &str) -> Result<()> {
+    let mut attempts = self.attempts.lock().unwrap_or_else(PoisonError::into_inner);
+    attempts.insert(
+      String::from(key),
+      Attempt::Accepted {
+        server_correlation_id: String::from(server_correlation_id),
+      },
+    );
+    Ok(())
+  }
+
+  fn remove(&self, key: &str) -> Result<()> {
+    let mut attempts = self.attempts.lock().unwrap_or_else(PoisonError::into_inner);
+    attempts.remove(key);
+    Ok(())
+  }
+}
+
+/// Record a payment attempt before it is sent.
+/// # Returns
+/// * `None` - If the payment can be sent
+/// * `Some(String)` - The server correlation id of the payment, if MVola already accepted it
+/// # Errors
+/// * `MVolaError::IndeterminatePayment` - If the same key is being sent, or was sent without
+///   an answer
+pub(crate) fn begin_payment(store: &dyn IdempotencyStore, key: &str) -> Result<Option<String>> {
+  match store.begin(key)? {
+    None => Ok(None),
+    Some(Attempt::Accepted {
+      server_correlation_id,
+    }) => Ok(Some(server_correlation_id)),
+    Some(Attempt::InFlight) => Err(MVolaError::IndeterminatePayment {
+      idempotency_key: String::from(key),
+    }),
+  }
+}
+
+/// Record the outcome of a payment attempt.
+///
+/// The outcome is what the caller gets: a store failure is only logged, and
+/// leaves the key `InFlight`.
+pub(crate) fn finish_payment(
+  store: &dyn IdempotencyStore,
+  key: &str,
+  res: &Result<TransactionResponse>,
+) {
+  let recorded = match res {
+    Ok(response) => store.accept(key, &response.server_correlation_id),
+    Err(err) if is_rejected(err) => store.remove(key),
+    Err(_) => Ok(()),
+  };
+  if let Err(err) = recorded {
+    log::warn!("could not record the outcome of payment {}: {}", key, err);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::idempotency::{Attempt, IdempotencyStore, InMemoryIdempotencyStore};
+
+  #[test]
+  fn test_in_memory_store() {
+    let store = InMemoryIdempotencyStore::new();
+    assert_eq!(store.begin("order-42").unwrap(), None);
+    assert_eq!(store.begin("order-42").unwrap(), Some(Attempt::InFlight));
+
+    store.accept("order-42", "2ba1d66a").unwrap();
+    assert_eq!(
+      store.begin("order-42").unwrap(),
+      Some(Attempt::Accepted {
+        server_correlation_id: String::from("2ba1d66a")
+      })
+    );
+
+    store.remove("order-42").unwrap();
+    assert_eq!(store.begin("order-42").unwrap(), None);
+  }
+}
@@ -0,0 +1,142 @@
+use crate::error::{MVolaError, Result};
+use crate::idempotency::{Attempt, IdempotencyStore};
+use chrono::{SecondsFormat, Utc};
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+use std::sync::{Mutex, PoisonError};
+
+/// An `IdempotencyStore` kept in a SQLite database, in the
+/// `mvola_idempotency` table.
+///
+/// The table is created if missing. Several processes can share the same
+/// database file.
+pub struct SqliteIdempotencyStore {
+  connection: Mutex<Connection>,
+}
+
+impl SqliteIdempotencyStore {
+  /// Open or create the database file.
+  /// # Errors
+  /// * `MVolaError::IdempotencyStore` - If the database cannot be opened
+  pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
+    Self::from_connection(Connection::open(path).map_err(store_error)?)
+  }
+
+  /// A database kept in memory, e.g. for tests.
+  /// # Errors
+  /// * `MVolaError::IdempotencyStore` - If the database cannot be created
+  pub fn open_in_memory() -> Result<Self> {
+    Self::from_connection(Connection::open_in_memory().map_err(store_error)?)
+  }
+
+  /// Use an open connection, e.g. to the database of the application.
+  /// # Errors
+  /// * `MVolaError::IdempotencyStore` - If the table cannot be created
+  pub fn from_connection(connection: Connection) -> Result<Self> {
+    connection
+      .execute(
+        "CREATE TABLE IF NOT EXISTS mvola_idempotency (
+           key TEXT PRIMARY KEY NOT NULL,
+           server_correlation_id TEXT,
+           created_at TEXT NOT NULL
+         )",
+        [],
+      )
+      .map_err(store_error)?;
+    Ok(Self {
+      connection: Mutex::new(connection),
+    })
+  }
+}
+
+impl IdempotencyStore for SqliteIdempotencyStore {
+  fn begin(&self, key: &str) -> Result<Option<Attempt>> {
+    let connection = self
+      .connection
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    let created_at = Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true);
+    let inserted = connection
+      .execute(
+        "INSERT INTO mvola_idempotency (key, created_at) VALUES (?1, ?2)
+         ON CONFLICT (key) DO NOTHING",
+        params![key, created_at],
+      )
+      .map_err(store_error)?;
+    if inserted == 1 {
+      return Ok(None);
+    }
+
+    let server_correlation_id: Option<Option<String>> = connection
+      .query_row(
+        "SELECT server_correlation_id FROM mvola_idempotency WHERE key = ?1",
+        params![key],
+        |row| row.get(0),
+      )
+      .optional()
+      .map_err(store_error)?;
+    Ok(Some(match server_correlation_id.flatten() {
+      Some(server_correlation_id) => Attempt::Accepted {
+        server_correlation_id,
+      },
+      None => Attempt::InFlight,
+    }))
+  }
+
+  fn accept(&self, key: &str, server_correlation_id: &str) -> Result<()> {
+    let connection = self
+      .connection
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    connection
+      .execute(
+        "UPDATE mvola_idempotency SET server_correlation_id = ?2 WHERE key = ?1",
+        params![key, server_correlation_id],
+      )
+      .map_err(store_error)?;
+    Ok(())
+  }
+
+  fn remove(&self, key: &str) -> Result<()> {
+    let connection = self
+      .connection
+      .lock()
+      .unwrap_or_else(PoisonError::into_inner);
+    connection
+      .execute("DELETE FROM mvola_idempotency WHERE key = ?1", params![key])
+      .map_err(store_error)?;
+    Ok(())
+  }
+}
+
+fn store_error(err: rusqlite::Error) -> MVolaError {
+  MVolaError::IdempotencyStore(Box::new(err))
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::idempotency::{Attempt, IdempotencyStore, SqliteIdempotencyStore};
+
+  #[test]
+  fn test_sqlite_store() {
+    let path = std::env::temp_dir().join(format!("mvola-{}.sqlite", uuid::Uuid::new_v4()));
+    let store = SqliteIdempotencyStore::open(&path).unwrap();
+    assert_eq!(store.begin("order-42").unwrap(), None);
+    assert_eq!(store.begin("order-42").unwrap(), Some(Attempt::InFlight));
+    store.accept("order-42", "2ba1d66a").unwrap();
+    drop(store);
+
+    // The attempts outlive the process that recorded them.
+    let store = SqliteIdempotencyStore::open(&path).unwrap();
+    assert_eq!(
+      store.begin("order-42").unwrap(),
+      Some(Attempt::Accepted {
+        server_correlation_id: String::from("2ba1d66a")
+      })
+    );
+    store.remove("order-42").unwrap();
+    assert_eq!(store.begin("order-42").unwrap(), None);
+    drop(store);
+    std::fs::remove_file(&path).unwrap();
+  }
+}
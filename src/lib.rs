@@ -1,20 +1,103 @@
 pub mod auth;
+#[cfg(feature = "batch")]
+pub mod batch;
+#[cfg(feature = "blocking")]
+pub mod blocking;
+pub mod builder;
+pub mod callback;
+pub mod context;
+pub mod error;
+pub mod http;
+pub mod idempotency;
+pub mod money;
+pub mod msisdn;
+pub mod party;
+pub mod poll;
+pub mod refund;
+pub mod routes;
+#[cfg(feature = "server")]
+pub mod server;
+mod service;
+pub mod status;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod token;
 pub mod transaction;
 pub mod types;
 
+pub use builder::MVolaBuilder;
+pub use context::RequestContext;
+pub use error::{ApiError, MVolaError, Result};
+pub use idempotency::{IdempotencyStore, InMemoryIdempotencyStore};
+pub use money::Money;
+pub use msisdn::Msisdn;
+pub use party::{Party, TransactionMetadata};
+pub use poll::{Completion, PollPolicy};
+pub use refund::RefundLedger;
+pub use routes::{ApiVersion, TransactionType};
+pub use status::{NotificationMethod, PaymentStatus};
+
+use std::sync::Arc;
+use token::TokenManager;
+
 pub const SANDBOX_URL: &str = "https://devapi.mvola.mg";
 pub const PRODUCTION_URL: &str = "https://api.mvola.mg";
 
+/// The MVola API environment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Environment {
+    Sandbox,
+    Production,
+}
+
+impl Environment {
+    pub fn base_url(&self) -> &'static str {
+        match self {
+            Environment::Sandbox => SANDBOX_URL,
+            Environment::Production => PRODUCTION_URL,
+        }
+    }
+}
+
+/// MVola client.
+///
+/// Cheap to clone: the clones share the same connection pool, settings and
+/// token, so one client can be shared by every task of a server.
+#[derive(Clone)]
 pub struct MVola {
     pub transaction: transaction::TransactionService,
     pub auth: auth::AuthService,
+    token_manager: Option<Arc<TokenManager>>,
 }
 
 impl MVola {
-    pub fn new(base_url: &str) -> Self {
-        Self {
-            auth: auth::AuthService::new(base_url),
-            transaction: transaction::TransactionService::new(base_url),
-        }
+    /// # Errors
+    /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+    pub fn new(base_url: &str) -> Result<Self> {
+        Self::builder().base_url(base_url).build()
+    }
+
+    /// Create a client that generates its access token from the consumer key
+    /// and consumer secret, and refreshes it before it expires.
+    ///
+    /// There is no need to call `set_authorization` on the transaction service.
+    pub fn with_credentials(
+        base_url: &str,
+        consumer_key: &str,
+        consumer_secret: &str,
+    ) -> Result<Self> {
+        Self::builder()
+            .base_url(base_url)
+            .credentials(consumer_key, consumer_secret)
+            .build()
+    }
+
+    pub fn builder() -> MVolaBuilder {
+        MVolaBuilder::new()
+    }
+
+    /// The token manager of a client built with credentials.
+    pub fn token_manager(&self) -> Option<&TokenManager> {
+        self.token_manager.as_deref()
     }
 }
@@ -0,0 +1,295 @@
+use crate::error::{MVolaError, Result};
+use serde::de::{self, Visitor};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Sub};
+use std::str::FromStr;
+
+/// An amount of money as sent and received by MVola, e.g. "1000" or "10000.00".
+///
+/// The amount is stored in hundredths so that it is parsed and formatted
+/// exactly, without floats. The operators panic on overflow, in release
+/// builds too: use `checked_add` and `checked_sub` to handle it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Money {
+  hundredths: i64,
+}
+
+impl Money {
+  pub const ZERO: Money = Money { hundredths: 0 };
+
+  /// A whole amount, e.g. `Money::ariary(1000)` for 1000 Ar.
+  /// # Panics
+  /// If the amount does not fit in hundredths, see `checked_ariary`.
+  pub const fn ariary(amount: i64) -> Self {
+    match Self::checked_ariary(amount) {
+      Some(money) => money,
+      None => panic!("amount out of range"),
+    }
+  }
+
+  /// A whole amount, or `None` if it does not fit in hundredths.
+  pub const fn checked_ariary(amount: i64) -> Option<Self> {
+    match amount.checked_mul(100) {
+      Some(hundredths) => Some(Self { hundredths }),
+      None => None,
+    }
+  }
+
+  /// An amount in hundredths, e.g. `Money::from_hundredths(1050)` for "10.50".
+  pub const fn from_hundredths(hundredths: i64) -> Self {
+    Self { hundredths }
+  }
+
+  pub const fn hundredths(&self) -> i64 {
+    self.hundredths
+  }
+
+  pub const fn is_whole(&self) -> bool {
+    self.hundredths % 100 == 0
+  }
+
+  pub const fn is_positive(&self) -> bool {
+    self.hundredths > 0
+  }
+
+  pub fn checked_add(self, other: Money) -> Option<Money> {
+    self
+      .hundredths
+      .checked_add(other.hundredths)
+      .map(Money::from_hundredths)
+  }
+
+  pub fn checked_sub(self, other: Money) -> Option<Money> {
+    self
+      .hundredths
+      .checked_sub(other.hundredths)
+      .map(Money::from_hundredths)
+  }
+
+  /// Check that the amount can be sent in a transaction: MVola only accepts
+  /// positive, whole Ariary amounts.
+  /// # Errors
+  /// * `MVolaError::InvalidAmount` - If the amount is not positive or not whole
+  pub fn validate_payment(&self) -> Result<()> {
+    if !self.is_positive() {
+      return Err(MVolaError::InvalidAmount(format!(
+        "{} is not a positive amount",
+        self
+      )));
+    }
+    if !self.is_whole() {
+      return Err(MVolaError::InvalidAmount(format!(
+        "{} is not a whole amount of Ariary",
+        self
+      )));
+    }
+    Ok(())
+  }
+}
+
+impl FromStr for Money {
+  type Err = MVolaError;
+
+  fn from_str(s: &str) -> Result<Self> {
+    let invalid = || MVolaError::InvalidAmount(format!("\"{}\" is not a valid amount", s));
+    // A negative amount, as formatted by `Display`.
+    let (negative, unsigned) = match s.strip_prefix('-') {
+      Some(unsigned) => (true, unsigned),
+      None => (false, s),
+    };
+    let (whole, fraction) = match unsigned.split_once('.') {
+      Some((whole, fraction)) => (whole, fraction),
+      None => (unsigned, ""),
+    };
+    let digits = |part: &str| part.bytes().all(|b| b.is_ascii_digit());
+    if whole.is_empty() || !digits(whole) || !digits(fraction) || fraction.len() > 2 {
+      return Err(invalid());
+    }
+    if s.ends_with('.') {
+      return Err(invalid());
+    }
+
+    let whole: i64 = whole.parse().map_err(|_| invalid())?;
+    let fraction: i64 = match fraction.len() {
+      0 => 0,
+      1 => fraction.parse::<i64>().map_err(|_| invalid())? * 10,
+      _ => fraction.parse().map_err(|_| invalid())?,
+    };
+    // Built with the sign, so that the most negative amount does not overflow.
+    let (whole, fraction) = if negative {
+      (-whole, -fraction)
+    } else {
+      (whole, fraction)
+    };
+    whole
+      .checked_mul(100)
+      .and_then(|w| w.checked_add(fraction))
+      .map(Money::from_hundredths)
+      .ok_or_else(invalid)
+  }
+}
+
+impl fmt::Display for Money {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    let sign = if self.hundredths < 0 { "-" } else { "" };
+    let abs = self.hundredths.unsigned_abs();
+    match abs % 100 {
+      0 => write!(f, "{}{}", sign, abs / 100),
+      fraction => write!(f, "{}{}.{:02}", sign, abs / 100, fraction),
+    }
+  }
+}
+
+impl Add for Money {
+  type Output = Money;
+
+  fn add(self, other: Money) -> Money {
+    self.checked_add(other).expect("amount out of range")
+  }
+}
+
+impl AddAssign for Money {
+  fn add_assign(&mut self, other: Money) {
+    *self = *self + other;
+  }
+}
+
+impl Sub for Money {
+  type Output = Money;
+
+  fn sub(self, other: Money) -> Money {
+    self.checked_sub(other).expect("amount out of range")
+  }
+}
+
+impl Sum for Money {
+  fn sum<I: Iterator<Item = Money>>(iter: I) -> Money {
+    iter.fold(Money::ZERO, Add::add)
+  }
+}
+
+impl Serialize for Money {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Money {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    struct MoneyVisitor;
+
+    impl<'de> Visitor<'de> for MoneyVisitor {
+      type Value = Money;
+
+      fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an amount such as \"1000\" or \"10000.00\"")
+      }
+
+      fn visit_str<E: de::Error>(self, v: &str) -> std::result::Result<Money, E> {
+        v.parse().map_err(E::custom)
+      }
+
+      fn visit_u64<E: de::Error>(self, v: u64) -> std::result::Result<Money, E> {
+        i64::try_from(v)
+          .ok()
+          .and_then(|v| v.checked_mul(100))
+          .map(Money::from_hundredths)
+          .ok_or_else(|| E::custom("amount out of range"))
+      }
+    }
+
+    deserializer.deserialize_any(MoneyVisitor)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::money::Money;
+
+  #[test]
+  fn test_parse() {
+    assert_eq!("1000".parse::<Money>().unwrap(), Money::ariary(1000));
+    assert_eq!("10000.00".parse::<Money>().unwrap(), Money::ariary(10000));
+    assert_eq!(
+      "10.5".parse::<Money>().unwrap(),
+      Money::from_hundredths(1050)
+    );
+    assert_eq!("0.05".parse::<Money>().unwrap(), Money::from_hundredths(5));
+    assert_eq!("-10".parse::<Money>().unwrap(), Money::ariary(-10));
+    for invalid in [
+      "", "-", "--10", "+10", "1,000", "10.", ".5", "10.001", "1e3", "abc",
+    ] {
+      assert!(invalid.parse::<Money>().is_err(), "{}", invalid);
+    }
+  }
+
+  #[test]
+  fn test_display() {
+    assert_eq!(Money::ariary(1000).to_string(), "1000");
+    assert_eq!(Money::from_hundredths(1050).to_string(), "10.50");
+    assert_eq!(Money::from_hundredths(-5).to_string(), "-0.05");
+  }
+
+  #[test]
+  fn test_round_trip() {
+    for hundredths in [0, 5, -5, 1050, -1050, -100_000, i64::MAX, i64::MIN] {
+      let money = Money::from_hundredths(hundredths);
+      assert_eq!(money.to_string().parse::<Money>().unwrap(), money);
+    }
+  }
+
+  #[test]
+  fn test_arithmetic() {
+    let fees = [Money::ariary(150), Money::from_hundredths(25)];
+    assert_eq!(
+      fees.iter().copied().sum::<Money>(),
+      Money::from_hundredths(15025)
+    );
+    assert_eq!(
+      Money::ariary(1000) + Money::ariary(150),
+      Money::ariary(1150)
+    );
+    assert!(Money::ariary(1000) > Money::from_hundredths(99999));
+    assert_eq!(
+      Money::ariary(i64::MAX / 100).checked_add(Money::ariary(1)),
+      None
+    );
+    assert_eq!(
+      Money::ariary(i64::MIN / 100).checked_sub(Money::ariary(1)),
+      None
+    );
+    assert_eq!(Money::checked_ariary(i64::MAX), None);
+  }
+
+  #[test]
+  #[should_panic(expected = "amount out of range")]
+  fn test_overflow_panics() {
+    let _ = Money::from_hundredths(i64::MAX) + Money::from_hundredths(1);
+  }
+
+  #[test]
+  fn test_validate_payment() {
+    assert!(Money::ariary(1000).validate_payment().is_ok());
+    assert!(Money::ZERO.validate_payment().is_err());
+    assert!(Money::from_hundredths(1050).validate_payment().is_err());
+  }
+
+  #[test]
+  fn test_serde() {
+    assert_eq!(
+      serde_json::to_string(&Money::ariary(1000)).unwrap(),
+      r#""1000""#
+    );
+    assert_eq!(
+      serde_json::from_str::<Money>(r#""10000.00""#).unwrap(),
+      Money::ariary(10000)
+    );
+    assert_eq!(
+      serde_json::from_str::<Money>("150").unwrap(),
+      Money::ariary(150)
+    );
+    assert!(serde_json::from_str::<Money>("1.5").is_err());
+  }
+}
@@ -0,0 +1,159 @@
+use crate::error::{MVolaError, Result};
+use crate::party::Party;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+/// The prefixes of the MVola (Telma) numbers.
+const PREFIXES: [&str; 2] = ["034", "038"];
+
+/// A MVola phone number, e.g. "0343500003".
+///
+/// It accepts the local ("034 35 000 03"), "+261" and "261" forms, and is
+/// always stored in the local form expected by the API.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Msisdn(String);
+
+impl Msisdn {
+  /// Parse and normalize a MVola number.
+  /// # Arguments
+  /// * `number` - The number in local, "+261" or "261" form. Spaces, dots and
+  ///   dashes are ignored.
+  /// # Errors
+  /// * `MVolaError::InvalidMsisdn` - If the number is malformed or is not a MVola number
+  pub fn new(number: &str) -> Result<Self> {
+    let invalid = |reason: &str| MVolaError::InvalidMsisdn(format!("\"{}\" {}", number, reason));
+    let digits: String = number
+      .chars()
+      .filter(|c| !matches!(c, ' ' | '.' | '-'))
+      .collect();
+
+    let local = if let Some(national) = digits.strip_prefix("+261") {
+      format!("0{}", national)
+    } else if digits.len() == 12 && digits.starts_with("261") {
+      format!("0{}", &digits[3..])
+    } else {
+      digits
+    };
+
+    if local.len() != 10 || !local.starts_with('0') || !local.bytes().all(|b| b.is_ascii_digit()) {
+      return Err(invalid("is not a Malagasy phone number"));
+    }
+    if !PREFIXES.iter().any(|prefix| local.starts_with(prefix)) {
+      return Err(invalid("is not a MVola number (034 or 038)"));
+    }
+    Ok(Self(local))
+  }
+
+  /// The local form, e.g. "0343500003".
+  pub fn as_str(&self) -> &str {
+    &self.0
+  }
+
+  /// The international form, e.g. "+261343500003".
+  pub fn to_international(&self) -> String {
+    format!("+261{}", &self.0[1..])
+  }
+
+  /// The debit or credit party of this number.
+  pub fn to_party(&self) -> Party {
+    Party::from(self.clone())
+  }
+
+  /// The value of the `UserAccountIdentifier` header, e.g. "msisdn;0343500003".
+  pub fn account_identifier(&self) -> String {
+    format!("msisdn;{}", self.0)
+  }
+}
+
+impl FromStr for Msisdn {
+  type Err = MVolaError;
+
+  fn from_str(s: &str) -> Result<Self> {
+    Self::new(s)
+  }
+}
+
+impl fmt::Display for Msisdn {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl Serialize for Msisdn {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(&self.0)
+  }
+}
+
+impl<'de> Deserialize<'de> for Msisdn {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    let number = String::deserialize(deserializer)?;
+    Msisdn::new(&number).map_err(serde::de::Error::custom)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::MVolaError;
+  use crate::msisdn::Msisdn;
+
+  #[test]
+  fn test_normalize() {
+    for number in [
+      "0343500003",
+      "034 35 000 03",
+      "+261343500003",
+      "261343500003",
+      "+261 34 35 000 03",
+    ] {
+      assert_eq!(
+        Msisdn::new(number).unwrap().as_str(),
+        "0343500003",
+        "{}",
+        number
+      );
+    }
+    assert_eq!(Msisdn::new("0381234567").unwrap().as_str(), "0381234567");
+  }
+
+  #[test]
+  fn test_invalid() {
+    for number in [
+      "",
+      "034350000",
+      "03435000031",
+      "034350000a",
+      "343500003",
+      "+33343500003",
+    ] {
+      assert!(
+        matches!(Msisdn::new(number), Err(MVolaError::InvalidMsisdn(_))),
+        "{}",
+        number
+      );
+    }
+    // Orange and Airtel numbers
+    assert!(Msisdn::new("0321234567").is_err());
+    assert!(Msisdn::new("+261331234567").is_err());
+  }
+
+  #[test]
+  fn test_format() {
+    let msisdn: Msisdn = "+261343500003".parse().unwrap();
+    assert_eq!(msisdn.to_string(), "0343500003");
+    assert_eq!(msisdn.to_international(), "+261343500003");
+    assert_eq!(msisdn.account_identifier(), "msisdn;0343500003");
+
+    let party = msisdn.to_party();
+    assert_eq!(party.msisdn.as_deref(), Some("0343500003"));
+    assert!(party.extras.is_empty());
+  }
+
+  #[test]
+  fn test_serde() {
+    let msisdn: Msisdn = serde_json::from_str(r#""261343500003""#).unwrap();
+    assert_eq!(serde_json::to_string(&msisdn).unwrap(), r#""0343500003""#);
+    assert!(serde_json::from_str::<Msisdn>(r#""0321234567""#).is_err());
+  }
+}
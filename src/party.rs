@@ -0,0 +1,350 @@
+//! The parties and the metadata of a transaction.
+//!
+//! The API sends them as lists of key/value pairs:
+//!
+//! ```json
+//! "debitParty": [{ "key": "msisdn", "value": "0343500003" }],
+//! "metadata": [{ "key": "partnerName", "value": "TestMVola" }]
+//! ```
+//!
+//! `Party` and `TransactionMetadata` give the known keys a named field, keep
+//! the other pairs and remember the order of all of them, so that they
+//! serialize back to the same list. A key repeated in a list keeps its last
+//! value.
+
+use crate::msisdn::Msisdn;
+use crate::types::KeyValue;
+use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+/// The debit or credit party of a transaction.
+///
+/// Serialized as a list of key/value pairs.
+///
+/// Two parties are equal if they have the same entries, in any order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "Vec<KeyValue>", into = "Vec<KeyValue>")]
+pub struct Party {
+  /// The `msisdn` entry, e.g. "0343500003".
+  pub msisdn: Option<String>,
+  /// The other entries.
+  pub extras: IndexMap<String, String>,
+  /// The keys in the order they were received, the entries set since are
+  /// sent after them.
+  order: Vec<String>,
+}
+
+impl Party {
+  /// The `msisdn` entry, if it is a valid MVola number.
+  pub fn to_msisdn(&self) -> Option<Msisdn> {
+    self
+      .msisdn
+      .as_deref()
+      .and_then(|msisdn| Msisdn::new(msisdn).ok())
+  }
+
+  /// The value of an entry, known or not.
+  pub fn get(&self, key: &str) -> Option<&str> {
+    match key {
+      "msisdn" => self.msisdn.as_deref(),
+      key => self.extras.get(key).map(String::as_str),
+    }
+  }
+
+  /// The entries as sent to the API.
+  pub fn to_key_values(&self) -> Vec<KeyValue> {
+    let known = [("msisdn", &self.msisdn)];
+    key_values(&self.order, &known, &self.extras)
+  }
+}
+
+impl PartialEq for Party {
+  fn eq(&self, other: &Self) -> bool {
+    self.msisdn == other.msisdn && self.extras == other.extras
+  }
+}
+
+impl Eq for Party {}
+
+impl From<Msisdn> for Party {
+  fn from(msisdn: Msisdn) -> Self {
+    Self {
+      msisdn: Some(String::from(msisdn.as_str())),
+      ..Self::default()
+    }
+  }
+}
+
+impl From<Vec<KeyValue>> for Party {
+  fn from(key_values: Vec<KeyValue>) -> Self {
+    let mut party = Self::default();
+    for KeyValue { key, value } in key_values {
+      push_key(&mut party.order, &key);
+      match key.as_str() {
+        "msisdn" => party.msisdn = Some(value),
+        _ => {
+          party.extras.insert(key, value);
+        }
+      }
+    }
+    party
+  }
+}
+
+impl From<Party> for Vec<KeyValue> {
+  fn from(party: Party) -> Self {
+    party.to_key_values()
+  }
+}
+
+/// The metadata of a transaction.
+///
+/// Serialized as a list of key/value pairs.
+///
+/// Two metadata are equal if they have the same entries, in any order.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(from = "Vec<KeyValue>", into = "Vec<KeyValue>")]
+pub struct TransactionMetadata {
+  /// The `partnerName` entry.
+  pub partner_name: Option<String>,
+  /// The `fc` entry, the code of the foreign currency, e.g. "USD".
+  pub foreign_currency: Option<String>,
+  /// The `amountFc` entry, the amount in the foreign currency.
+  pub amount_foreign_currency: Option<String>,
+  /// The `originalTransactionResult` entry.
+  pub original_transaction_result: Option<String>,
+  /// The other entries.
+  pub extras: IndexMap<String, String>,
+  /// The keys in the order they were received, the entries set since are
+  /// sent after them.
+  order: Vec<String>,
+}
+
+impl TransactionMetadata {
+  /// Whether there is no entry at all.
+  pub fn is_empty(&self) -> bool {
+    self.partner_name.is_none()
+      && self.foreign_currency.is_none()
+      && self.amount_foreign_currency.is_none()
+      && self.original_transaction_result.is_none()
+      && self.extras.is_empty()
+  }
+
+  /// The value of an entry, known or not.
+  pub fn get(&self, key: &str) -> Option<&str> {
+    match key {
+      "partnerName" => self.partner_name.as_deref(),
+      "fc" => self.foreign_currency.as_deref(),
+      "amountFc" => self.amount_foreign_currency.as_deref(),
+      "originalTransactionResult" => self.original_transaction_result.as_deref(),
+      key => self.extras.get(key).map(String::as_str),
+    }
+  }
+
+  /// The entries as sent to the API.
+  pub fn to_key_values(&self) -> Vec<KeyValue> {
+    let known = [
+      ("partnerName", &self.partner_name),
+      ("fc", &self.foreign_currency),
+      ("amountFc", &self.amount_foreign_currency),
+      (
+        "originalTransactionResult",
+        &self.original_transaction_result,
+      ),
+    ];
+    key_values(&self.order, &known, &self.extras)
+  }
+}
+
+impl PartialEq for TransactionMetadata {
+  fn eq(&self, other: &Self) -> bool {
+    self.partner_name == other.partner_name
+      && self.foreign_currency == other.foreign_currency
+      && self.amount_foreign_currency == other.amount_foreign_currency
+      && self.original_transaction_result == other.original_transaction_result
+      && self.extras == other.extras
+  }
+}
+
+impl Eq for TransactionMetadata {}
+
+impl From<Vec<KeyValue>> for TransactionMetadata {
+  fn from(key_values: Vec<KeyValue>) -> Self {
+    let mut metadata = Self::default();
+    for KeyValue { key, value } in key_values {
+      push_key(&mut metadata.order, &key);
+      match key.as_str() {
+        "partnerName" => metadata.partner_name = Some(value),
+        "fc" => metadata.foreign_currency = Some(value),
+        "amountFc" => metadata.amount_foreign_currency = Some(value),
+        "originalTransactionResult" => metadata.original_transaction_result = Some(value),
+        _ => {
+          metadata.extras.insert(key, value);
+        }
+      }
+    }
+    metadata
+  }
+}
+
+impl From<TransactionMetadata> for Vec<KeyValue> {
+  fn from(metadata: TransactionMetadata) -> Self {
+    metadata.to_key_values()
+  }
+}
+
+fn push_key(order: &mut Vec<String>, key: &str) {
+  if !order.iter().any(|k| k == key) {
+    order.push(String::from(key));
+  }
+}
+
+/// The entries in the order they were received, then the known entries and
+/// the extras set since.
+fn key_values(
+  order: &[String],
+  known: &[(&str, &Option<String>)],
+  extras: &IndexMap<String, String>,
+) -> Vec<KeyValue> {
+  let value = |key: &str| match known.iter().find(|(k, _)| *k == key) {
+    Some((_, value)) => value.as_deref(),
+    None => extras.get(key).map(String::as_str),
+  };
+  let received = order
+    .iter()
+    .filter_map(|key| value(key).map(|value| KeyValue::new(key, value)));
+  let known = known
+    .iter()
+    .filter(|(key, _)| !order.iter().any(|k| k == key))
+    .filter_map(|(key, value)| value.as_deref().map(|value| KeyValue::new(key, value)));
+  let extras = extras
+    .iter()
+    .filter(|(key, _)| !order.contains(key))
+    .map(|(key, value)| KeyValue::new(key, value));
+  received.chain(known).chain(extras).collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::msisdn::Msisdn;
+  use crate::party::{Party, TransactionMetadata};
+  use crate::types::KeyValue;
+  use serde_json::json;
+
+  #[test]
+  fn test_party() {
+    let body = json!([
+      { "key": "msisdn", "value": "0343500003" },
+      { "key": "name", "value": "Rakoto" },
+      { "key": "accountType", "value": "customer" }
+    ]);
+    let party: Party = serde_json::from_value(body.clone()).unwrap();
+    assert_eq!(party.msisdn.as_deref(), Some("0343500003"));
+    assert_eq!(party.to_msisdn(), Some(Msisdn::new("0343500003").unwrap()));
+    assert_eq!(party.get("name"), Some("Rakoto"));
+    assert_eq!(serde_json::to_value(&party).unwrap(), body);
+
+    let party = Party::from(Msisdn::new("+261343500004").unwrap());
+    assert_eq!(
+      serde_json::to_value(&party).unwrap(),
+      json!([{ "key": "msisdn", "value": "0343500004" }])
+    );
+    assert_eq!(
+      serde_json::from_value::<Party>(json!([])).unwrap(),
+      Party::default()
+    );
+  }
+
+  #[test]
+  fn test_metadata() {
+    let body = json!([
+      { "key": "partnerName", "value": "TestMVola" },
+      { "key": "fc", "value": "USD" },
+      { "key": "amountFc", "value": "1" },
+      { "key": "originalTransactionResult", "value": "0" },
+      { "key": "zeta", "value": "last" },
+      { "key": "alpha", "value": "first" }
+    ]);
+    let metadata: TransactionMetadata = serde_json::from_value(body.clone()).unwrap();
+    assert_eq!(metadata.partner_name.as_deref(), Some("TestMVola"));
+    assert_eq!(metadata.foreign_currency.as_deref(), Some("USD"));
+    assert_eq!(metadata.amount_foreign_currency.as_deref(), Some("1"));
+    assert_eq!(metadata.original_transaction_result.as_deref(), Some("0"));
+    let extras: Vec<&str> = metadata.extras.keys().map(String::as_str).collect();
+    assert_eq!(extras, vec!["zeta", "alpha"]);
+    assert_eq!(serde_json::to_value(&metadata).unwrap(), body);
+    assert!(TransactionMetadata::default().is_empty());
+  }
+
+  #[test]
+  fn test_order_round_trip() {
+    let body = json!([
+      { "key": "zeta", "value": "first" },
+      { "key": "amountFc", "value": "1" },
+      { "key": "partnerName", "value": "TestMVola" },
+      { "key": "alpha", "value": "last" }
+    ]);
+    let mut metadata: TransactionMetadata = serde_json::from_value(body.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&metadata).unwrap(), body);
+
+    // The entries set after parsing come after the received ones.
+    metadata.partner_name = None;
+    metadata.foreign_currency = Some(String::from("USD"));
+    metadata
+      .extras
+      .insert(String::from("beta"), String::from("new"));
+    let keys: Vec<String> = metadata
+      .to_key_values()
+      .into_iter()
+      .map(|kv| kv.key)
+      .collect();
+    assert_eq!(keys, vec!["zeta", "amountFc", "alpha", "fc", "beta"]);
+
+    let party: Party = serde_json::from_value(json!([
+      { "key": "name", "value": "Rakoto" },
+      { "key": "msisdn", "value": "0343500003" }
+    ]))
+    .unwrap();
+    assert_eq!(party.to_key_values()[0], KeyValue::new("name", "Rakoto"));
+  }
+
+  #[test]
+  fn test_equality_ignores_order() {
+    let metadata: TransactionMetadata = serde_json::from_value(json!([
+      { "key": "zeta", "value": "first" },
+      { "key": "partnerName", "value": "TestMVola" }
+    ]))
+    .unwrap();
+    let expected = TransactionMetadata {
+      partner_name: Some(String::from("TestMVola")),
+      extras: [(String::from("zeta"), String::from("first"))].into(),
+      ..TransactionMetadata::default()
+    };
+    assert_eq!(metadata, expected);
+    assert_ne!(metadata.to_key_values(), expected.to_key_values());
+  }
+
+  #[test]
+  fn test_duplicate_keys() {
+    let party = Party::from(vec![
+      KeyValue::new("msisdn", "0343500003"),
+      KeyValue::new("msisdn", "0343500004"),
+    ]);
+    assert_eq!(party.msisdn.as_deref(), Some("0343500004"));
+
+    // A response repeating a key still decodes.
+    let body = json!([
+      { "key": "note", "value": "a" },
+      { "key": "partnerName", "value": "TestMVola" },
+      { "key": "note", "value": "b" }
+    ]);
+    let metadata: TransactionMetadata = serde_json::from_value(body).unwrap();
+    assert_eq!(
+      serde_json::to_value(&metadata).unwrap(),
+      json!([
+        { "key": "note", "value": "b" },
+        { "key": "partnerName", "value": "TestMVola" }
+      ])
+    );
+  }
+}
@@ -0,0 +1,226 @@
+use crate::error::{MVolaError, Result};
+use crate::status::PaymentStatus;
+use crate::types::{TransactionDetails, TransactionStatus};
+use std::time::{Duration, Instant};
+
+/// How often and for how long to poll the status of a transaction.
+///
+/// The delay starts at `interval` and is multiplied by the backoff factor
+/// after each attempt, up to `max_interval`. A random part of each delay, up
+/// to the jitter ratio, is removed so that many clients do not poll in sync.
+///
+/// # Example
+/// ```no_run
+/// let policy = PollPolicy::new()
+///   .interval(Duration::from_secs(1))
+///   .backoff(2.0, Duration::from_secs(10))
+///   .jitter(0.2)
+///   .deadline(Duration::from_secs(120));
+/// ```
+#[derive(Debug, Clone)]
+pub struct PollPolicy {
+  pub(crate) interval: Duration,
+  pub(crate) max_interval: Duration,
+  pub(crate) multiplier: f64,
+  pub(crate) jitter: f64,
+  pub(crate) deadline: Duration,
+}
+
+impl Default for PollPolicy {
+  /// Poll after 2 seconds, then up to every 30 seconds, for 3 minutes.
+  fn default() -> Self {
+    Self {
+      interval: Duration::from_secs(2),
+      max_interval: Duration::from_secs(30),
+      multiplier: 1.5,
+      jitter: 0.2,
+      deadline: Duration::from_secs(180),
+    }
+  }
+}
+
+impl PollPolicy {
+  /// The shortest delay between two status requests: a shorter interval is
+  /// raised to it, so that a zero interval does not flood the API.
+  pub const MIN_INTERVAL: Duration = Duration::from_millis(10);
+
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Poll every `interval` until `deadline`, without backoff nor jitter.
+  pub fn fixed(interval: Duration, deadline: Duration) -> Self {
+    let interval = interval.max(Self::MIN_INTERVAL);
+    Self {
+      interval,
+      max_interval: interval,
+      multiplier: 1.0,
+      jitter: 0.0,
+      deadline,
+    }
+  }
+
+  /// The delay before the second status request.
+  pub fn interval(mut self, interval: Duration) -> Self {
+    self.interval = interval.max(Self::MIN_INTERVAL);
+    self.max_interval = self.max_interval.max(interval);
+    self
+  }
+
+  /// Multiply the delay by `multiplier` after each attempt, up to `max_interval`.
+  pub fn backoff(mut self, multiplier: f64, max_interval: Duration) -> Self {
+    self.multiplier = multiplier.max(1.0);
+    self.max_interval = max_interval.max(self.interval);
+    self
+  }
+
+  /// Remove a random part of each delay, up to `ratio` (between 0 and 1).
+  ///
+  /// A ratio that is not a finite number means no jitter.
+  pub fn jitter(mut self, ratio: f64) -> Self {
+    self.jitter = if ratio.is_finite() {
+      ratio.clamp(0.0, 1.0)
+    } else {
+      0.0
+    };
+    self
+  }
+
+  /// The maximum time to wait for a final status.
+  pub fn deadline(mut self, deadline: Duration) -> Self {
+    self.deadline = deadline;
+    self
+  }
+
+  /// The delay after the given attempt, starting at 0, before jitter.
+  pub fn delay(&self, attempt: u32) -> Duration {
+    let factor = self.multiplier.powi(attempt.min(i32::MAX as u32) as i32);
+    let delay = self.interval.as_secs_f64() * factor;
+    Duration::from_secs_f64(delay.min(self.max_interval.as_secs_f64()))
+  }
+
+  pub(crate) fn start(&self) -> Poller {
+    Poller {
+      policy: self.clone(),
+      deadline: Instant::now() + self.deadline,
+      attempt: 0,
+    }
+  }
+}
+
+/// Tracks the attempts of a polling loop.
+pub(crate) struct Poller {
+  policy: PollPolicy,
+  deadline: Instant,
+  attempt: u32,
+}
+
+impl Poller {
+  /// The time to sleep before the next attempt, or `None` once the deadline is reached.
+  pub(crate) fn next_delay(&mut self) -> Option<Duration> {
+    let remaining = self.deadline.checked_duration_since(Instant::now())?;
+    if remaining.is_zero() {
+      return None;
+    }
+    let delay = self.policy.delay(self.attempt);
+    let delay = delay.mul_f64(1.0 - self.policy.jitter * fastrand::f64());
+    let delay = delay.max(PollPolicy::MIN_INTERVAL);
+    self.attempt = self.attempt.saturating_add(1);
+    Some(delay.min(remaining))
+  }
+
+  /// The time to sleep after a status before requesting the next one, or
+  /// `None` once the status is final.
+  /// # Errors
+  /// * `MVolaError::DeadlineExceeded` - If the status is not final at the deadline
+  pub(crate) fn after(
+    &mut self,
+    server_correlation_id: &str,
+    status: &PaymentStatus,
+  ) -> Result<Option<Duration>> {
+    if status.is_final() {
+      return Ok(None);
+    }
+    match self.next_delay() {
+      Some(delay) => Ok(Some(delay)),
+      None => Err(MVolaError::DeadlineExceeded {
+        server_correlation_id: String::from(server_correlation_id),
+        status: status.clone(),
+      }),
+    }
+  }
+}
+
+/// The outcome of `wait_for_completion`.
+#[derive(Debug)]
+pub struct Completion {
+  /// The final status, `Completed` or `Failed`.
+  pub status: PaymentStatus,
+  pub server_correlation_id: String,
+  /// The reference of the transaction, used by `get_transaction`.
+  pub object_reference: String,
+  /// The details of the transaction, only fetched when it completed.
+  pub details: Option<TransactionDetails>,
+}
+
+impl Completion {
+  pub(crate) fn new(status: TransactionStatus, details: Option<TransactionDetails>) -> Self {
+    Self {
+      status: status.status,
+      server_correlation_id: status.server_correlation_id,
+      object_reference: status.object_reference,
+      details,
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::poll::PollPolicy;
+  use std::time::Duration;
+
+  #[test]
+  fn test_backoff() {
+    let policy = PollPolicy::new()
+      .interval(Duration::from_secs(1))
+      .backoff(2.0, Duration::from_secs(5));
+
+    let delays: Vec<u64> = (0..5)
+      .map(|attempt| policy.delay(attempt).as_secs())
+      .collect();
+    assert_eq!(delays, vec![1, 2, 4, 5, 5]);
+
+    let fixed = PollPolicy::fixed(Duration::from_millis(500), Duration::from_secs(5));
+    assert_eq!(fixed.delay(10), Duration::from_millis(500));
+  }
+
+  #[test]
+  fn test_jitter_and_deadline() {
+    let mut poller = PollPolicy::new()
+      .interval(Duration::from_secs(1))
+      .jitter(0.5)
+      .deadline(Duration::from_secs(60))
+      .start();
+    let delay = poller.next_delay().unwrap();
+    assert!(delay >= Duration::from_millis(500) && delay <= Duration::from_secs(1));
+
+    let mut poller = PollPolicy::fixed(Duration::from_secs(1), Duration::ZERO).start();
+    assert_eq!(poller.next_delay(), None);
+
+    for ratio in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY] {
+      let policy = PollPolicy::new().jitter(ratio);
+      assert_eq!(policy.jitter, 0.0, "{}", ratio);
+      assert!(policy.start().next_delay().is_some(), "{}", ratio);
+    }
+  }
+
+  #[test]
+  fn test_min_interval() {
+    let policy = PollPolicy::new().interval(Duration::ZERO).jitter(1.0);
+    assert_eq!(policy.delay(0), PollPolicy::MIN_INTERVAL);
+    assert!(policy.start().next_delay().unwrap() >= PollPolicy::MIN_INTERVAL);
+
+    let fixed = PollPolicy::fixed(Duration::ZERO, Duration::from_secs(5));
+    assert_eq!(fixed.delay(3), PollPolicy::MIN_INTERVAL);
+  }
+}
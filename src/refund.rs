@@ -0,0 +1,225 @@
+//! Refunds of completed transactions.
+//!
+//! A refund is a transaction in the reverse direction: the party credited by
+//! the original transaction is debited, and its `originalTransactionReference`
+//! is the reference of the original transaction. The `RefundLedger` of a
+//! service tracks the partial refunds of each transaction, so that the
+//! refunds of a transaction never add up to more than its amount.
+
+use crate::error::{MVolaError, Result};
+use crate::money::Money;
+use crate::party::TransactionMetadata;
+use crate::types::{TransactionDetails, TransactionRequest, TransactionResponse};
+use chrono::{SecondsFormat, Utc};
+use std::collections::HashMap;
+use std::sync::{Mutex, PoisonError};
+use uuid::Uuid;
+
+/// The amounts refunded per original transaction reference.
+///
+/// The ledger is kept in memory: to keep track of the refunds across
+/// restarts, `record` the refunds stored by the application at startup.
+#[derive(Debug, Default)]
+pub struct RefundLedger {
+  refunded: Mutex<HashMap<String, Money>>,
+}
+
+impl RefundLedger {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The amount refunded so far on a transaction.
+  pub fn refunded(&self, transaction_reference: &str) -> Money {
+    let refunded = self.refunded.lock().unwrap_or_else(PoisonError::into_inner);
+    refunded
+      .get(transaction_reference)
+      .copied()
+      .unwrap_or(Money::ZERO)
+  }
+
+  /// The amount that can still be refunded on a transaction.
+  pub fn remaining(&self, original: &TransactionDetails) -> Money {
+    let refunded = self.refunded(&original.transaction_reference);
+    original
+      .amount
+      .checked_sub(refunded)
+      .filter(|remaining| *remaining > Money::ZERO)
+      .unwrap_or(Money::ZERO)
+  }
+
+  /// Count a refund made outside of this client, e.g. before a restart.
+  pub fn record(&self, transaction_reference: &str, amount: Money) {
+    let mut refunded = self.refunded.lock().unwrap_or_else(PoisonError::into_inner);
+    let total = refunded
+      .entry(String::from(transaction_reference))
+      .or_insert(Money::ZERO);
+    *total += amount;
+  }
+
+  /// Stop counting a refund, e.g. once its status is failed.
+  pub fn release(&self, transaction_reference: &str, amount: Money) {
+    let mut refunded = self.refunded.lock().unwrap_or_else(PoisonError::into_inner);
+    if let Some(total) = refunded.get_mut(transaction_reference) {
+      *total = total
+        .checked_sub(amount)
+        .filter(|total| *total > Money::ZERO)
+        .unwrap_or(Money::ZERO);
+    }
+  }
+
+  /// Count a refund before it is sent, if it does not exceed what is left.
+  ///
+  /// Checking and counting under the same lock keeps two concurrent refunds
+  /// from both passing the check.
+  pub(crate) fn reserve(&self, original: &TransactionDetails, amount: Money) -> Result<()> {
+    let mut refunded = self.refunded.lock().unwrap_or_else(PoisonError::into_inner);
+    let total = refunded
+      .entry(original.transaction_reference.clone())
+      .or_insert(Money::ZERO);
+    let remaining = original
+      .amount
+      .checked_sub(*total)
+      .filter(|remaining| *remaining > Money::ZERO)
+      .unwrap_or(Money::ZERO);
+    if amount > remaining {
+      return Err(MVolaError::RefundExceedsOriginal {
+        transaction_reference: original.transaction_reference.clone(),
+        requested: amount,
+        remaining,
+      });
+    }
+    *total += amount;
+    Ok(())
+  }
+
+  /// Build a refund and count it before it is sent.
+  pub(crate) fn begin_refund(
+    &self,
+    original: &TransactionDetails,
+    amount: Money,
+  ) -> Result<TransactionRequest> {
+    let tx = refund_request(original, amount)?;
+    self.reserve(original, amount)?;
+    Ok(tx)
+  }
+
+  /// Stop counting a refund if MVola rejected it.
+  pub(crate) fn finish_refund(
+    &self,
+    original: &TransactionDetails,
+    amount: Money,
+    res: &Result<TransactionResponse>,
+  ) {
+    if res.as_ref().is_err_and(is_rejected) {
+      self.release(&original.transaction_reference, amount);
+    }
+  }
+}
+
+/// Whether a failed transaction request was certainly not executed, so that
+/// it can be sent again.
+///
+/// A request that timed out, whose response was lost or that failed with a
+/// server error may have been executed: a refund stays counted.
+pub(crate) fn is_rejected(err: &MVolaError) -> bool {
+  match err {
+    MVolaError::Api(err) => err.status < 500,
+    MVolaError::Authentication { .. }
+    | MVolaError::MissingConfiguration(_)
+    | MVolaError::InvalidAmount(_)
+    | MVolaError::InvalidUrl(_) => true,
+    _ => false,
+  }
+}
+
+/// The transaction refunding `amount` of a completed transaction.
+/// # Errors
+/// * `MVolaError::NotRefundable` - If the original transaction is not completed
+/// * `MVolaError::InvalidAmount` - If the amount is not a positive, whole amount of Ariary
+pub fn refund_request(original: &TransactionDetails, amount: Money) -> Result<TransactionRequest> {
+  if !original.transaction_status.is_success() {
+    return Err(MVolaError::NotRefundable {
+      transaction_reference: original.transaction_reference.clone(),
+      status: original.transaction_status.clone(),
+    });
+  }
+  amount.validate_payment()?;
+
+  let reference = Uuid::new_v4().to_string();
+  let mut metadata = TransactionMetadata::default();
+  metadata.partner_name = original.metadata.partner_name.clone();
+  Ok(TransactionRequest {
+    amount,
+    currency: original.currency.clone(),
+    description_text: format!("Refund {}", original.transaction_reference),
+    request_date: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+    debit_party: original.credit_party.clone(),
+    credit_party: original.debit_party.clone(),
+    metadata,
+    requesting_organisation_transaction_reference: reference,
+    original_transaction_reference: original.transaction_reference.clone(),
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::MVolaError;
+  use crate::money::Money;
+  use crate::refund::{refund_request, RefundLedger};
+  use crate::status::PaymentStatus;
+  use crate::types::TransactionDetails;
+
+  fn details() -> TransactionDetails {
+    serde_json::from_str(include_str!("../tests/fixtures/transaction_details.json")).unwrap()
+  }
+
+  #[test]
+  fn test_refund_request() {
+    let original = details();
+    let tx = refund_request(&original, Money::ariary(4000)).unwrap();
+    assert_eq!(tx.debit_party, original.credit_party);
+    assert_eq!(tx.credit_party, original.debit_party);
+    assert_eq!(
+      tx.original_transaction_reference,
+      original.transaction_reference
+    );
+    assert_ne!(
+      tx.requesting_organisation_transaction_reference,
+      original.transaction_reference
+    );
+
+    let mut pending = details();
+    pending.transaction_status = PaymentStatus::Pending;
+    assert!(matches!(
+      refund_request(&pending, Money::ariary(4000)),
+      Err(MVolaError::NotRefundable { .. })
+    ));
+    assert!(matches!(
+      refund_request(&original, Money::from_hundredths(150)),
+      Err(MVolaError::InvalidAmount(_))
+    ));
+  }
+
+  #[test]
+  fn test_ledger() {
+    let original = details();
+    let ledger = RefundLedger::new();
+    ledger.reserve(&original, Money::ariary(4000)).unwrap();
+    ledger.reserve(&original, Money::ariary(6000)).unwrap();
+    assert_eq!(ledger.remaining(&original), Money::ZERO);
+    match ledger.reserve(&original, Money::ariary(1)) {
+      Err(MVolaError::RefundExceedsOriginal { remaining, .. }) => {
+        assert_eq!(remaining, Money::ZERO)
+      }
+      res => panic!("unexpected result: {:?}", res),
+    }
+
+    ledger.release(&original.transaction_reference, Money::ariary(6000));
+    assert_eq!(
+      ledger.refunded(&original.transaction_reference),
+      Money::ariary(4000)
+    );
+    assert_eq!(ledger.remaining(&original), Money::ariary(6000));
+  }
+}
@@ -0,0 +1,248 @@
+//! The endpoints of the MVola API.
+//!
+//! Every URL sent by the services is built from a `Route`, so that the
+//! transaction type and the API version are parameters instead of literals:
+//!
+//! ```text
+//! POST {base}/token
+//! POST {base}/mvola/mm/transactions/type/{type}/{version}/
+//! GET  {base}/mvola/mm/transactions/type/{type}/{version}/{transaction_id}
+//! GET  {base}/mvola/mm/transactions/type/{type}/{version}/status/{server_correlation_id}
+//! ```
+
+use crate::error::{MVolaError, Result};
+use crate::http::Method;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use url::Url;
+
+/// The type of a transaction, the `{type}` segment of its paths.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum TransactionType {
+  /// A customer pays a merchant.
+  #[default]
+  MerchantPay,
+  /// A merchant pays a customer.
+  Disbursement,
+  /// A transfer between two accounts.
+  Transfer,
+  /// A type this version of the crate does not know.
+  Custom(String),
+}
+
+impl TransactionType {
+  pub fn as_str(&self) -> &str {
+    match self {
+      TransactionType::MerchantPay => "merchantpay",
+      TransactionType::Disbursement => "disbursement",
+      TransactionType::Transfer => "transfer",
+      TransactionType::Custom(transaction_type) => transaction_type,
+    }
+  }
+}
+
+impl From<&str> for TransactionType {
+  fn from(transaction_type: &str) -> Self {
+    match transaction_type.to_ascii_lowercase().as_str() {
+      "merchantpay" => TransactionType::MerchantPay,
+      "disbursement" => TransactionType::Disbursement,
+      "transfer" => TransactionType::Transfer,
+      _ => TransactionType::Custom(String::from(transaction_type)),
+    }
+  }
+}
+
+impl fmt::Display for TransactionType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Serialize for TransactionType {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+impl<'de> Deserialize<'de> for TransactionType {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    let transaction_type = String::deserialize(deserializer)?;
+    Ok(TransactionType::from(transaction_type.as_str()))
+  }
+}
+
+/// The version of the API, the `{version}` segment of the transaction paths.
+///
+/// Not to be confused with the `Version` header of `Options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ApiVersion {
+  pub major: u16,
+  pub minor: u16,
+  pub patch: u16,
+}
+
+impl ApiVersion {
+  /// The version of the current API, "1.0.0".
+  pub const V1: ApiVersion = ApiVersion::new(1, 0, 0);
+
+  pub const fn new(major: u16, minor: u16, patch: u16) -> Self {
+    Self {
+      major,
+      minor,
+      patch,
+    }
+  }
+}
+
+impl Default for ApiVersion {
+  fn default() -> Self {
+    Self::V1
+  }
+}
+
+impl fmt::Display for ApiVersion {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+  }
+}
+
+/// The transaction type and API version of the transaction endpoints.
+///
+/// Defaults to merchant pay on version 1.0.0.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TransactionApi {
+  pub transaction_type: TransactionType,
+  pub version: ApiVersion,
+}
+
+impl TransactionApi {
+  pub fn new(transaction_type: TransactionType, version: ApiVersion) -> Self {
+    Self {
+      transaction_type,
+      version,
+    }
+  }
+}
+
+/// An endpoint of the API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route<'a> {
+  /// Generate an access token.
+  Token,
+  /// Send a transaction.
+  Transaction(&'a TransactionApi),
+  /// Get the details of a transaction by its transaction id.
+  Details(&'a TransactionApi, &'a str),
+  /// Get the status of a transaction by its server correlation id.
+  Status(&'a TransactionApi, &'a str),
+}
+
+impl Route<'_> {
+  pub fn method(&self) -> Method {
+    match self {
+      Route::Token | Route::Transaction(_) => Method::Post,
+      Route::Details(..) | Route::Status(..) => Method::Get,
+    }
+  }
+
+  /// The segments of the path, each one percent-encoded when the URL is built.
+  fn segments(&self) -> Vec<String> {
+    let transaction = |api: &TransactionApi| {
+      vec![
+        String::from("mvola"),
+        String::from("mm"),
+        String::from("transactions"),
+        String::from("type"),
+        api.transaction_type.to_string(),
+        api.version.to_string(),
+      ]
+    };
+    match self {
+      Route::Token => vec![String::from("token")],
+      Route::Transaction(api) => {
+        let mut segments = transaction(api);
+        segments.push(String::new());
+        segments
+      }
+      Route::Details(api, transaction_id) => {
+        let mut segments = transaction(api);
+        segments.push(String::from(*transaction_id));
+        segments
+      }
+      Route::Status(api, server_correlation_id) => {
+        let mut segments = transaction(api);
+        segments.push(String::from("status"));
+        segments.push(String::from(*server_correlation_id));
+        segments
+      }
+    }
+  }
+
+  /// The URL of the endpoint, below the path of `base_url`.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid base URL
+  pub fn url(&self, base_url: &str) -> Result<Url> {
+    let mut url = Url::parse(base_url)?;
+    url
+      .path_segments_mut()
+      .map_err(|_| MVolaError::InvalidUrl(url::ParseError::RelativeUrlWithCannotBeABaseBase))?
+      .pop_if_empty()
+      .extend(self.segments());
+    Ok(url)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::routes::{ApiVersion, Route, TransactionApi, TransactionType};
+
+  #[test]
+  fn test_urls() {
+    let base_url = "https://devapi.mvola.mg";
+    let api = TransactionApi::default();
+    assert_eq!(
+      Route::Token.url(base_url).unwrap().as_str(),
+      "https://devapi.mvola.mg/token"
+    );
+    assert_eq!(
+      Route::Transaction(&api).url(base_url).unwrap().as_str(),
+      "https://devapi.mvola.mg/mvola/mm/transactions/type/merchantpay/1.0.0/"
+    );
+    assert_eq!(
+      Route::Status(&api, "2ba1d66a")
+        .url(base_url)
+        .unwrap()
+        .as_str(),
+      "https://devapi.mvola.mg/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a"
+    );
+
+    let api = TransactionApi::new(TransactionType::Disbursement, ApiVersion::new(2, 1, 0));
+    assert_eq!(
+      Route::Details(&api, "636042511")
+        .url("http://localhost:8080/simulator/")
+        .unwrap()
+        .as_str(),
+      "http://localhost:8080/simulator/mvola/mm/transactions/type/disbursement/2.1.0/636042511"
+    );
+    assert_eq!(
+      Route::Details(&api, "../token")
+        .url(base_url)
+        .unwrap()
+        .path(),
+      "/mvola/mm/transactions/type/disbursement/2.1.0/..%2Ftoken"
+    );
+  }
+
+  #[test]
+  fn test_transaction_type() {
+    assert_eq!(
+      TransactionType::from("merchantpay"),
+      TransactionType::MerchantPay
+    );
+    assert_eq!(TransactionType::from("Transfer"), TransactionType::Transfer);
+    let custom = TransactionType::from("cashout");
+    assert_eq!(custom, TransactionType::Custom(String::from("cashout")));
+    assert_eq!(serde_json::to_string(&custom).unwrap(), r#""cashout""#);
+  }
+}
@@ -0,0 +1,405 @@
+//! Axum router receiving the callbacks of MVola.
+//!
+//! The router accepts the `PUT` requests sent to the callback URL, checks them,
+//! drops the notifications it has already handled and passes the others to a
+//! `CallbackHandler`:
+//!
+//! ```no_run
+//! struct Orders;
+//!
+//! #[async_trait]
+//! impl CallbackHandler for Orders {
+//!   async fn handle(&self, event: CallbackEvent) -> Result<(), HandlerError> {
+//!     println!("{} is {}", event.notification.server_correlation_id, event.notification.transaction_status);
+//!     Ok(())
+//!   }
+//! }
+//!
+//! let app = axum::Router::new().nest("/mvola", CallbackServer::new(Orders).router("/callback"));
+//! ```
+
+use crate::callback::{
+  parse_callback, CallbackError, CallbackNotification, CallbackSigner, CallbackToken, ReplayGuard,
+};
+use async_trait::async_trait;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::routing::put;
+use axum::Router;
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// The number of handled notifications remembered to drop duplicates.
+const DEDUPLICATION_CAPACITY: usize = 10_000;
+
+pub type HandlerError = Box<dyn std::error::Error + Send + Sync>;
+
+/// A checked callback, passed to the `CallbackHandler`.
+///
+/// With a signer, only the token is authenticated: the ids in the body may
+/// name another payment, find the payment by `reference` instead.
+#[derive(Debug, Clone)]
+pub struct CallbackEvent {
+  pub notification: CallbackNotification,
+  /// The verified token of the callback URL, when a signer is set.
+  pub token: Option<CallbackToken>,
+}
+
+impl CallbackEvent {
+  /// The reference of the payment, from the signed token.
+  pub fn reference(&self) -> Option<&str> {
+    self.token.as_ref().map(|token| token.reference.as_str())
+  }
+}
+
+/// Receives the callbacks accepted by the router.
+///
+/// When the handler fails, the router answers 500 and the notification is not
+/// marked as handled, so that a retry of MVola is handled again.
+#[async_trait]
+pub trait CallbackHandler: Send + Sync + 'static {
+  async fn handle(&self, event: CallbackEvent) -> Result<(), HandlerError>;
+}
+
+/// Builds the router receiving the callbacks.
+pub struct CallbackServer {
+  handler: Arc<dyn CallbackHandler>,
+  signer: Option<Arc<CallbackSigner>>,
+}
+
+impl CallbackServer {
+  pub fn new<H: CallbackHandler>(handler: H) -> Self {
+    Self {
+      handler: Arc::new(handler),
+      signer: None,
+    }
+  }
+
+  /// Only accept the callback URLs signed by the given signer.
+  ///
+  /// Use the same merchant secret as `MVolaBuilder::callback_secret`.
+  pub fn signer(mut self, signer: CallbackSigner) -> Self {
+    self.signer = Some(Arc::new(signer));
+    self
+  }
+
+  /// A router accepting the callbacks on `PUT path`.
+  ///
+  /// It answers:
+  /// * 204 - When the notification was handled, or had already been handled
+  /// * 400 - When the request is not a valid notification
+  /// * 401 - When the token of the callback URL is invalid, expired or replayed with the
+  ///   same status
+  /// * 500 - When the handler failed
+  pub fn router(self, path: &str) -> Router {
+    let state = Arc::new(ServerState {
+      handler: self.handler,
+      signer: self.signer,
+      guard: ReplayGuard::new(),
+      handled: Mutex::new(Handled::default()),
+    });
+    Router::new().route(path, put(receive)).with_state(state)
+  }
+}
+
+struct ServerState {
+  handler: Arc<dyn CallbackHandler>,
+  signer: Option<Arc<CallbackSigner>>,
+  guard: ReplayGuard,
+  handled: Mutex<Handled>,
+}
+
+/// The notifications handled or being handled, oldest first.
+#[derive(Default)]
+struct Handled {
+  keys: HashSet<String>,
+  order: VecDeque<String>,
+}
+
+impl Handled {
+  /// Mark a notification as handled, unless it already is.
+  fn insert(&mut self, key: &str) -> bool {
+    if !self.keys.insert(String::from(key)) {
+      return false;
+    }
+    self.order.push_back(String::from(key));
+    if self.order.len() > DEDUPLICATION_CAPACITY {
+      if let Some(oldest) = self.order.pop_front() {
+        self.keys.remove(&oldest);
+      }
+    }
+    true
+  }
+
+  fn remove(&mut self, key: &str) {
+    self.keys.remove(key);
+    self.order.retain(|k| k != key);
+  }
+}
+
+async fn receive(
+  State(state): State<Arc<ServerState>>,
+  uri: Uri,
+  headers: HeaderMap,
+  body: Bytes,
+) -> StatusCode {
+  let token = match &state.signer {
+    Some(signer) => {
+      let url = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+      match signer.verify(url) {
+        Ok(token) => Some(token),
+        Err(err) => return status_of(&err),
+      }
+    }
+    None => None,
+  };
+
+  let header_pairs = headers
+    .iter()
+    .filter_map(|(name, value)| Some((name.as_str(), value.to_str().ok()?)));
+  let notification = match parse_callback(header_pairs, &body) {
+    Ok(notification) => notification,
+    Err(err) => return status_of(&err),
+  };
+
+  // The signed reference identifies the payment, the body could name any.
+  let payment = match &token {
+    Some(token) => &token.reference,
+    None => &notification.server_correlation_id,
+  };
+  let key = format!("{}/{}", payment, notification.transaction_status);
+  if !lock(&state.handled).insert(&key) {
+    return StatusCode::NO_CONTENT;
+  }
+  if let Some(token) = &token {
+    if let Err(err) = state.guard.check(token, &notification.transaction_status) {
+      lock(&state.handled).remove(&key);
+      return status_of(&err);
+    }
+  }
+
+  let status = notification.transaction_status.clone();
+  let event = CallbackEvent {
+    notification,
+    token: token.clone(),
+  };
+  match state.handler.handle(event).await {
+    Ok(()) => StatusCode::NO_CONTENT,
+    Err(_) => {
+      if let Some(token) = &token {
+        state.guard.release(token, &status);
+      }
+      lock(&state.handled).remove(&key);
+      StatusCode::INTERNAL_SERVER_ERROR
+    }
+  }
+}
+
+fn lock(handled: &Mutex<Handled>) -> std::sync::MutexGuard<'_, Handled> {
+  handled.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn status_of(err: &CallbackError) -> StatusCode {
+  match err {
+    CallbackError::InvalidToken(_) | CallbackError::ExpiredToken | CallbackError::ReplayedToken => {
+      StatusCode::UNAUTHORIZED
+    }
+    CallbackError::UnsupportedContentType(_) => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+    CallbackError::EmptyBody | CallbackError::InvalidBody(_) => StatusCode::BAD_REQUEST,
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::callback::CallbackSigner;
+  use crate::server::{CallbackEvent, CallbackHandler, CallbackServer, HandlerError};
+  use crate::status::PaymentStatus;
+  use async_trait::async_trait;
+  use axum::body::Body;
+  use axum::http::{Request, StatusCode};
+  use axum::Router;
+  use std::sync::{Arc, Mutex};
+  use tower::ServiceExt;
+
+  /// Records the events, failing the first `failures` calls.
+  #[derive(Clone, Default)]
+  struct Recorder {
+    events: Arc<Mutex<Vec<CallbackEvent>>>,
+    failures: Arc<Mutex<usize>>,
+  }
+
+  #[async_trait]
+  impl CallbackHandler for Recorder {
+    async fn handle(&self, event: CallbackEvent) -> Result<(), HandlerError> {
+      let mut failures = self.failures.lock().unwrap();
+      if *failures > 0 {
+        *failures -= 1;
+        return Err("database unavailable".into());
+      }
+      self.events.lock().unwrap().push(event);
+      Ok(())
+    }
+  }
+
+  async fn put(router: &Router, uri: &str, body: &'static [u8]) -> StatusCode {
+    let request = Request::put(uri)
+      .header("Content-Type", "application/json")
+      .body(Body::from(body))
+      .unwrap();
+    router.clone().oneshot(request).await.unwrap().status()
+  }
+
+  #[tokio::test]
+  async fn test_duplicates_are_dropped() {
+    let recorder = Recorder::default();
+    let router = CallbackServer::new(recorder.clone()).router("/callback");
+    let completed = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    assert_eq!(
+      put(&router, "/callback", completed).await,
+      StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+      put(&router, "/callback", completed).await,
+      StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+      put(&router, "/callback", b"{}").await,
+      StatusCode::BAD_REQUEST
+    );
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(
+      events[0].notification.transaction_status,
+      PaymentStatus::Completed
+    );
+  }
+
+  #[tokio::test]
+  async fn test_failed_handler_is_retried() {
+    let recorder = Recorder::default();
+    *recorder.failures.lock().unwrap() = 1;
+    let router = CallbackServer::new(recorder.clone()).router("/callback");
+    let failed = include_bytes!("../tests/fixtures/callback_failed.json");
+
+    assert_eq!(
+      put(&router, "/callback", failed).await,
+      StatusCode::INTERNAL_SERVER_ERROR
+    );
+    assert_eq!(
+      put(&router, "/callback", failed).await,
+      StatusCode::NO_CONTENT
+    );
+    assert_eq!(recorder.events.lock().unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_failed_signed_handler_is_retried() {
+    let recorder = Recorder::default();
+    *recorder.failures.lock().unwrap() = 1;
+    let signer = CallbackSigner::new(b"merchant secret");
+    let url = signer
+      .callback_url("http://localhost/callback", "", "order-42")
+      .unwrap();
+    let router = CallbackServer::new(recorder.clone())
+      .signer(signer)
+      .router("/callback");
+    let failed = include_bytes!("../tests/fixtures/callback_failed.json");
+
+    assert_eq!(
+      put(&router, &url, failed).await,
+      StatusCode::INTERNAL_SERVER_ERROR
+    );
+    assert_eq!(put(&router, &url, failed).await, StatusCode::NO_CONTENT);
+    assert_eq!(recorder.events.lock().unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_statuses_on_one_url() {
+    let recorder = Recorder::default();
+    let signer = CallbackSigner::new(b"merchant secret");
+    let url = signer
+      .callback_url("http://localhost/callback", "", "order-42")
+      .unwrap();
+    let router = CallbackServer::new(recorder.clone())
+      .signer(signer)
+      .router("/callback");
+    let pending = include_bytes!("../tests/fixtures/callback_pending.json");
+    let completed = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    assert_eq!(put(&router, &url, pending).await, StatusCode::NO_CONTENT);
+    assert_eq!(put(&router, &url, completed).await, StatusCode::NO_CONTENT);
+    assert_eq!(put(&router, &url, completed).await, StatusCode::NO_CONTENT);
+
+    let events = recorder.events.lock().unwrap();
+    let statuses: Vec<_> = events
+      .iter()
+      .map(|e| &e.notification.transaction_status)
+      .collect();
+    assert_eq!(
+      statuses,
+      vec![&PaymentStatus::Pending, &PaymentStatus::Completed]
+    );
+  }
+
+  #[tokio::test]
+  async fn test_signed_callbacks() {
+    let recorder = Recorder::default();
+    let router = CallbackServer::new(recorder.clone())
+      .signer(CallbackSigner::new(b"merchant secret"))
+      .router("/callback");
+    let completed = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    assert_eq!(
+      put(&router, "/callback", completed).await,
+      StatusCode::UNAUTHORIZED
+    );
+    let forged = CallbackSigner::new(b"other secret")
+      .callback_url("http://localhost/callback", "", "order-42")
+      .unwrap();
+    assert_eq!(
+      put(&router, &forged, completed).await,
+      StatusCode::UNAUTHORIZED
+    );
+
+    let url = CallbackSigner::new(b"merchant secret")
+      .callback_url("http://localhost/callback", "", "order-42")
+      .unwrap();
+    assert_eq!(put(&router, &url, completed).await, StatusCode::NO_CONTENT);
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].token.as_ref().unwrap().reference, "order-42");
+  }
+
+  #[tokio::test]
+  async fn test_swapped_server_correlation_id() {
+    let recorder = Recorder::default();
+    let signer = CallbackSigner::new(b"merchant secret");
+    let own = signer
+      .callback_url("http://localhost/callback", "", "order-43")
+      .unwrap();
+    let victim = signer
+      .callback_url("http://localhost/callback", "", "order-42")
+      .unwrap();
+    let router = CallbackServer::new(recorder.clone())
+      .signer(signer)
+      .router("/callback");
+    let completed = include_bytes!("../tests/fixtures/callback_completed.json");
+
+    // A callback for order-43 carrying the server correlation id of order-42
+    // does not mark the notification of order-42 as handled.
+    assert_eq!(put(&router, &own, completed).await, StatusCode::NO_CONTENT);
+    assert_eq!(
+      put(&router, &victim, completed).await,
+      StatusCode::NO_CONTENT
+    );
+
+    let events = recorder.events.lock().unwrap();
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].reference(), Some("order-43"));
+    assert_eq!(events[1].reference(), Some("order-42"));
+  }
+}
@@ -0,0 +1,274 @@
+//! The part of the transaction services that does not depend on the transport.
+//!
+//! The async `TransactionService` and its blocking counterpart share their
+//! settings and build the same requests: only sending them differs.
+
+use crate::callback::CallbackSigner;
+use crate::context::{Headers, RequestContext};
+use crate::error::{MVolaError, Result};
+use crate::http::HttpRequest;
+use crate::idempotency::IdempotencyStore;
+use crate::refund::RefundLedger;
+use crate::routes::{Route, TransactionApi, TransactionType};
+use crate::types::{Options, TransactionRequest};
+use std::sync::{Arc, PoisonError, RwLock};
+use url::Url;
+
+/// The state of a transaction service, shared by its clones.
+pub(crate) struct Core<T: ?Sized, M> {
+  pub(crate) transport: Arc<T>,
+  pub(crate) base_url: String,
+  pub(crate) refunds: RefundLedger,
+  settings: RwLock<Arc<Settings<M>>>,
+}
+
+impl<T: ?Sized, M> Core<T, M> {
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub(crate) fn new(base_url: &str, transport: Arc<T>) -> Result<Self> {
+    Url::parse(base_url)?;
+    Ok(Self {
+      transport,
+      base_url: String::from(base_url.trim_end_matches('/')),
+      refunds: RefundLedger::new(),
+      settings: RwLock::new(Arc::new(Settings::default())),
+    })
+  }
+
+  /// A snapshot of the settings, so that a setter never waits for a request.
+  pub(crate) fn settings(&self) -> Arc<Settings<M>> {
+    let settings = self.settings.read().unwrap_or_else(PoisonError::into_inner);
+    settings.clone()
+  }
+
+  pub(crate) fn update<F: FnOnce(&mut Settings<M>)>(&self, update: F) {
+    let mut settings = self
+      .settings
+      .write()
+      .unwrap_or_else(PoisonError::into_inner);
+    update(Arc::make_mut(&mut settings));
+  }
+}
+
+/// The settings of a transaction service, `M` being its token manager.
+pub(crate) struct Settings<M> {
+  pub(crate) authorization: Option<String>,
+  pub(crate) token_manager: Option<Arc<M>>,
+  pub(crate) callback_signer: Option<Arc<CallbackSigner>>,
+  pub(crate) idempotency_store: Option<Arc<dyn IdempotencyStore>>,
+  pub(crate) options: Options,
+  pub(crate) api: TransactionApi,
+}
+
+// Not derived, which would require `M: Clone`.
+impl<M> Clone for Settings<M> {
+  fn clone(&self) -> Self {
+    Self {
+      authorization: self.authorization.clone(),
+      token_manager: self.token_manager.clone(),
+      callback_signer: self.callback_signer.clone(),
+      idempotency_store: self.idempotency_store.clone(),
+      options: self.options.clone(),
+      api: self.api.clone(),
+    }
+  }
+}
+
+impl<M> Default for Settings<M> {
+  fn default() -> Self {
+    Self {
+      authorization: None,
+      token_manager: None,
+      callback_signer: None,
+      idempotency_store: None,
+      options: Options {
+        version: String::from("1.0"),
+        user_language: None,
+        user_account_identifier: String::from(""),
+        partner_name: None,
+        callback_url: None,
+      },
+      api: TransactionApi::default(),
+    }
+  }
+}
+
+/// A request of a transaction service.
+pub(crate) enum Call<'a> {
+  Details(&'a str),
+  Status(&'a str),
+  Payment(&'a TransactionRequest),
+}
+
+/// A call with its headers and endpoint, built again for each authorization
+/// it is sent with.
+pub(crate) struct Prepared<'a, M> {
+  settings: &'a Settings<M>,
+  headers: Headers,
+  api: TransactionApi,
+  call: Call<'a>,
+}
+
+impl<'a, M> Prepared<'a, M> {
+  /// `transaction_type` overrides the type of the context and of the service,
+  /// e.g. for `send_transaction`.
+  pub(crate) fn new(
+    settings: &'a Settings<M>,
+    context: &RequestContext,
+    transaction_type: Option<TransactionType>,
+    call: Call<'a>,
+  ) -> Self {
+    let mut api = context.api(&settings.api);
+    if let Some(transaction_type) = transaction_type {
+      api.transaction_type = transaction_type;
+    }
+    Self {
+      settings,
+      headers: context.headers(&settings.options),
+      api,
+      call,
+    }
+  }
+
+  /// Reject up front the requests MVola would refuse for lack of settings,
+  /// and pick how the others are authorized.
+  pub(crate) fn authorization(&self) -> Result<Authorization<'a, M>> {
+    let settings = self.settings;
+    let mut missing = vec![];
+    let authorization = match (&settings.token_manager, &settings.authorization) {
+      (Some(token_manager), _) => Some(Authorization::Managed(token_manager.as_ref())),
+      (None, Some(fixed)) => Some(Authorization::Fixed(fixed.as_str())),
+      (None, None) => {
+        missing.push("authorization");
+        None
+      }
+    };
+    if self.headers.version.is_empty() {
+      missing.push("version");
+    }
+    if self.headers.user_account_identifier.is_empty() {
+      missing.push("user_account_identifier");
+    }
+    match authorization {
+      Some(authorization) if missing.is_empty() => Ok(authorization),
+      _ => Err(MVolaError::MissingConfiguration(missing)),
+    }
+  }
+
+  /// The request, sent with the given `Authorization` header.
+  pub(crate) fn request(&self, base_url: &str, authorization: &str) -> Result<HttpRequest> {
+    let headers = &self.headers;
+    match self.call {
+      Call::Details(id) => transaction_request(base_url, authorization, headers, &self.api, id),
+      Call::Status(id) => status_request(base_url, authorization, headers, &self.api, id),
+      Call::Payment(tx) => payment_request(
+        base_url,
+        authorization,
+        headers,
+        &self.api,
+        self.settings.callback_signer.as_deref(),
+        tx,
+      ),
+    }
+  }
+}
+
+/// How a request is authorized.
+pub(crate) enum Authorization<'a, M> {
+  /// The authorization set with `set_authorization`.
+  Fixed(&'a str),
+  /// A token of the token manager, renewed once if it is rejected.
+  Managed(&'a M),
+}
+
+fn transaction_request(
+  base_url: &str,
+  authorization: &str,
+  headers: &Headers,
+  api: &TransactionApi,
+  id: &str,
+) -> Result<HttpRequest> {
+  let route = Route::Details(api, id);
+  let mut req = HttpRequest::new(route.method(), route.url(base_url)?);
+  req.set_header("Authorization", authorization);
+  req.set_header("Accept", "application/json");
+  req.set_header("Version", headers.version.as_str());
+  req.set_header("X-CorrelationID", headers.correlation_id.as_str());
+  req.set_header("Cache-Control", "no-cache");
+  req.set_header(
+    "UserAccountIdentifier",
+    headers.user_account_identifier.as_str(),
+  );
+  Ok(req)
+}
+
+fn status_request(
+  base_url: &str,
+  authorization: &str,
+  headers: &Headers,
+  api: &TransactionApi,
+  server_correlation_id: &str,
+) -> Result<HttpRequest> {
+  let route = Route::Status(api, server_correlation_id);
+  let mut req = HttpRequest::new(route.method(), route.url(base_url)?);
+  req.set_header("Authorization", authorization);
+  req.set_header("Version", headers.version.as_str());
+  req.set_header("X-CorrelationID", headers.correlation_id.as_str());
+  if let Some(user_language) = &headers.user_language {
+    req.set_header("UserLanguage", user_language.as_str());
+  }
+  if let Some(partner_name) = &headers.partner_name {
+    req.set_header("PartnerName", partner_name.as_str());
+  }
+  req.set_header("Cache-Control", "no-cache");
+  req.set_header(
+    "UserAccountIdentifier",
+    headers.user_account_identifier.as_str(),
+  );
+  Ok(req)
+}
+
+fn payment_request(
+  base_url: &str,
+  authorization: &str,
+  headers: &Headers,
+  api: &TransactionApi,
+  callback_signer: Option<&CallbackSigner>,
+  tx: &TransactionRequest,
+) -> Result<HttpRequest> {
+  tx.amount.validate_payment()?;
+  let route = Route::Transaction(api);
+  let mut req = HttpRequest::new(route.method(), route.url(base_url)?);
+  req.set_header("Authorization", authorization);
+  req.set_header("Accept", "application/json");
+  req.set_header("Version", headers.version.as_str());
+  req.set_header("X-CorrelationID", headers.correlation_id.as_str());
+  if let Some(user_language) = &headers.user_language {
+    req.set_header("UserLanguage", user_language.as_str());
+  }
+  if let Some(partner_name) = &headers.partner_name {
+    req.set_header("PartnerName", partner_name.as_str());
+  }
+  req.set_header("Cache-Control", "no-cache");
+  req.set_header(
+    "UserAccountIdentifier",
+    headers.user_account_identifier.as_str(),
+  );
+  if let Some(callback_url) = &headers.callback_url {
+    let callback_url = match callback_signer {
+      Some(signer) => signer.callback_url(
+        callback_url,
+        &headers.correlation_id,
+        &tx.requesting_organisation_transaction_reference,
+      )?,
+      None => callback_url.clone(),
+    };
+    req.set_header("X-Callback-URL", &callback_url);
+  }
+  req.set_json(tx)?;
+  Ok(req)
+}
+
+pub(crate) fn bearer(token: &str) -> String {
+  format!("Bearer {}", token)
+}
@@ -0,0 +1,160 @@
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+
+/// The status of a transaction.
+///
+/// Values unknown to this version of the crate are kept in `Unknown` so that
+/// new statuses sent by MVola do not break deserialization.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PaymentStatus {
+  /// The customer has not confirmed the transaction yet.
+  Pending,
+  /// The transaction succeeded.
+  Completed,
+  /// The transaction was rejected or cancelled.
+  Failed,
+  /// A status this version of the crate does not know.
+  Unknown(String),
+}
+
+impl PaymentStatus {
+  pub fn as_str(&self) -> &str {
+    match self {
+      PaymentStatus::Pending => "pending",
+      PaymentStatus::Completed => "completed",
+      PaymentStatus::Failed => "failed",
+      PaymentStatus::Unknown(status) => status,
+    }
+  }
+
+  /// Whether the transaction will not change anymore.
+  ///
+  /// Unknown statuses are not considered final.
+  pub fn is_final(&self) -> bool {
+    matches!(self, PaymentStatus::Completed | PaymentStatus::Failed)
+  }
+
+  pub fn is_success(&self) -> bool {
+    matches!(self, PaymentStatus::Completed)
+  }
+
+  pub fn is_pending(&self) -> bool {
+    matches!(self, PaymentStatus::Pending)
+  }
+}
+
+impl From<&str> for PaymentStatus {
+  fn from(status: &str) -> Self {
+    match status.to_ascii_lowercase().as_str() {
+      "pending" => PaymentStatus::Pending,
+      "completed" => PaymentStatus::Completed,
+      "failed" => PaymentStatus::Failed,
+      _ => PaymentStatus::Unknown(String::from(status)),
+    }
+  }
+}
+
+impl fmt::Display for PaymentStatus {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Serialize for PaymentStatus {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+impl<'de> Deserialize<'de> for PaymentStatus {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    let status = String::deserialize(deserializer)?;
+    Ok(PaymentStatus::from(status.as_str()))
+  }
+}
+
+/// How MVola reports the outcome of a transaction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum NotificationMethod {
+  /// The status must be polled with `get_transaction_status`.
+  Polling,
+  /// MVola calls the `X-Callback-URL` of the request.
+  Callback,
+  /// A method this version of the crate does not know.
+  Unknown(String),
+}
+
+impl NotificationMethod {
+  pub fn as_str(&self) -> &str {
+    match self {
+      NotificationMethod::Polling => "polling",
+      NotificationMethod::Callback => "callback",
+      NotificationMethod::Unknown(method) => method,
+    }
+  }
+}
+
+impl From<&str> for NotificationMethod {
+  fn from(method: &str) -> Self {
+    match method.to_ascii_lowercase().as_str() {
+      "polling" => NotificationMethod::Polling,
+      "callback" => NotificationMethod::Callback,
+      _ => NotificationMethod::Unknown(String::from(method)),
+    }
+  }
+}
+
+impl fmt::Display for NotificationMethod {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(self.as_str())
+  }
+}
+
+impl Serialize for NotificationMethod {
+  fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.as_str())
+  }
+}
+
+impl<'de> Deserialize<'de> for NotificationMethod {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+    let method = String::deserialize(deserializer)?;
+    Ok(NotificationMethod::from(method.as_str()))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::status::{NotificationMethod, PaymentStatus};
+
+  #[test]
+  fn test_payment_status() {
+    assert_eq!(PaymentStatus::from("completed"), PaymentStatus::Completed);
+    assert_eq!(PaymentStatus::from("FAILED"), PaymentStatus::Failed);
+    assert!(PaymentStatus::Completed.is_final() && PaymentStatus::Completed.is_success());
+    assert!(PaymentStatus::Failed.is_final() && !PaymentStatus::Failed.is_success());
+    assert!(PaymentStatus::Pending.is_pending() && !PaymentStatus::Pending.is_final());
+
+    let unknown = PaymentStatus::from("reversed");
+    assert_eq!(unknown, PaymentStatus::Unknown(String::from("reversed")));
+    assert!(!unknown.is_final());
+    assert_eq!(unknown.to_string(), "reversed");
+  }
+
+  #[test]
+  fn test_serde() {
+    let status: PaymentStatus = serde_json::from_str(r#""pending""#).unwrap();
+    assert_eq!(status, PaymentStatus::Pending);
+    assert_eq!(serde_json::to_string(&status).unwrap(), r#""pending""#);
+
+    let status: PaymentStatus = serde_json::from_str(r#""onHold""#).unwrap();
+    assert_eq!(serde_json::to_string(&status).unwrap(), r#""onHold""#);
+
+    let method: NotificationMethod = serde_json::from_str(r#""callback""#).unwrap();
+    assert_eq!(method, NotificationMethod::Callback);
+    let method: NotificationMethod = serde_json::from_str(r#""sms""#).unwrap();
+    assert_eq!(method, NotificationMethod::Unknown(String::from("sms")));
+  }
+}
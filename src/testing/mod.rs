@@ -0,0 +1,1152 @@
+//! In-process MVola simulator for integration tests.
+//!
+//! `Simulator` serves the token and transaction endpoints on a local port,
+//! with sandbox accounts and balances. Payments stay pending until the
+//! simulated time is advanced past the confirmation delay, then complete, or
+//! fail when the customer cannot pay, and the callback URL is notified:
+//!
+//! ```no_run
+//! let simulator = Simulator::start().await?;
+//! let client = simulator.client()?;
+//! let response = client.transaction.send_payment(tx).await?;
+//! simulator.advance(Duration::from_secs(30)).await;
+//! let status = client.transaction.get_transaction_status(&response.server_correlation_id).await?;
+//! assert!(status.status.is_success());
+//! ```
+//!
+//! Failure modes, such as a customer rejecting the payment or a burst of
+//! server errors, are set up with scenarios, see `scenario`.
+
+pub mod scenario;
+
+pub use scenario::{Behavior, Scenario, ScenarioError, Scenarios};
+
+use crate::callback::CallbackNotification;
+use crate::error::{MVolaError, Result};
+use crate::http::{default_transport, HttpRequest, HttpTransport, Method, TransportConfig};
+use crate::money::Money;
+use crate::msisdn::Msisdn;
+use crate::party::Party;
+use crate::routes::TransactionType;
+use crate::status::{NotificationMethod, PaymentStatus};
+use crate::types::{
+  AuthRequest, AuthResponse, ErrorResponse, TransactionDetails, TransactionRequest,
+  TransactionResponse, TransactionStatus,
+};
+use crate::MVola;
+use axum::body::{Body, Bytes};
+use axum::extract::{Path, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::Response;
+use axum::routing::{get, post};
+use axum::Router;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+const TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+/// The transaction endpoints of every transaction type, e.g. merchantpay or disbursement.
+const TRANSACTIONS: &str = "/mvola/mm/transactions/type/{transaction_type}/1.0.0";
+
+/// A callback sent by the simulator.
+#[derive(Debug, Clone)]
+pub struct SentCallback {
+  pub url: String,
+  pub notification: CallbackNotification,
+  /// The status answered by the callback URL, `None` if it could not be reached.
+  pub response_status: Option<u16>,
+}
+
+/// Builder for a `Simulator`.
+pub struct SimulatorBuilder {
+  accounts: HashMap<Msisdn, Money>,
+  merchant: Msisdn,
+  consumer_key: String,
+  consumer_secret: String,
+  confirmation_delay: Duration,
+  transport: Option<Arc<dyn HttpTransport>>,
+  scenarios: Vec<Scenario>,
+}
+
+impl Default for SimulatorBuilder {
+  /// The sandbox accounts 0343500003 and 0343500004 with 1 000 000 Ar each,
+  /// 0343500004 being the merchant.
+  fn default() -> Self {
+    let customer = Msisdn::new("0343500003").expect("valid sandbox msisdn");
+    let merchant = Msisdn::new("0343500004").expect("valid sandbox msisdn");
+    Self {
+      accounts: HashMap::from([
+        (customer, Money::ariary(1_000_000)),
+        (merchant.clone(), Money::ariary(1_000_000)),
+      ]),
+      merchant,
+      consumer_key: String::from("consumer_key"),
+      consumer_secret: String::from("consumer_secret"),
+      confirmation_delay: Duration::from_secs(10),
+      transport: None,
+      scenarios: vec![],
+    }
+  }
+}
+
+impl SimulatorBuilder {
+  /// Add an account, or set the balance of an existing one.
+  pub fn account(mut self, msisdn: Msisdn, balance: Money) -> Self {
+    self.accounts.insert(msisdn, balance);
+    self
+  }
+
+  /// The account used by `Simulator::client`. Defaults to 0343500004.
+  pub fn merchant(mut self, msisdn: Msisdn) -> Self {
+    self.accounts.entry(msisdn.clone()).or_insert(Money::ZERO);
+    self.merchant = msisdn;
+    self
+  }
+
+  /// The only credentials accepted by `/token`.
+  /// Defaults to "consumer_key" and "consumer_secret".
+  pub fn credentials(mut self, consumer_key: &str, consumer_secret: &str) -> Self {
+    self.consumer_key = String::from(consumer_key);
+    self.consumer_secret = String::from(consumer_secret);
+    self
+  }
+
+  /// The simulated time a customer takes to confirm a payment. Defaults to 10 seconds.
+  pub fn confirmation_delay(mut self, delay: Duration) -> Self {
+    self.confirmation_delay = delay;
+    self
+  }
+
+  /// Send the callbacks with the given transport instead of the default one.
+  pub fn transport(mut self, transport: Arc<dyn HttpTransport>) -> Self {
+    self.transport = Some(transport);
+    self
+  }
+
+  /// Add a scenario, after the ones already added.
+  ///
+  /// The account of its MSISDN is created with 1 000 000 Ar if it does not exist.
+  pub fn scenario(mut self, scenario: Scenario) -> Self {
+    if let Some(msisdn) = &scenario.msisdn {
+      self
+        .accounts
+        .entry(msisdn.clone())
+        .or_insert(Money::ariary(1_000_000));
+    }
+    self.scenarios.push(scenario);
+    self
+  }
+
+  /// Add scenarios, e.g. loaded with `Scenarios::from_file`.
+  pub fn scenarios(self, scenarios: Scenarios) -> Self {
+    scenarios.scenarios.into_iter().fold(self, Self::scenario)
+  }
+
+  /// Start the simulator on a free local port.
+  /// # Errors
+  /// * `MVolaError::Transport` - If no local port can be bound
+  pub async fn start(self) -> Result<Simulator> {
+    let transport = match self.transport {
+      Some(transport) => Some(transport),
+      None => default_transport(&TransportConfig::default()).ok(),
+    };
+    let shared = Arc::new(Shared {
+      state: Mutex::new(SimState {
+        accounts: self.accounts,
+        consumer_key: self.consumer_key.clone(),
+        consumer_secret: self.consumer_secret.clone(),
+        confirmation_delay: self.confirmation_delay,
+        started_at: Utc::now(),
+        clock: Duration::ZERO,
+        tokens: HashMap::new(),
+        payments: vec![],
+        next_reference: 636_042_511,
+        callbacks: vec![],
+        scenarios: self
+          .scenarios
+          .into_iter()
+          .map(|scenario| (scenario, 0))
+          .collect(),
+      }),
+    });
+
+    let router = Router::new()
+      .route("/token", post(token))
+      .route(&format!("{}/", TRANSACTIONS), post(pay))
+      .route(&format!("{}/status/{{id}}", TRANSACTIONS), get(status))
+      .route(&format!("{}/{{id}}", TRANSACTIONS), get(details))
+      .with_state(shared.clone());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+      .await
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+    let address = listener
+      .local_addr()
+      .map_err(|e| MVolaError::Transport(Box::new(e)))?;
+    let server = tokio::spawn(async move {
+      let _ = axum::serve(listener, router).await;
+    });
+
+    Ok(Simulator {
+      base_url: format!("http://{}", address),
+      shared,
+      transport,
+      merchant: self.merchant,
+      consumer_key: self.consumer_key,
+      consumer_secret: self.consumer_secret,
+      server,
+    })
+  }
+}
+
+/// A local MVola API, see the module documentation.
+///
+/// The server stops when the simulator is dropped.
+pub struct Simulator {
+  base_url: String,
+  shared: Arc<Shared>,
+  transport: Option<Arc<dyn HttpTransport>>,
+  merchant: Msisdn,
+  consumer_key: String,
+  consumer_secret: String,
+  server: JoinHandle<()>,
+}
+
+impl Simulator {
+  pub fn builder() -> SimulatorBuilder {
+    SimulatorBuilder::default()
+  }
+
+  /// Start a simulator with the default accounts and settings.
+  /// # Errors
+  /// * `MVolaError::Transport` - If no local port can be bound
+  pub async fn start() -> Result<Self> {
+    Self::builder().start().await
+  }
+
+  /// The base URL to pass to `MVolaBuilder::base_url`, e.g. "http://127.0.0.1:41231".
+  pub fn base_url(&self) -> &str {
+    &self.base_url
+  }
+
+  /// A client with the credentials of the simulator, sending the requests of
+  /// the merchant account.
+  /// # Errors
+  /// * `MVolaError::MissingConfiguration` - If no transport feature is enabled
+  pub fn client(&self) -> Result<MVola> {
+    MVola::builder()
+      .base_url(&self.base_url)
+      .credentials(&self.consumer_key, &self.consumer_secret)
+      .user_account_identifier(&self.merchant.account_identifier())
+      .build()
+  }
+
+  /// The balance of an account.
+  pub fn balance(&self, msisdn: &Msisdn) -> Option<Money> {
+    self.shared.lock().accounts.get(msisdn).copied()
+  }
+
+  /// The status of a payment.
+  pub fn payment_status(&self, server_correlation_id: &str) -> Option<PaymentStatus> {
+    let state = self.shared.lock();
+    state
+      .payment(server_correlation_id)
+      .map(|payment| payment.status.clone())
+  }
+
+  /// The callbacks sent so far.
+  pub fn callbacks(&self) -> Vec<SentCallback> {
+    self.shared.lock().callbacks.clone()
+  }
+
+  /// Move the simulated time forward, settle the payments whose confirmation
+  /// delay has passed and send their callbacks.
+  pub async fn advance(&self, by: Duration) {
+    let due = self.shared.lock().advance(by);
+    for (url, correlation_id, notification) in due {
+      let response_status = self
+        .send_callback(&url, &correlation_id, &notification)
+        .await;
+      self.shared.lock().callbacks.push(SentCallback {
+        url,
+        notification,
+        response_status,
+      });
+    }
+  }
+
+  async fn send_callback(
+    &self,
+    url: &str,
+    correlation_id: &str,
+    notification: &CallbackNotification,
+  ) -> Option<u16> {
+    let transport = self.transport.as_ref()?;
+    let mut req = HttpRequest::new(Method::Put, url.parse().ok()?);
+    req.set_header("X-CorrelationID", correlation_id);
+    req.set_json(notification).ok()?;
+    transport.send(req).await.ok().map(|res| res.status)
+  }
+}
+
+impl Drop for Simulator {
+  fn drop(&mut self) {
+    self.server.abort();
+  }
+}
+
+struct Shared {
+  state: Mutex<SimState>,
+}
+
+impl Shared {
+  fn lock(&self) -> MutexGuard<'_, SimState> {
+    self.state.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+struct SimState {
+  accounts: HashMap<Msisdn, Money>,
+  consumer_key: String,
+  consumer_secret: String,
+  confirmation_delay: Duration,
+  started_at: DateTime<Utc>,
+  clock: Duration,
+  tokens: HashMap<String, Duration>,
+  payments: Vec<Payment>,
+  next_reference: u64,
+  callbacks: Vec<SentCallback>,
+  /// The scenarios, with the number of server errors they answered.
+  scenarios: Vec<(Scenario, u32)>,
+}
+
+struct Payment {
+  transaction_type: TransactionType,
+  server_correlation_id: String,
+  transaction_reference: String,
+  correlation_id: String,
+  request: TransactionRequest,
+  debit: Msisdn,
+  credit: Msisdn,
+  status: PaymentStatus,
+  created_at: Duration,
+  callback_url: Option<String>,
+  behavior: Option<Behavior>,
+}
+
+impl Payment {
+  fn delay(&self) -> Option<Duration> {
+    self.behavior.as_ref().and_then(Behavior::delay)
+  }
+}
+
+impl SimState {
+  fn date(&self, at: Duration) -> String {
+    let at = chrono::Duration::from_std(at).unwrap_or_default();
+    (self.started_at + at).to_rfc3339_opts(SecondsFormat::Millis, true)
+  }
+
+  fn payment(&self, server_correlation_id: &str) -> Option<&Payment> {
+    self
+      .payments
+      .iter()
+      .find(|payment| payment.server_correlation_id == server_correlation_id)
+  }
+
+  /// Settle the payments that are due, returning the callbacks to send.
+  fn advance(&mut self, by: Duration) -> Vec<(String, String, CallbackNotification)> {
+    self.clock += by;
+    let mut callbacks = vec![];
+    for i in 0..self.payments.len() {
+      let payment = &self.payments[i];
+      if !payment.status.is_pending() {
+        continue;
+      }
+      // Whether the customer confirms the payment, once it is due.
+      let confirmed = match payment.behavior {
+        Some(Behavior::StuckPending) => continue,
+        Some(Behavior::Expire { after_secs }) => {
+          if payment.created_at + Duration::from_secs(after_secs) > self.clock {
+            continue;
+          }
+          false
+        }
+        _ if payment.created_at + self.confirmation_delay > self.clock => continue,
+        Some(Behavior::Reject | Behavior::InsufficientFunds) => false,
+        _ => true,
+      };
+      let amount = payment.request.amount;
+      let (debit, credit) = (payment.debit.clone(), payment.credit.clone());
+      let balance = self.accounts.get(&debit).copied().unwrap_or(Money::ZERO);
+      let status = if confirmed && balance >= amount {
+        self.accounts.insert(debit, balance - amount);
+        *self.accounts.entry(credit).or_insert(Money::ZERO) += amount;
+        PaymentStatus::Completed
+      } else {
+        PaymentStatus::Failed
+      };
+
+      let payment = &mut self.payments[i];
+      payment.status = status;
+      if let Some(url) = &payment.callback_url {
+        let notification = CallbackNotification {
+          transaction_status: payment.status.clone(),
+          server_correlation_id: payment.server_correlation_id.clone(),
+          transaction_reference: payment
+            .status
+            .is_success()
+            .then(|| payment.transaction_reference.clone()),
+          request_date: Some(payment.request.request_date.clone()),
+          debit_party: payment.request.debit_party.clone(),
+          credit_party: payment.request.credit_party.clone(),
+          fees: vec![],
+          metadata: payment.request.metadata.clone(),
+          correlation_id: None,
+        };
+        callbacks.push((url.clone(), payment.correlation_id.clone(), notification));
+      }
+    }
+    callbacks
+  }
+
+  /// The behavior of the first scenario matching a payment request.
+  ///
+  /// A server error is counted as answered: once all of them are, the
+  /// scenario is skipped and the next matching one applies.
+  fn behavior(&mut self, request: &TransactionRequest) -> Option<Behavior> {
+    let parties = [&request.debit_party, &request.credit_party];
+    for (scenario, server_errors) in self.scenarios.iter_mut() {
+      if !scenario.matches(request.amount, &parties) {
+        continue;
+      }
+      if let Behavior::ServerErrors { count, .. } = scenario.behavior {
+        if *server_errors >= count {
+          continue;
+        }
+        *server_errors += 1;
+      }
+      return Some(scenario.behavior);
+    }
+    None
+  }
+
+  /// Check the headers sent with every transaction request.
+  fn check_headers(&self, headers: &HeaderMap) -> std::result::Result<String, Rejection> {
+    let token = header_value(headers, "Authorization")
+      .and_then(|value| value.strip_prefix("Bearer "))
+      .filter(|token| {
+        self
+          .tokens
+          .get(*token)
+          .is_some_and(|expiry| *expiry > self.clock)
+      });
+    if token.is_none() {
+      return Err(Rejection::InvalidToken);
+    }
+    if header_value(headers, "Version") != Some("1.0") {
+      return Err(Rejection::Invalid(String::from(
+        "The Version header must be 1.0",
+      )));
+    }
+    match header_value(headers, "X-CorrelationID") {
+      Some(correlation_id) if !correlation_id.is_empty() => Ok(String::from(correlation_id)),
+      _ => Err(Rejection::Invalid(String::from(
+        "The X-CorrelationID header is required",
+      ))),
+    }
+  }
+}
+
+/// A request refused by the simulator.
+enum Rejection {
+  InvalidToken,
+  Invalid(String),
+  UnknownAccount(Msisdn),
+  DuplicateReference(String),
+  NotFound(String),
+  ServerError(u16),
+}
+
+impl Rejection {
+  fn into_response(self) -> Response {
+    match self {
+      Rejection::InvalidToken => json(
+        StatusCode::UNAUTHORIZED,
+        &serde_json::json!({
+          "fault": {
+            "code": 900901,
+            "message": "Invalid Credentials",
+            "description": "Invalid Credentials. Make sure you have given the correct access token"
+          }
+        }),
+      ),
+      Rejection::Invalid(description) => error(
+        StatusCode::BAD_REQUEST,
+        "validation",
+        "formatError",
+        &description,
+      ),
+      Rejection::UnknownAccount(msisdn) => error(
+        StatusCode::BAD_REQUEST,
+        "businessRule",
+        "unknownAccount",
+        &format!("Unknown account {}", msisdn),
+      ),
+      Rejection::DuplicateReference(reference) => error(
+        StatusCode::BAD_REQUEST,
+        "businessRule",
+        "duplicateReference",
+        &format!("The reference {} has already been used", reference),
+      ),
+      Rejection::NotFound(id) => error(
+        StatusCode::NOT_FOUND,
+        "identification",
+        "transactionNotFound",
+        &format!("Unknown transaction {}", id),
+      ),
+      Rejection::ServerError(status) => error(
+        StatusCode::from_u16(status).unwrap_or(StatusCode::SERVICE_UNAVAILABLE),
+        "internal",
+        "serviceUnavailable",
+        "The service is temporarily unavailable",
+      ),
+    }
+  }
+}
+
+async fn token(State(shared): State<Arc<Shared>>, headers: HeaderMap, body: Bytes) -> Response {
+  let mut state = shared.lock();
+  let credentials = header_value(&headers, "Authorization")
+    .and_then(|value| value.strip_prefix("Basic "))
+    .and_then(|encoded| STANDARD.decode(encoded).ok())
+    .and_then(|decoded| String::from_utf8(decoded).ok());
+  let expected = format!("{}:{}", state.consumer_key, state.consumer_secret);
+  if credentials.as_deref() != Some(expected.as_str()) {
+    return json(
+      StatusCode::UNAUTHORIZED,
+      &serde_json::json!({
+        "error_description": "Client Authentication failed.",
+        "error": "invalid_client"
+      }),
+    );
+  }
+
+  let grant = serde_urlencoded::from_bytes::<AuthRequest>(&body).ok();
+  if grant.as_ref().map(|grant| grant.grant_type.as_str()) != Some("client_credentials") {
+    return json(
+      StatusCode::BAD_REQUEST,
+      &serde_json::json!({
+        "error_description": "Unsupported grant_type value",
+        "error": "unsupported_grant_type"
+      }),
+    );
+  }
+
+  let access_token = Uuid::new_v4().simple().to_string();
+  let expiry = state.clock + TOKEN_LIFETIME;
+  state.tokens.insert(access_token.clone(), expiry);
+  json(
+    StatusCode::OK,
+    &AuthResponse {
+      access_token,
+      token_type: String::from("Bearer"),
+      expires_in: TOKEN_LIFETIME.as_secs(),
+      scope: grant.map(|grant| grant.scope).unwrap_or_default(),
+    },
+  )
+}
+
+async fn pay(
+  State(shared): State<Arc<Shared>>,
+  Path(transaction_type): Path<String>,
+  headers: HeaderMap,
+  body: Bytes,
+) -> Response {
+  let (response, delay) = {
+    let mut state = shared.lock();
+    let correlation_id = match state.check_headers(&headers) {
+      Ok(correlation_id) => correlation_id,
+      Err(rejection) => return rejection.into_response(),
+    };
+    let request: TransactionRequest = match serde_json::from_slice(&body) {
+      Ok(request) => request,
+      Err(e) => return Rejection::Invalid(format!("Invalid body: {}", e)).into_response(),
+    };
+    if let Err(e) = request.amount.validate_payment() {
+      return Rejection::Invalid(e.to_string()).into_response();
+    }
+    let parties = party(&state, &request.debit_party)
+      .and_then(|debit| Ok((debit, party(&state, &request.credit_party)?)));
+    let (debit, credit) = match parties {
+      Ok(parties) => parties,
+      Err(rejection) => return rejection.into_response(),
+    };
+
+    let reference = &request.requesting_organisation_transaction_reference;
+    let behavior = state.behavior(&request);
+    let duplicate = state.payments.iter().any(|payment| {
+      payment
+        .request
+        .requesting_organisation_transaction_reference
+        == *reference
+    });
+    match behavior {
+      Some(Behavior::ServerErrors { status, .. }) => {
+        return Rejection::ServerError(status).into_response()
+      }
+      Some(Behavior::DuplicateReference) => {
+        return Rejection::DuplicateReference(reference.clone()).into_response()
+      }
+      _ if duplicate => return Rejection::DuplicateReference(reference.clone()).into_response(),
+      _ => {}
+    }
+
+    let callback_url = header_value(&headers, "X-Callback-URL").map(String::from);
+    let notification_method = match callback_url {
+      Some(_) => NotificationMethod::Callback,
+      None => NotificationMethod::Polling,
+    };
+    let server_correlation_id = Uuid::new_v4().to_string();
+    let transaction_reference = state.next_reference.to_string();
+    state.next_reference += 1;
+    let created_at = state.clock;
+    state.payments.push(Payment {
+      transaction_type: TransactionType::from(transaction_type.as_str()),
+      server_correlation_id: server_correlation_id.clone(),
+      transaction_reference,
+      correlation_id,
+      request,
+      debit,
+      credit,
+      status: PaymentStatus::Pending,
+      created_at,
+      callback_url,
+      behavior,
+    });
+
+    let response = json(
+      StatusCode::ACCEPTED,
+      &TransactionResponse {
+        status: PaymentStatus::Pending,
+        server_correlation_id,
+        notification_method,
+      },
+    );
+    (response, behavior.as_ref().and_then(Behavior::delay))
+  };
+  slow(response, delay).await
+}
+
+async fn status(
+  State(shared): State<Arc<Shared>>,
+  Path((transaction_type, id)): Path<(String, String)>,
+  headers: HeaderMap,
+) -> Response {
+  let (response, delay) = {
+    let state = shared.lock();
+    if let Err(rejection) = state.check_headers(&headers) {
+      return rejection.into_response();
+    }
+    let transaction_type = TransactionType::from(transaction_type.as_str());
+    let payment = state
+      .payment(&id)
+      .filter(|payment| payment.transaction_type == transaction_type);
+    let Some(payment) = payment else {
+      return Rejection::NotFound(id).into_response();
+    };
+    let response = json(
+      StatusCode::OK,
+      &TransactionStatus {
+        status: payment.status.clone(),
+        server_correlation_id: payment.server_correlation_id.clone(),
+        notification_method: match payment.callback_url {
+          Some(_) => NotificationMethod::Callback,
+          None => NotificationMethod::Polling,
+        },
+        object_reference: if payment.status.is_success() {
+          payment.transaction_reference.clone()
+        } else {
+          String::new()
+        },
+      },
+    );
+    (response, payment.delay())
+  };
+  slow(response, delay).await
+}
+
+async fn details(
+  State(shared): State<Arc<Shared>>,
+  Path((transaction_type, id)): Path<(String, String)>,
+  headers: HeaderMap,
+) -> Response {
+  let (response, delay) = {
+    let state = shared.lock();
+    if let Err(rejection) = state.check_headers(&headers) {
+      return rejection.into_response();
+    }
+    let transaction_type = TransactionType::from(transaction_type.as_str());
+    let payment = state.payments.iter().find(|payment| {
+      payment.transaction_type == transaction_type
+        && payment.transaction_reference == id
+        && payment.status.is_success()
+    });
+    let Some(payment) = payment else {
+      return Rejection::NotFound(id).into_response();
+    };
+    let response = json(
+      StatusCode::OK,
+      &TransactionDetails {
+        amount: payment.request.amount,
+        currency: payment.request.currency.clone(),
+        transaction_reference: payment.transaction_reference.clone(),
+        transaction_status: payment.status.clone(),
+        creation_date: state.date(payment.created_at),
+        request_date: payment.request.request_date.clone(),
+        debit_party: payment.request.debit_party.clone(),
+        credit_party: payment.request.credit_party.clone(),
+        metadata: payment.request.metadata.clone(),
+        fees: vec![],
+      },
+    );
+    (response, payment.delay())
+  };
+  slow(response, delay).await
+}
+
+/// Answer after the delay of a `Behavior::Delay` scenario.
+async fn slow(response: Response, delay: Option<Duration>) -> Response {
+  if let Some(delay) = delay {
+    tokio::time::sleep(delay).await;
+  }
+  response
+}
+
+/// The account of a debit or credit party.
+fn party(state: &SimState, party: &Party) -> std::result::Result<Msisdn, Rejection> {
+  let msisdn = party
+    .to_msisdn()
+    .ok_or_else(|| Rejection::Invalid(String::from("Each party must have a MVola msisdn")))?;
+  if !state.accounts.contains_key(&msisdn) {
+    return Err(Rejection::UnknownAccount(msisdn));
+  }
+  Ok(msisdn)
+}
+
+fn header_value<'a>(headers: &'a HeaderMap, name: &str) -> Option<&'a str> {
+  headers.get(name).and_then(|value| value.to_str().ok())
+}
+
+fn json<T: Serialize>(status: StatusCode, body: &T) -> Response {
+  Response::builder()
+    .status(status)
+    .header(header::CONTENT_TYPE, "application/json")
+    .body(Body::from(serde_json::to_vec(body).unwrap_or_default()))
+    .expect("valid response")
+}
+
+fn error(status: StatusCode, category: &str, code: &str, description: &str) -> Response {
+  json(
+    status,
+    &ErrorResponse {
+      error_category: String::from(category),
+      error_code: String::from(code),
+      error_description: Some(String::from(description)),
+      error_date_time: Some(Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)),
+      error_parameters: vec![],
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::context::RequestContext;
+  use crate::error::{MVolaError, Result};
+  use crate::http::{HttpRequest, HttpResponse, HttpTransport};
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::status::{NotificationMethod, PaymentStatus};
+  use crate::testing::{Behavior, Scenario, Scenarios, Simulator};
+  use crate::types::{Options, Service, TransactionRequest};
+  use async_trait::async_trait;
+  use std::sync::{Arc, Mutex};
+  use std::time::Duration;
+
+  /// Records the callbacks and answers 204.
+  #[derive(Default)]
+  struct CallbackRecorder {
+    requests: Mutex<Vec<HttpRequest>>,
+  }
+
+  #[async_trait]
+  impl HttpTransport for CallbackRecorder {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+      self.requests.lock().unwrap().push(request);
+      Ok(HttpResponse {
+        status: 204,
+        headers: vec![],
+        body: vec![],
+      })
+    }
+  }
+
+  fn payment(amount: Money) -> TransactionRequest {
+    payment_from("0343500003", amount)
+  }
+
+  fn payment_from(customer: &str, amount: Money) -> TransactionRequest {
+    TransactionRequest::builder()
+      .amount(amount)
+      .debit(Msisdn::new(customer).unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test")
+      .build()
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn test_payment_completes() {
+    let callbacks = Arc::new(CallbackRecorder::default());
+    let simulator = Simulator::builder()
+      .transport(callbacks.clone())
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+    client.transaction.set_options(Options {
+      version: String::from("1.0"),
+      user_language: None,
+      user_account_identifier: String::from("msisdn;0343500004"),
+      partner_name: None,
+      callback_url: Some(String::from("https://shop.mg/callback")),
+    });
+
+    let response = client
+      .transaction
+      .send_payment(payment(Money::ariary(1000)))
+      .await
+      .unwrap();
+    assert_eq!(response.status, PaymentStatus::Pending);
+    assert_eq!(response.notification_method, NotificationMethod::Callback);
+
+    simulator.advance(Duration::from_secs(5)).await;
+    let status = client
+      .transaction
+      .get_transaction_status(&response.server_correlation_id)
+      .await
+      .unwrap();
+    assert_eq!(status.status, PaymentStatus::Pending);
+
+    simulator.advance(Duration::from_secs(5)).await;
+    let status = client
+      .transaction
+      .get_transaction_status(&response.server_correlation_id)
+      .await
+      .unwrap();
+    assert_eq!(status.status, PaymentStatus::Completed);
+    let details = client
+      .transaction
+      .get_transaction(&status.object_reference)
+      .await
+      .unwrap();
+    assert_eq!(details.amount, Money::ariary(1000));
+
+    let customer = Msisdn::new("0343500003").unwrap();
+    let merchant = Msisdn::new("0343500004").unwrap();
+    assert_eq!(simulator.balance(&customer), Some(Money::ariary(999_000)));
+    assert_eq!(simulator.balance(&merchant), Some(Money::ariary(1_001_000)));
+
+    let sent = simulator.callbacks();
+    assert_eq!(sent.len(), 1);
+    assert_eq!(sent[0].url, "https://shop.mg/callback");
+    assert_eq!(sent[0].response_status, Some(204));
+    assert_eq!(
+      sent[0].notification.transaction_status,
+      PaymentStatus::Completed
+    );
+    assert_eq!(callbacks.requests.lock().unwrap().len(), 1);
+  }
+
+  #[tokio::test]
+  async fn test_insufficient_funds_fail() {
+    let simulator = Simulator::builder()
+      .account(Msisdn::new("0343500003").unwrap(), Money::ariary(500))
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    let response = client
+      .transaction
+      .send_payment(payment(Money::ariary(1000)))
+      .await
+      .unwrap();
+    simulator.advance(Duration::from_secs(10)).await;
+
+    assert_eq!(
+      simulator.payment_status(&response.server_correlation_id),
+      Some(PaymentStatus::Failed)
+    );
+    assert_eq!(
+      simulator.balance(&Msisdn::new("0343500003").unwrap()),
+      Some(Money::ariary(500))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_headers_are_enforced() {
+    let simulator = Simulator::start().await.unwrap();
+    let client = simulator.client().unwrap();
+
+    client.transaction.set_options(Options {
+      version: String::from("2.0"),
+      user_language: None,
+      user_account_identifier: String::from("msisdn;0343500004"),
+      partner_name: None,
+      callback_url: None,
+    });
+    let err = client
+      .transaction
+      .get_transaction_status("unknown")
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::Api(ref e) if e.status == 400),
+      "{:?}",
+      err
+    );
+
+    client.transaction.set_options(Options {
+      version: String::from("1.0"),
+      user_language: None,
+      user_account_identifier: String::from("msisdn;0343500004"),
+      partner_name: None,
+      callback_url: None,
+    });
+    let err = client
+      .transaction
+      .with_context(RequestContext::new().correlation_id(""))
+      .get_transaction_status("unknown")
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::Api(ref e) if e.status == 400),
+      "{:?}",
+      err
+    );
+
+    let client = crate::MVola::builder()
+      .base_url(simulator.base_url())
+      .user_account_identifier("msisdn;0343500004")
+      .build()
+      .unwrap();
+    client.transaction.set_authorization("forged token");
+    let err = client
+      .transaction
+      .get_transaction_status("unknown")
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      err,
+      MVolaError::Authentication { status: 401, .. }
+    ));
+
+    let client =
+      crate::MVola::with_credentials(simulator.base_url(), "consumer_key", "wrong").unwrap();
+    let err = client
+      .auth
+      .generate_token("consumer_key", "wrong")
+      .await
+      .unwrap_err();
+    assert!(matches!(
+      err,
+      MVolaError::Authentication { status: 401, .. }
+    ));
+  }
+
+  #[tokio::test]
+  async fn test_unknown_account_is_rejected() {
+    let simulator = Simulator::start().await.unwrap();
+    let client = simulator.client().unwrap();
+
+    let tx = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0349999999").unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test")
+      .build()
+      .unwrap();
+    let err = client.transaction.send_payment(tx).await.unwrap_err();
+    match err {
+      MVolaError::Api(err) => assert_eq!(err.error.unwrap().error_code, "unknownAccount"),
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_scenarios_fail_payments() {
+    let simulator = Simulator::builder()
+      .scenarios(Scenarios::from_file("tests/fixtures/scenarios.toml").unwrap())
+      .scenario(Scenario::for_msisdn(
+        Msisdn::new("0340000005").unwrap(),
+        Behavior::StuckPending,
+      ))
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    let mut ids = vec![];
+    for customer in ["0340000001", "0340000002", "0340000003", "0340000005"] {
+      let response = client
+        .transaction
+        .send_payment(payment_from(customer, Money::ariary(1000)))
+        .await
+        .unwrap();
+      ids.push(response.server_correlation_id);
+    }
+    let statuses = |simulator: &Simulator| -> Vec<PaymentStatus> {
+      ids
+        .iter()
+        .map(|id| simulator.payment_status(id).unwrap())
+        .collect()
+    };
+
+    simulator.advance(Duration::from_secs(10)).await;
+    assert_eq!(
+      statuses(&simulator),
+      vec![
+        PaymentStatus::Failed,
+        PaymentStatus::Failed,
+        PaymentStatus::Pending,
+        PaymentStatus::Pending
+      ]
+    );
+    simulator.advance(Duration::from_secs(3600)).await;
+    assert_eq!(
+      statuses(&simulator)[2..],
+      [PaymentStatus::Failed, PaymentStatus::Pending]
+    );
+    assert_eq!(
+      simulator.balance(&Msisdn::new("0340000002").unwrap()),
+      Some(Money::ariary(1_000_000))
+    );
+  }
+
+  #[tokio::test]
+  async fn test_server_errors_and_duplicate_references() {
+    let simulator = Simulator::builder()
+      .scenario(Scenario::for_amount(
+        Money::ariary(666),
+        Behavior::ServerErrors {
+          count: 2,
+          status: 503,
+        },
+      ))
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    for _ in 0..2 {
+      let err = client
+        .transaction
+        .send_payment(payment(Money::ariary(666)))
+        .await
+        .unwrap_err();
+      assert!(
+        matches!(err, MVolaError::Api(ref e) if e.status == 503),
+        "{:?}",
+        err
+      );
+    }
+    let order = || {
+      TransactionRequest::builder()
+        .amount(Money::ariary(666))
+        .debit(Msisdn::new("0343500003").unwrap())
+        .credit(Msisdn::new("0343500004").unwrap())
+        .description("test")
+        .reference("order-42")
+        .build()
+        .unwrap()
+    };
+    client.transaction.send_payment(order()).await.unwrap();
+
+    let err = client.transaction.send_payment(order()).await.unwrap_err();
+    match err {
+      MVolaError::Api(err) => assert_eq!(err.error.unwrap().error_code, "duplicateReference"),
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_exhausted_server_errors() {
+    let simulator = Simulator::builder()
+      .scenario(Scenario::for_amount(
+        Money::ariary(777),
+        Behavior::ServerErrors {
+          count: 1,
+          status: 500,
+        },
+      ))
+      .scenario(Scenario::for_amount(
+        Money::ariary(777),
+        Behavior::DuplicateReference,
+      ))
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    let err = client
+      .transaction
+      .send_payment(payment(Money::ariary(777)))
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::Api(ref e) if e.status == 500),
+      "{:?}",
+      err
+    );
+    let err = client
+      .transaction
+      .send_payment(payment(Money::ariary(777)))
+      .await
+      .unwrap_err();
+    match err {
+      MVolaError::Api(err) => assert_eq!(err.error.unwrap().error_code, "duplicateReference"),
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_slow_responses() {
+    let simulator = Simulator::builder()
+      .scenario(Scenario::for_msisdn(
+        Msisdn::new("0340000004").unwrap(),
+        Behavior::Delay { delay_ms: 300 },
+      ))
+      .start()
+      .await
+      .unwrap();
+    let client = simulator.client().unwrap();
+
+    let started = std::time::Instant::now();
+    let response = client
+      .transaction
+      .send_payment(payment_from("0340000004", Money::ariary(1000)))
+      .await
+      .unwrap();
+    client
+      .transaction
+      .get_transaction_status(&response.server_correlation_id)
+      .await
+      .unwrap();
+    assert!(started.elapsed() >= Duration::from_millis(600));
+  }
+}
@@ -0,0 +1,270 @@
+//! Failure modes of the simulator, tied to MSISDNs or amounts.
+//!
+//! A scenario applies to the payments whose debit or credit party is its
+//! MSISDN and whose amount is its amount, when set. The first matching
+//! scenario wins. Scenarios can be written in TOML:
+//!
+//! ```toml
+//! [[scenarios]]
+//! msisdn = "0340000001"
+//! behavior = "reject"
+//!
+//! [[scenarios]]
+//! amount = "666"
+//! behavior = "server_errors"
+//! count = 3
+//!
+//! [[scenarios]]
+//! msisdn = "0340000002"
+//! behavior = "delay"
+//! delay_ms = 2000
+//! ```
+//!
+//! or in JSON, with the same fields: `{"scenarios": [{"msisdn": "0340000001", "behavior": "reject"}]}`.
+
+use crate::money::Money;
+use crate::msisdn::Msisdn;
+use crate::party::Party;
+use serde::{de, Deserialize, Serialize};
+use serde_json::Value;
+use std::path::Path;
+use std::time::Duration;
+
+/// What the simulator does with a matching payment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "behavior", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Behavior {
+  /// The customer rejects the payment on the handset: it fails once confirmed.
+  Reject,
+  /// The payment fails for insufficient funds, whatever the balance.
+  InsufficientFunds,
+  /// The payment stays pending forever.
+  StuckPending,
+  /// The payment stays pending, then fails after `after_secs` of simulated time.
+  Expire { after_secs: u64 },
+  /// The payment is refused as if its reference had already been used.
+  DuplicateReference,
+  /// The first `count` payment requests are answered with a server error.
+  ServerErrors {
+    count: u32,
+    #[serde(default = "default_server_error")]
+    status: u16,
+  },
+  /// The requests about the payment are answered after `delay_ms` of real time.
+  Delay { delay_ms: u64 },
+}
+
+impl Behavior {
+  pub(crate) fn delay(&self) -> Option<Duration> {
+    match self {
+      Behavior::Delay { delay_ms } => Some(Duration::from_millis(*delay_ms)),
+      _ => None,
+    }
+  }
+}
+
+fn default_server_error() -> u16 {
+  503
+}
+
+/// A behavior and the payments it applies to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "ScenarioFields")]
+pub struct Scenario {
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub msisdn: Option<Msisdn>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub amount: Option<Money>,
+  #[serde(flatten)]
+  pub behavior: Behavior,
+}
+
+impl Scenario {
+  /// Apply `behavior` to the payments from or to `msisdn`.
+  pub fn for_msisdn(msisdn: Msisdn, behavior: Behavior) -> Self {
+    Self {
+      msisdn: Some(msisdn),
+      amount: None,
+      behavior,
+    }
+  }
+
+  /// Apply `behavior` to the payments of `amount`.
+  pub fn for_amount(amount: Money, behavior: Behavior) -> Self {
+    Self {
+      msisdn: None,
+      amount: Some(amount),
+      behavior,
+    }
+  }
+
+  pub(crate) fn matches(&self, amount: Money, parties: &[&Party]) -> bool {
+    let msisdn_matches = self.msisdn.as_ref().is_none_or(|msisdn| {
+      parties
+        .iter()
+        .any(|party| party.to_msisdn().as_ref() == Some(msisdn))
+    });
+    msisdn_matches && self.amount.is_none_or(|expected| expected == amount)
+  }
+}
+
+/// A scenario as written in a file.
+///
+/// serde cannot deny the unknown fields of a struct with a flattened field,
+/// so the fields of the behavior are listed here, then checked by `Behavior`.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioFields {
+  #[serde(default)]
+  msisdn: Option<Msisdn>,
+  #[serde(default)]
+  amount: Option<Money>,
+  behavior: String,
+  count: Option<u32>,
+  status: Option<u16>,
+  after_secs: Option<u64>,
+  delay_ms: Option<u64>,
+}
+
+impl TryFrom<ScenarioFields> for Scenario {
+  type Error = serde_json::Error;
+
+  fn try_from(fields: ScenarioFields) -> Result<Self, Self::Error> {
+    let given = serde_json::json!({
+      "behavior": fields.behavior,
+      "count": fields.count,
+      "status": fields.status,
+      "after_secs": fields.after_secs,
+      "delay_ms": fields.delay_ms,
+    });
+    let Value::Object(mut given) = given else {
+      unreachable!("json! builds an object");
+    };
+    given.retain(|_, value| !value.is_null());
+    let behavior: Behavior = serde_json::from_value(Value::Object(given.clone()))?;
+
+    // The unit variants ignore the fields of the others.
+    let Value::Object(used) = serde_json::to_value(behavior)? else {
+      unreachable!("a behavior is serialized as an object");
+    };
+    if let Some(field) = given.keys().find(|field| !used.contains_key(*field)) {
+      return Err(de::Error::custom(format!(
+        "field `{}` does not apply to behavior `{}`",
+        field, fields.behavior
+      )));
+    }
+    Ok(Self {
+      msisdn: fields.msisdn,
+      amount: fields.amount,
+      behavior,
+    })
+  }
+}
+
+/// A list of scenarios, as loaded from a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenarios {
+  #[serde(default)]
+  pub scenarios: Vec<Scenario>,
+}
+
+/// Errors returned when loading scenarios.
+#[derive(Debug, thiserror::Error)]
+pub enum ScenarioError {
+  #[error("cannot read scenarios: {0}")]
+  Io(#[from] std::io::Error),
+  #[error("invalid JSON scenarios: {0}")]
+  Json(#[from] serde_json::Error),
+  #[error("invalid TOML scenarios: {0}")]
+  Toml(#[from] toml::de::Error),
+  /// The file is neither a `.toml` nor a `.json` file.
+  #[error("unsupported scenario file: {0}")]
+  UnsupportedFormat(String),
+}
+
+impl Scenarios {
+  pub fn from_json(json: &str) -> Result<Self, ScenarioError> {
+    Ok(serde_json::from_str(json)?)
+  }
+
+  pub fn from_toml(toml: &str) -> Result<Self, ScenarioError> {
+    Ok(toml::from_str(toml)?)
+  }
+
+  /// Load a `.toml` or `.json` file.
+  pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, ScenarioError> {
+    let path = path.as_ref();
+    let content = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|ext| ext.to_str()) {
+      Some("toml") => Self::from_toml(&content),
+      Some("json") => Self::from_json(&content),
+      _ => Err(ScenarioError::UnsupportedFormat(path.display().to_string())),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::testing::scenario::{Behavior, Scenario, ScenarioError, Scenarios};
+
+  #[test]
+  fn test_load() {
+    let toml = Scenarios::from_file("tests/fixtures/scenarios.toml").unwrap();
+    let json = Scenarios::from_file("tests/fixtures/scenarios.json").unwrap();
+    assert_eq!(toml, json);
+    assert_eq!(
+      toml.scenarios[0],
+      Scenario::for_msisdn(Msisdn::new("0340000001").unwrap(), Behavior::Reject)
+    );
+    assert_eq!(
+      toml.scenarios[3].behavior,
+      Behavior::ServerErrors {
+        count: 3,
+        status: 503
+      }
+    );
+    assert!(matches!(
+      Scenarios::from_file("README.md"),
+      Err(ScenarioError::UnsupportedFormat(_))
+    ));
+  }
+
+  #[test]
+  fn test_unknown_fields() {
+    let reject = r#"{"msisdn": "0340000001", "behavior": "reject"}"#;
+    for json in [
+      r#"{"msisdn": "0340000001", "behavior": "reject", "cuont": 3}"#,
+      r#"{"msisdn": "0340000001", "behavior": "reject", "count": 3}"#,
+      r#"{"amount": "666", "behavior": "server_errors", "count": 3, "after_secs": 60}"#,
+    ] {
+      let json = format!(r#"{{"scenarios": [{}]}}"#, json);
+      assert!(Scenarios::from_json(&json).is_err(), "{}", json);
+    }
+    let json = format!(r#"{{"scenarios": [{}], "typo": true}}"#, reject);
+    assert!(Scenarios::from_json(&json).is_err());
+    assert!(
+      Scenarios::from_toml("[[scenarios]]\nbehavior = \"reject\"\nmsidsn = \"0340000001\"")
+        .is_err()
+    );
+
+    let json = format!(r#"{{"scenarios": [{}]}}"#, reject);
+    let scenarios = Scenarios::from_json(&json).unwrap();
+    assert_eq!(scenarios.scenarios[0].behavior, Behavior::Reject);
+  }
+
+  #[test]
+  fn test_matches() {
+    let customer = Msisdn::new("0340000001").unwrap().to_party();
+    let merchant = Msisdn::new("0343500004").unwrap().to_party();
+    let parties = [&customer, &merchant];
+
+    let by_msisdn = Scenario::for_msisdn(Msisdn::new("+261340000001").unwrap(), Behavior::Reject);
+    assert!(by_msisdn.matches(Money::ariary(1000), &parties));
+    let by_amount = Scenario::for_amount(Money::ariary(666), Behavior::StuckPending);
+    assert!(!by_amount.matches(Money::ariary(1000), &parties));
+    assert!(by_amount.matches(Money::ariary(666), &parties));
+  }
+}
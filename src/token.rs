@@ -0,0 +1,161 @@
+use crate::auth::AuthService;
+use crate::error::Result;
+use crate::types::AuthResponse;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// How long before its expiry a token is considered stale and refreshed.
+const REFRESH_MARGIN: Duration = Duration::from_secs(60);
+
+struct CachedToken {
+  access_token: String,
+  refresh_at: Instant,
+}
+
+/// The token kept by a token manager, async or blocking: only the way they
+/// lock it and generate a new token differs.
+#[derive(Default)]
+pub(crate) struct TokenCache {
+  cached: Option<CachedToken>,
+}
+
+impl TokenCache {
+  /// The cached access token, unless it is missing or about to expire.
+  pub(crate) fn fresh(&self) -> Option<String> {
+    self
+      .cached
+      .as_ref()
+      .filter(|cached| Instant::now() < cached.refresh_at)
+      .map(|cached| cached.access_token.clone())
+  }
+
+  /// Keep a token requested at `requested_at`, and return its access token.
+  pub(crate) fn store(&mut self, res: AuthResponse, requested_at: Instant) -> String {
+    let expires_in = Duration::from_secs(res.expires_in);
+    let margin = REFRESH_MARGIN.min(expires_in / 2);
+    self.cached = Some(CachedToken {
+      access_token: res.access_token.clone(),
+      refresh_at: requested_at + expires_in - margin,
+    });
+    res.access_token
+  }
+
+  /// Drop the cached token if it is the one that was rejected.
+  pub(crate) fn invalidate(&mut self, rejected: &str) {
+    if self
+      .cached
+      .as_ref()
+      .is_some_and(|cached| cached.access_token == rejected)
+    {
+      self.cached = None;
+    }
+  }
+}
+
+/// Fetches an access token lazily and keeps it until shortly before it expires.
+///
+/// Concurrent callers share a single in-flight refresh: the first one generates
+/// the token while the others wait for it.
+pub struct TokenManager {
+  auth: AuthService,
+  consumer_key: String,
+  consumer_secret: String,
+  token: Mutex<TokenCache>,
+}
+
+impl TokenManager {
+  pub fn new(auth: AuthService, consumer_key: &str, consumer_secret: &str) -> Self {
+    Self {
+      auth,
+      consumer_key: String::from(consumer_key),
+      consumer_secret: String::from(consumer_secret),
+      token: Mutex::new(TokenCache::default()),
+    }
+  }
+
+  /// Get a valid access token, generating a new one if the cached token is
+  /// missing or about to expire.
+  /// # Returns
+  /// * `String` - The access token
+  /// # Errors
+  /// * `MVolaError` - If a new token cannot be generated
+  pub async fn access_token(&self) -> Result<String> {
+    let mut token = self.token.lock().await;
+    if let Some(access_token) = token.fresh() {
+      return Ok(access_token);
+    }
+
+    let requested_at = Instant::now();
+    let res = self
+      .auth
+      .generate_token(&self.consumer_key, &self.consumer_secret)
+      .await?;
+    Ok(token.store(res, requested_at))
+  }
+
+  /// Drop the cached token if it is the one that was rejected, so that the
+  /// next call to `access_token` generates a new one.
+  pub async fn invalidate(&self, rejected: &str) {
+    self.token.lock().await.invalidate(rejected);
+  }
+
+  #[cfg(test)]
+  pub(crate) async fn set_cached(&self, access_token: &str, valid_for: Duration) {
+    self.token.lock().await.cached = Some(CachedToken {
+      access_token: String::from(access_token),
+      refresh_at: Instant::now() + valid_for,
+    });
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::auth::AuthService;
+  use crate::token::TokenManager;
+  use mockito::{mock, SERVER_URL};
+  use std::sync::Arc;
+  use std::time::Duration;
+
+  const TOKEN_RESPONSE: &str = r#"{
+    "access_token": "fresh_token",
+    "expires_in": 3600,
+    "token_type": "Bearer",
+    "scope": "EXT_INT_MVOLA_SCOPE"
+  }"#;
+
+  #[tokio::test]
+  async fn test_token_is_cached() {
+    let m = mock("POST", "/cached/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .expect(1)
+      .create();
+
+    let auth = AuthService::new(&format!("{}/cached", SERVER_URL)).unwrap();
+    let manager = Arc::new(TokenManager::new(auth, "consumer_key", "consumer_secret"));
+
+    let (a, b) = tokio::join!(manager.access_token(), manager.access_token());
+    assert_eq!(a.unwrap(), "fresh_token");
+    assert_eq!(b.unwrap(), "fresh_token");
+    assert_eq!(manager.access_token().await.unwrap(), "fresh_token");
+    m.assert();
+  }
+
+  #[tokio::test]
+  async fn test_expired_token_is_refreshed() {
+    let m = mock("POST", "/expired/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(TOKEN_RESPONSE)
+      .expect(1)
+      .create();
+
+    let auth = AuthService::new(&format!("{}/expired", SERVER_URL)).unwrap();
+    let manager = TokenManager::new(auth, "consumer_key", "consumer_secret");
+    manager.set_cached("stale_token", Duration::ZERO).await;
+
+    assert_eq!(manager.access_token().await.unwrap(), "fresh_token");
+    m.assert();
+  }
+}
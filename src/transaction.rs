@@ -1,52 +1,128 @@
+use crate::callback::CallbackSigner;
+use crate::context::RequestContext;
+use crate::error::{MVolaError, Result};
+use crate::http::{default_transport, recv_json, HttpTransport, TransportConfig};
+use crate::idempotency::{self, IdempotencyStore};
+use crate::money::Money;
+use crate::poll::{Completion, PollPolicy, Poller};
+use crate::refund::RefundLedger;
+use crate::routes::{TransactionApi, TransactionType};
+use crate::service::{bearer, Authorization, Call, Core, Prepared};
+use crate::status::PaymentStatus;
+use crate::token::TokenManager;
 use crate::types::{
   Options, Service, TransactionDetails, TransactionRequest, TransactionResponse, TransactionStatus,
+  TypedRequest,
 };
-use std::str::FromStr;
-
-use std::time::Duration;
-use surf::http::auth::{AuthenticationScheme, Authorization};
-use surf::http::{Method, Mime};
-use surf::{Client, Config, Url};
+use futures::stream::{self, Stream};
+use serde::de::DeserializeOwned;
+use std::sync::Arc;
 
+/// Sends the transaction requests.
+///
+/// The service is cheap to clone: the clones share the same connection pool
+/// and settings, and can be used from many tasks at once.
+#[derive(Clone)]
 pub struct TransactionService {
-  client: Client,
-  base_url: String,
-  authorization: Option<Authorization>,
-  options: Options,
+  inner: Arc<Core<dyn HttpTransport, TokenManager>>,
+}
+
+/// State of the stream returned by `TransactionService::watch_with_policy`.
+struct Watch<'a> {
+  view: WithContext<'a>,
+  server_correlation_id: String,
+  poller: Poller,
+  last: Option<PaymentStatus>,
+  polled: bool,
+  done: bool,
 }
 
 impl Service for TransactionService {
-  fn set_authorization(&mut self, token: &str) {
-    self.authorization = Some(Authorization::new(
-      AuthenticationScheme::Bearer,
-      String::from(token),
-    ));
+  fn set_authorization(&self, token: &str) {
+    self
+      .inner
+      .update(|settings| settings.authorization = Some(bearer(token)));
   }
 
-  fn set_options(&mut self, options: Options) {
-    self.options = options;
+  fn set_options(&self, options: Options) {
+    self.inner.update(|settings| settings.options = options);
   }
 }
 
 impl TransactionService {
-  pub fn new(base_url: &str) -> Self {
-    let client = Config::new()
-      .set_timeout(Some(Duration::from_secs(5)))
-      .try_into()
-      .unwrap();
-    let options = Options {
-      version: String::from("1.0"),
-      correlation_id: String::from(""),
-      user_language: None,
-      user_account_identifier: String::from(""),
-      partner_name: None,
-      callback_url: None,
-    };
-    Self {
-      client,
-      base_url: String::from(base_url),
-      authorization: None,
-      options,
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  /// * `MVolaError::MissingConfiguration` - If no transport feature is enabled
+  pub fn new(base_url: &str) -> Result<Self> {
+    Self::with_transport(base_url, default_transport(&TransportConfig::default())?)
+  }
+
+  /// Send the requests with the given transport.
+  /// # Errors
+  /// * `MVolaError::InvalidUrl` - If `base_url` is not a valid URL
+  pub fn with_transport(base_url: &str, transport: Arc<dyn HttpTransport>) -> Result<Self> {
+    Ok(Self {
+      inner: Arc::new(Core::new(base_url, transport)?),
+    })
+  }
+
+  /// Use the given token manager to authorize the requests instead of the
+  /// token set with `set_authorization`.
+  ///
+  /// A request rejected with 401 is retried once with a new token.
+  pub fn set_token_manager(&self, token_manager: Arc<TokenManager>) {
+    self
+      .inner
+      .update(|settings| settings.token_manager = Some(token_manager));
+  }
+
+  /// Sign the callback URL of each payment with the given signer.
+  ///
+  /// The callback URL of the options is then only the base of the signed URLs.
+  pub fn set_callback_signer(&self, callback_signer: Arc<CallbackSigner>) {
+    self
+      .inner
+      .update(|settings| settings.callback_signer = Some(callback_signer));
+  }
+
+  /// Record the payments in the given store, so that a payment sent again
+  /// with the same idempotency key is not executed twice.
+  ///
+  /// See `mvola::idempotency`.
+  pub fn set_idempotency_store(&self, idempotency_store: Arc<dyn IdempotencyStore>) {
+    self
+      .inner
+      .update(|settings| settings.idempotency_store = Some(idempotency_store));
+  }
+
+  /// The default transaction type and API version of the requests.
+  ///
+  /// Defaults to merchant pay on version 1.0.0. `send_transaction` uses the
+  /// type of its request instead.
+  pub fn set_transaction_api(&self, api: TransactionApi) {
+    self.inner.update(|settings| settings.api = api);
+  }
+
+  /// The refunds sent by the service and its clones.
+  pub fn refunds(&self) -> &RefundLedger {
+    &self.inner.refunds
+  }
+
+  /// A view of the service sending its requests with the given context.
+  ///
+  /// The view borrows the service, so that a shared client can serve many
+  /// merchants or customers at once.
+  /// # Example
+  /// ```no_run
+  /// let context = RequestContext::new()
+  ///   .user_language("MG")
+  ///   .callback_url("https://shop.mg/mvola/callback");
+  /// let response = client.transaction.with_context(context).send_payment(tx).await;
+  /// ```
+  pub fn with_context(&self, context: RequestContext) -> WithContext<'_> {
+    WithContext {
+      service: self,
+      context,
     }
   }
 
@@ -56,12 +132,13 @@ impl TransactionService {
   /// # Returns
   /// * `TransactionDetails` - The details of the transaction
   /// # Errors
-  /// * `surf::Error` - If the request fails
+  /// * `MVolaError::Api` - If MVola rejects the request, with the parsed error body
+  /// * `MVolaError` - If the request fails
   /// # Example
   /// ```no_run
   /// #[tokio::main]
   /// async fn main() {
-  ///  let mut client = MVola::new(SANDBOX_URL);
+  ///  let client = MVola::new(SANDBOX_URL).unwrap();
   ///  let auth = client
   ///    .auth
   ///    .generate_token(
@@ -74,7 +151,6 @@ impl TransactionService {
   ///    .set_authorization(&auth.unwrap().access_token);
   ///  client.transaction.set_options(Options {
   ///    version: String::from("1.0"),
-  ///    correlation_id: Uuid::new_v4().to_string(),
   ///    user_language: None,
   ///    user_account_identifier: String::from("msisdn;0343500003"),
   ///    partner_name: None,
@@ -84,28 +160,11 @@ impl TransactionService {
   ///  println!("{:#?}", response);
   ///}
   /// ```
-  pub async fn get_transaction(&self, id: &str) -> Result<TransactionDetails, surf::Error> {
-    let path = format!(
-      "{}/mvola/mm/transactions/type/merchantpay/1.0.0/{}",
-      self.base_url, id
-    );
-    let url = Url::parse(&path).unwrap();
-    let mut req = surf::Request::new(Method::Get, url.clone());
-    req.set_header(
-      "Authorization",
-      self.authorization.as_ref().unwrap().value(),
-    );
-    req.set_header("Accept", "application/json");
-    req.set_header("Version", self.options.version.as_str());
-    req.set_header("X-CorrelationID", self.options.correlation_id.as_str());
-    req.set_header("Cache-Control", "no-cache");
-    req.set_header(
-      "UserAccountIdentifier",
-      self.options.user_account_identifier.as_str(),
-    );
-
-    let res = self.client.recv_json(req).await?;
-    Ok(res)
+  pub async fn get_transaction(&self, id: &str) -> Result<TransactionDetails> {
+    self
+      .with_context(RequestContext::new())
+      .get_transaction(id)
+      .await
   }
 
   /// Get the status of a transaction
@@ -115,12 +174,13 @@ impl TransactionService {
   /// # Returns
   /// * `TransactionStatus` - The object containing the status of the transaction
   /// # Errors
-  /// * `surf::Error` - If the request fails
+  /// * `MVolaError::Api` - If MVola rejects the request, with the parsed error body
+  /// * `MVolaError` - If the request fails
   /// # Example
   /// ```no_run
   /// #[tokio::main]
   /// async fn main() {
-  ///  let mut client = MVola::new(SANDBOX_URL);
+  ///  let client = MVola::new(SANDBOX_URL).unwrap();
   ///  let auth = client
   ///    .auth
   ///    .generate_token(
@@ -133,7 +193,6 @@ impl TransactionService {
   ///    .set_authorization(&auth.unwrap().access_token);
   ///  client.transaction.set_options(Options {
   ///    version: String::from("1.0"),
-  ///    correlation_id: Uuid::new_v4().to_string(),
   ///    user_language: Some("FR".to_string()),
   ///    user_account_identifier: String::from("msisdn;0343500003"),
   ///    partner_name: Some("TestMVola".to_string()),
@@ -146,53 +205,110 @@ impl TransactionService {
   ///  println!("{:#?}", response);
   /// }
   /// ```
-
   pub async fn get_transaction_status(
     &self,
     server_correlation_id: &str,
-  ) -> Result<TransactionStatus, surf::Error> {
-    let path = format!(
-      "{}/mvola/mm/transactions/type/merchantpay/1.0.0/status/{}",
-      self.base_url, server_correlation_id
-    );
-    let url = Url::parse(&path).unwrap();
-    let mut req = surf::Request::new(Method::Get, url.clone());
-    req.set_header(
-      "Authorization",
-      self.authorization.as_ref().unwrap().value(),
-    );
-    req.set_header("Version", self.options.version.as_str());
-    req.set_header("X-CorrelationID", self.options.correlation_id.as_str());
-    req.set_header(
-      "UserLanguage",
-      self.options.user_language.as_ref().unwrap().as_str(),
-    );
-    req.set_header(
-      "PartnerName",
-      self.options.partner_name.as_ref().unwrap().as_str(),
-    );
-    req.set_header("Cache-Control", "no-cache");
-    req.set_header(
-      "UserAccountIdentifier",
-      self.options.user_account_identifier.as_str(),
-    );
+  ) -> Result<TransactionStatus> {
+    self
+      .with_context(RequestContext::new())
+      .get_transaction_status(server_correlation_id)
+      .await
+  }
+
+  /// Poll the status of a transaction until it is completed or failed.
+  ///
+  /// The details of the transaction are fetched once it is completed.
+  /// # Arguments
+  /// * `server_correlation_id` - The id returned by `send_payment`
+  /// * `policy` - The interval, backoff, jitter and deadline of the polling
+  ///
+  /// # Returns
+  /// * `Completion` - The final status, the object reference and the details
+  /// # Errors
+  /// * `MVolaError::DeadlineExceeded` - If the transaction is not final before the deadline
+  /// * `MVolaError` - If a request fails
+  /// # Example
+  /// ```no_run
+  /// let response = client.transaction.send_payment(tx).await.unwrap();
+  /// let completion = client
+  ///   .transaction
+  ///   .wait_for_completion(&response.server_correlation_id, &PollPolicy::default())
+  ///   .await
+  ///   .unwrap();
+  /// if completion.status.is_success() {
+  ///   println!("{:#?}", completion.details);
+  /// }
+  /// ```
+  pub async fn wait_for_completion(
+    &self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> Result<Completion> {
+    self
+      .with_context(RequestContext::new())
+      .wait_for_completion(server_correlation_id, policy)
+      .await
+  }
+
+  /// Watch the status of a transaction with the default `PollPolicy`.
+  ///
+  /// See `watch_with_policy`.
+  pub fn watch<'a>(
+    &'a self,
+    server_correlation_id: &str,
+  ) -> impl Stream<Item = Result<TransactionStatus>> + 'a {
+    self.watch_with_policy(server_correlation_id, &PollPolicy::default())
+  }
 
-    let res: TransactionStatus = self.client.recv_json(req).await?;
-    Ok(res)
+  /// Poll the status of a transaction and yield each change of status.
+  ///
+  /// The stream ends after a completed or failed status, after a failed
+  /// request, or with `MVolaError::DeadlineExceeded` when the deadline of the
+  /// policy is reached. Nothing runs in the background: dropping the stream
+  /// stops the polling.
+  /// # Arguments
+  /// * `server_correlation_id` - The id returned by `send_payment`
+  /// * `policy` - The interval, backoff, jitter and deadline of the polling
+  /// # Example
+  /// ```no_run
+  /// let events = client
+  ///   .transaction
+  ///   .watch_with_policy(&response.server_correlation_id, &PollPolicy::default());
+  /// futures::pin_mut!(events);
+  /// while let Some(status) = events.next().await {
+  ///   println!("{}", status.unwrap().status);
+  /// }
+  /// ```
+  pub fn watch_with_policy<'a>(
+    &'a self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> impl Stream<Item = Result<TransactionStatus>> + 'a {
+    self
+      .with_context(RequestContext::new())
+      .watch_with_policy(server_correlation_id, policy)
   }
 
   /// Send a transaction
+  ///
+  /// With an idempotency store, a transaction whose idempotency key was
+  /// already accepted is not sent again: the response is built from its
+  /// current status. See `mvola::idempotency`.
   /// # Arguments
   /// * `tx` - The transaction to send
   /// # Returns
   /// * `TransactionResponse` - The response of the transaction
   /// # Errors
-  /// * `surf::Error` - If the request fails
+  /// * `MVolaError::InvalidAmount` - If the amount is not a positive, whole amount of Ariary
+  /// * `MVolaError::Api` - If MVola rejects the request, with the parsed error body
+  /// * `MVolaError::IndeterminatePayment` - If the same idempotency key is being sent, or
+  ///   was sent without an answer
+  /// * `MVolaError` - If the request fails
   /// # Example
   /// ```no_run
   /// #[tokio::main]
   /// async fn main() {
-  ///  let mut client = MVola::new(SANDBOX_URL);
+  ///  let client = MVola::new(SANDBOX_URL).unwrap();
   ///  let auth = client
   ///    .auth
   ///    .generate_token(
@@ -205,110 +321,339 @@ impl TransactionService {
   ///    .set_authorization(&auth.unwrap().access_token);
   ///  client.transaction.set_options(Options {
   ///    version: String::from("1.0"),
-  ///    correlation_id: Uuid::new_v4().to_string(),
   ///    user_language: Some("FR".to_string()),
   ///    user_account_identifier: String::from("msisdn;0343500004"),
   ///    partner_name: Some("TestMVola".to_string()),
   ///    callback_url: None,
   ///  });
-  ///  let transaction_ref = Uuid::new_v4();
-  ///
-  ///  let now = SystemTime::now();
-  ///  let now: DateTime<Utc> = now.into();
-  ///  let now = now.to_rfc3339_opts(SecondsFormat::Millis, true);
-  ///
-  ///  let tx: TransactionRequest = TransactionRequest {
-  ///    amount: String::from("1000"),
-  ///    currency: String::from("Ar"),
-  ///    description_text: String::from("test"),
-  ///    request_date: now.to_string(),
-  ///    debit_party: vec![KeyValue {
-  ///      key: String::from("msisdn"),
-  ///      value: String::from("0343500003"),
-  ///    }],
-  ///    credit_party: vec![KeyValue {
-  ///      key: String::from("msisdn"),
-  ///      value: String::from("0343500004"),
-  ///    }],
-  ///    metadata: vec![
-  ///      KeyValue {
-  ///        key: String::from("partnerName"),
-  ///        value: String::from("TestMVola"),
-  ///      },
-  ///      KeyValue {
-  ///        key: String::from("fc"),
-  ///        value: String::from("USD"),
-  ///      },
-  ///      KeyValue {
-  ///        key: String::from("amountFc"),
-  ///        value: String::from("1"),
-  ///      },
-  ///    ],
-  ///
-  ///    requesting_organisation_transaction_reference: transaction_ref.to_string(),
-  ///    original_transaction_reference: transaction_ref.to_string(),
-  ///  };
+  ///  let tx = TransactionRequest::builder()
+  ///    .amount(Money::ariary(1000))
+  ///    .debit(Msisdn::new("0343500003").unwrap())
+  ///    .credit(Msisdn::new("0343500004").unwrap())
+  ///    .description("test")
+  ///    .partner_name("TestMVola")
+  ///    .foreign_currency("USD", Money::ariary(1))
+  ///    .build()
+  ///    .unwrap();
   ///  let response = client.transaction.send_payment(tx).await;
   ///  println!("{:#?}", response);
   /// }
   /// ```
-  pub async fn send_payment(
+  pub async fn send_payment(&self, tx: TransactionRequest) -> Result<TransactionResponse> {
+    self
+      .with_context(RequestContext::new())
+      .send_payment(tx)
+      .await
+  }
+
+  /// Send a transaction to the endpoint of its type.
+  ///
+  /// `send_payment` sends to the default type of the service, this method to
+  /// the type of the request, e.g. `disbursement` for a `DisbursementRequest`.
+  /// # Arguments
+  /// * `tx` - The typed transaction to send
+  /// # Errors
+  /// * `MVolaError::InvalidAmount` - If the amount is not a positive, whole amount of Ariary
+  /// * `MVolaError::Api` - If MVola rejects the request, with the parsed error body
+  /// * `MVolaError` - If the request fails
+  /// # Example
+  /// ```no_run
+  /// let tx = TransactionRequest::builder()
+  ///   .amount(Money::ariary(1000))
+  ///   .debit(Msisdn::new("0343500004").unwrap())
+  ///   .credit(Msisdn::new("0343500003").unwrap())
+  ///   .description("Cashback")
+  ///   .build()
+  ///   .unwrap();
+  /// let response = client
+  ///   .transaction
+  ///   .send_transaction(DisbursementRequest(tx))
+  ///   .await;
+  /// ```
+  pub async fn send_transaction<T: TypedRequest>(&self, tx: T) -> Result<TransactionResponse> {
+    self
+      .with_context(RequestContext::new())
+      .send_transaction(tx)
+      .await
+  }
+
+  /// Refund part or all of a completed transaction.
+  ///
+  /// The refund is sent like `send_payment`, from the party credited by the
+  /// original transaction to the party debited by it, with the reference of
+  /// the original transaction as `originalTransactionReference`. The amount
+  /// is counted in `refunds` as soon as it is sent, and only released if MVola
+  /// rejects the refund.
+  /// # Arguments
+  /// * `original` - The details of the completed transaction to refund
+  /// * `amount` - The amount to refund, at most what is left to refund
+  /// # Returns
+  /// * `TransactionResponse` - The response of the refund transaction
+  /// # Errors
+  /// * `MVolaError::NotRefundable` - If the original transaction is not completed
+  /// * `MVolaError::RefundExceedsOriginal` - If the refunds would exceed the original amount
+  /// * `MVolaError::InvalidAmount` - If the amount is not a positive, whole amount of Ariary
+  /// * `MVolaError` - If the request fails
+  /// # Example
+  /// ```no_run
+  /// let original = client.transaction.get_transaction("636042511").await.unwrap();
+  /// let response = client.transaction.refund(&original, Money::ariary(500)).await;
+  /// println!("{} left", client.transaction.refunds().remaining(&original));
+  /// ```
+  pub async fn refund(
     &self,
-    tx: TransactionRequest,
-  ) -> Result<TransactionResponse, surf::Error> {
-    let path = format!(
-      "{}/mvola/mm/transactions/type/merchantpay/1.0.0/",
-      self.base_url
-    );
-    let url = Url::parse(&path).unwrap();
-    let mut req = surf::Request::new(Method::Post, url.clone());
-    req.set_header(
-      "Authorization",
-      self.authorization.as_ref().unwrap().value(),
-    );
-    req.set_header("Accept", "application/json");
-    req.set_header("Version", self.options.version.as_str());
-    req.set_header("X-CorrelationID", self.options.correlation_id.as_str());
-    req.set_header(
-      "UserLanguage",
-      self.options.user_language.as_ref().unwrap().as_str(),
-    );
-    req.set_header(
-      "PartnerName",
-      self.options.partner_name.as_ref().unwrap().as_str(),
+    original: &TransactionDetails,
+    amount: Money,
+  ) -> Result<TransactionResponse> {
+    self
+      .with_context(RequestContext::new())
+      .refund(original, amount)
+      .await
+  }
+
+  /// Send a call with the current authorization.
+  ///
+  /// With a token manager, a request rejected with 401 is rebuilt and sent
+  /// once more with a new token.
+  async fn execute<T: DeserializeOwned>(&self, call: &Prepared<'_, TokenManager>) -> Result<T> {
+    let transport = self.inner.transport.as_ref();
+    let base_url = &self.inner.base_url;
+    let token_manager = match call.authorization()? {
+      Authorization::Fixed(authorization) => {
+        return recv_json(transport, call.request(base_url, authorization)?).await
+      }
+      Authorization::Managed(token_manager) => token_manager,
+    };
+
+    let token = token_manager.access_token().await?;
+    match recv_json(transport, call.request(base_url, &bearer(&token))?).await {
+      Err(MVolaError::Authentication { .. }) => {
+        token_manager.invalidate(&token).await;
+        let token = token_manager.access_token().await?;
+        recv_json(transport, call.request(base_url, &bearer(&token))?).await
+      }
+      res => res,
+    }
+  }
+}
+
+/// A `TransactionService` sending its requests with a `RequestContext`.
+///
+/// See `TransactionService::with_context`.
+pub struct WithContext<'a> {
+  service: &'a TransactionService,
+  context: RequestContext,
+}
+
+impl<'a> WithContext<'a> {
+  /// Get the details of a transaction.
+  ///
+  /// See `TransactionService::get_transaction`.
+  pub async fn get_transaction(&self, id: &str) -> Result<TransactionDetails> {
+    let settings = self.service.inner.settings();
+    let call = Prepared::new(&settings, &self.context, None, Call::Details(id));
+    self.service.execute(&call).await
+  }
+
+  /// Get the status of a transaction.
+  ///
+  /// See `TransactionService::get_transaction_status`.
+  pub async fn get_transaction_status(
+    &self,
+    server_correlation_id: &str,
+  ) -> Result<TransactionStatus> {
+    let settings = self.service.inner.settings();
+    let call = Prepared::new(
+      &settings,
+      &self.context,
+      None,
+      Call::Status(server_correlation_id),
     );
-    req.set_header("Cache-Control", "no-cache");
-    req.set_header(
-      "UserAccountIdentifier",
-      self.options.user_account_identifier.as_str(),
+    self.service.execute(&call).await
+  }
+
+  /// Send a transaction.
+  ///
+  /// See `TransactionService::send_payment`.
+  pub async fn send_payment(&self, tx: TransactionRequest) -> Result<TransactionResponse> {
+    self.send(None, &tx).await
+  }
+
+  /// Send a transaction to the endpoint of its type.
+  ///
+  /// See `TransactionService::send_transaction`.
+  pub async fn send_transaction<T: TypedRequest>(&self, tx: T) -> Result<TransactionResponse> {
+    self.send(Some(tx.transaction_type()), tx.request()).await
+  }
+
+  /// Refund part or all of a completed transaction.
+  ///
+  /// See `TransactionService::refund`.
+  pub async fn refund(
+    &self,
+    original: &TransactionDetails,
+    amount: Money,
+  ) -> Result<TransactionResponse> {
+    let refunds = &self.service.inner.refunds;
+    let tx = refunds.begin_refund(original, amount)?;
+    let res = self.send(None, &tx).await;
+    refunds.finish_refund(original, amount, &res);
+    res
+  }
+
+  /// Send a transaction, unless its idempotency key was already sent.
+  async fn send(
+    &self,
+    transaction_type: Option<TransactionType>,
+    tx: &TransactionRequest,
+  ) -> Result<TransactionResponse> {
+    let settings = self.service.inner.settings();
+    let context = &self.context;
+    let payment = Prepared::new(
+      &settings,
+      context,
+      transaction_type.clone(),
+      Call::Payment(tx),
     );
+    let Some(store) = &settings.idempotency_store else {
+      return self.service.execute(&payment).await;
+    };
 
-    if self.options.callback_url != None {
-      req.set_header(
-        "X-Callback-URL",
-        self.options.callback_url.as_ref().unwrap(),
-      );
+    let idempotency_key = context.idempotency_key_of(tx);
+    let store = store.as_ref();
+    if let Some(server_correlation_id) = idempotency::begin_payment(store, &idempotency_key)? {
+      let call = Call::Status(&server_correlation_id);
+      let status: TransactionStatus = self
+        .service
+        .execute(&Prepared::new(&settings, context, transaction_type, call))
+        .await?;
+      return Ok(TransactionResponse::from(status));
     }
+    let res = self.service.execute(&payment).await;
+    idempotency::finish_payment(store, &idempotency_key, &res);
+    res
+  }
+
+  /// Poll the status of a transaction until it is completed or failed.
+  ///
+  /// See `TransactionService::wait_for_completion`.
+  pub async fn wait_for_completion(
+    &self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> Result<Completion> {
+    let mut poller = policy.start();
+    let status = loop {
+      let status = self.get_transaction_status(server_correlation_id).await?;
+      match poller.after(server_correlation_id, &status.status)? {
+        Some(delay) => tokio::time::sleep(delay).await,
+        None => break status,
+      }
+    };
 
-    req.set_content_type(Mime::from_str("application/json").unwrap());
-    req.body_json(&tx).unwrap();
-    let res: TransactionResponse = self.client.recv_json(req).await?;
-    Ok(res)
+    let details = if status.status.is_success() {
+      Some(self.get_transaction(&status.object_reference).await?)
+    } else {
+      None
+    };
+    Ok(Completion::new(status, details))
+  }
+
+  /// Poll the status of a transaction and yield each change of status.
+  ///
+  /// See `TransactionService::watch_with_policy`.
+  pub fn watch_with_policy(
+    &self,
+    server_correlation_id: &str,
+    policy: &PollPolicy,
+  ) -> impl Stream<Item = Result<TransactionStatus>> + 'a {
+    let state = Watch {
+      view: self.service.with_context(self.context.clone()),
+      server_correlation_id: String::from(server_correlation_id),
+      poller: policy.start(),
+      last: None,
+      polled: false,
+      done: false,
+    };
+    stream::unfold(state, |mut state| async move {
+      if state.done {
+        return None;
+      }
+      loop {
+        if state.polled {
+          match state.poller.next_delay() {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => {
+              state.done = true;
+              let err = MVolaError::DeadlineExceeded {
+                server_correlation_id: state.server_correlation_id.clone(),
+                status: state.last.clone().unwrap_or(PaymentStatus::Pending),
+              };
+              return Some((Err(err), state));
+            }
+          }
+        }
+        state.polled = true;
+
+        let status = match state
+          .view
+          .get_transaction_status(&state.server_correlation_id)
+          .await
+        {
+          Ok(status) => status,
+          Err(err) => {
+            state.done = true;
+            return Some((Err(err), state));
+          }
+        };
+        if state.last.as_ref() != Some(&status.status) {
+          state.last = Some(status.status.clone());
+          state.done = status.status.is_final();
+          return Some((Ok(status), state));
+        }
+      }
+    })
   }
 }
 
 #[cfg(test)]
 mod tests {
+  use crate::auth::AuthService;
+  use crate::context::RequestContext;
+  use crate::error::{MVolaError, Result};
+  use crate::http::{HttpRequest, HttpResponse, HttpTransport, Method};
+  use crate::idempotency::{Attempt, IdempotencyStore, InMemoryIdempotencyStore};
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::party::{Party, TransactionMetadata};
+  use crate::poll::PollPolicy;
+  use crate::routes::{ApiVersion, TransactionApi, TransactionType};
+  use crate::status::{NotificationMethod, PaymentStatus};
+  use crate::token::TokenManager;
   use crate::transaction::TransactionService;
-  use crate::types::KeyValue;
   use crate::types::Options;
   use crate::types::Service;
+  use crate::types::TransactionDetails;
   use crate::types::TransactionRequest;
+  use crate::types::TransferRequest;
+  use async_trait::async_trait;
   use chrono::{DateTime, SecondsFormat, Utc};
-  use mockito::{mock, SERVER_URL};
-  use std::time::SystemTime;
+  use futures::StreamExt;
+  use mockito::{mock, Matcher, SERVER_URL};
+  use std::sync::{Arc, Mutex};
+  use std::time::{Duration, SystemTime};
   use uuid::Uuid;
 
+  fn options() -> Options {
+    Options {
+      version: String::from("1.0"),
+      user_language: None,
+      user_account_identifier: String::from("msisdn;0343500003"),
+      partner_name: None,
+      callback_url: None,
+    }
+  }
+
   #[tokio::test]
   async fn test_send_payment() {
     let _m = mock("POST", "/mvola/mm/transactions/type/merchantpay/1.0.0/")
@@ -323,56 +668,38 @@ mod tests {
     let now: DateTime<Utc> = now.into();
     let now = now.to_rfc3339_opts(SecondsFormat::Millis, true);
 
+    let mut metadata = TransactionMetadata::default();
+    metadata.partner_name = Some(String::from("TestMVola"));
+    metadata.foreign_currency = Some(String::from("USD"));
+    metadata.amount_foreign_currency = Some(String::from("1"));
     let tx: TransactionRequest = TransactionRequest {
-      amount: String::from("1000"),
+      amount: Money::ariary(1000),
       currency: String::from("Ar"),
       description_text: String::from("test"),
       request_date: now.to_string(),
-      debit_party: vec![KeyValue {
-        key: String::from("msisdn"),
-        value: String::from("0343500003"),
-      }],
-      credit_party: vec![KeyValue {
-        key: String::from("msisdn"),
-        value: String::from("0343500004"),
-      }],
-      metadata: vec![
-        KeyValue {
-          key: String::from("partnerName"),
-          value: String::from("TestMVola"),
-        },
-        KeyValue {
-          key: String::from("fc"),
-          value: String::from("USD"),
-        },
-        KeyValue {
-          key: String::from("amountFc"),
-          value: String::from("1"),
-        },
-      ],
+      debit_party: Msisdn::new("0343500003").unwrap().to_party(),
+      credit_party: Msisdn::new("0343500004").unwrap().to_party(),
+      metadata,
 
       requesting_organisation_transaction_reference: transaction_ref.to_string(),
       original_transaction_reference: transaction_ref.to_string(),
     };
-    let mut client = TransactionService::new(SERVER_URL);
+    let client = TransactionService::new(SERVER_URL).unwrap();
 
     client.set_authorization("access token");
     client.set_options(Options {
-      version: String::from("1.0"),
-      correlation_id: Uuid::new_v4().to_string(),
       user_language: Some("FR".to_string()),
-      user_account_identifier: String::from("msisdn;0343500003"),
       partner_name: Some("TestMVola".to_string()),
-      callback_url: None,
+      ..options()
     });
 
     let response = client.send_payment(tx).await.unwrap();
-    assert_eq!(response.status, "pending");
+    assert_eq!(response.status, PaymentStatus::Pending);
     assert_eq!(
       response.server_correlation_id,
       "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3"
     );
-    assert_eq!(response.notification_method, "polling");
+    assert_eq!(response.notification_method, NotificationMethod::Polling);
   }
 
   #[tokio::test]
@@ -386,16 +713,13 @@ mod tests {
     .with_body_from_file("tests/fixtures/transaction_status.json")
     .create();
 
-    let mut client = TransactionService::new(SERVER_URL);
+    let client = TransactionService::new(SERVER_URL).unwrap();
 
     client.set_authorization("access token");
     client.set_options(Options {
-      version: String::from("1.0"),
-      correlation_id: Uuid::new_v4().to_string(),
       user_language: Some("FR".to_string()),
-      user_account_identifier: String::from("msisdn;0343500003"),
       partner_name: Some("TestMVola".to_string()),
-      callback_url: None,
+      ..options()
     });
 
     let response = client
@@ -403,13 +727,136 @@ mod tests {
       .await
       .unwrap();
 
-    assert_eq!(response.status, "completed");
+    assert_eq!(response.status, PaymentStatus::Completed);
     assert_eq!(
       response.server_correlation_id,
       "2ba1d66a-25cf-4c12-8a6f-4cb01255148e"
     );
   }
 
+  #[tokio::test]
+  async fn test_wait_for_completion() {
+    let _status = mock(
+      "GET",
+      "/wait/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+    let details = mock(
+      "GET",
+      "/wait/mvola/mm/transactions/type/merchantpay/1.0.0/636042511",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_details.json")
+    .create();
+
+    let client = TransactionService::new(&format!("{}/wait", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let completion = client
+      .wait_for_completion(
+        "2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+        &PollPolicy::default(),
+      )
+      .await
+      .unwrap();
+
+    assert_eq!(completion.status, PaymentStatus::Completed);
+    assert_eq!(completion.object_reference, "636042511");
+    assert_eq!(completion.details.unwrap().amount, Money::ariary(10000));
+    details.assert();
+  }
+
+  #[tokio::test]
+  async fn test_wait_for_completion_deadline() {
+    let _m = mock(
+      "GET",
+      "/wait-deadline/mvola/mm/transactions/type/merchantpay/1.0.0/status/a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body(
+      r#"{
+          "status": "pending",
+          "serverCorrelationId": "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+          "notificationMethod": "polling"
+      }"#,
+    )
+    .create();
+
+    let client = TransactionService::new(&format!("{}/wait-deadline", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let policy = PollPolicy::fixed(Duration::from_millis(10), Duration::from_millis(100));
+    let err = client
+      .wait_for_completion("a6b5569b-6181-4fc9-bee3-b9f928dd7ae3", &policy)
+      .await
+      .unwrap_err();
+
+    match err {
+      MVolaError::DeadlineExceeded { status, .. } => assert_eq!(status, PaymentStatus::Pending),
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_watch() {
+    let _m = mock(
+      "GET",
+      "/watch/mvola/mm/transactions/type/merchantpay/1.0.0/status/2ba1d66a-25cf-4c12-8a6f-4cb01255148e",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+
+    let client = TransactionService::new(&format!("{}/watch", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let events: Vec<_> = client
+      .watch("2ba1d66a-25cf-4c12-8a6f-4cb01255148e")
+      .collect()
+      .await;
+
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].as_ref().unwrap().status, PaymentStatus::Completed);
+  }
+
+  #[tokio::test]
+  async fn test_watch_only_yields_transitions() {
+    let _m = mock(
+      "GET",
+      "/watch-pending/mvola/mm/transactions/type/merchantpay/1.0.0/status/a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_response.json")
+    .create();
+
+    let client = TransactionService::new(&format!("{}/watch-pending", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let policy = PollPolicy::fixed(Duration::from_millis(10), Duration::from_millis(100));
+    let events: Vec<_> = client
+      .watch_with_policy("a6b5569b-6181-4fc9-bee3-b9f928dd7ae3", &policy)
+      .collect()
+      .await;
+
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0].as_ref().unwrap().status, PaymentStatus::Pending);
+    assert!(matches!(
+      events[1],
+      Err(MVolaError::DeadlineExceeded { .. })
+    ));
+  }
+
   #[tokio::test]
   async fn test_get_transaction() {
     let _m = mock(
@@ -421,23 +868,667 @@ mod tests {
     .with_body_from_file("tests/fixtures/transaction_details.json")
     .create();
 
-    let mut client = TransactionService::new(SERVER_URL);
+    let client = TransactionService::new(SERVER_URL).unwrap();
 
     client.set_authorization("access token");
-    client.set_options(Options {
-      version: String::from("1.0"),
-      correlation_id: Uuid::new_v4().to_string(),
-      user_language: None,
-      user_account_identifier: String::from("msisdn;0343500003"),
-      partner_name: None,
-      callback_url: None,
-    });
+    client.set_options(options());
 
     let response = client
       .get_transaction("3A5C5E20-B2D9-449F-BBD6-2367A684E9C4")
       .await
       .unwrap();
 
-    assert_eq!(response.amount, "10000.00");
+    assert_eq!(response.amount, Money::ariary(10000));
+    assert_eq!(response.transaction_status, PaymentStatus::Completed);
+    assert_eq!(response.total_fees(), Money::ariary(150));
+    assert_eq!(response.total_with_fees(), Money::ariary(10150));
+  }
+
+  #[tokio::test]
+  async fn test_get_transaction_api_error() {
+    let _m = mock(
+      "GET",
+      "/mvola/mm/transactions/type/merchantpay/1.0.0/8D3B8D0C-0C34-4C5B-9A36-2C4A0C1D5B7E",
+    )
+    .with_status(400)
+    .with_header("Content-Type", "application/json")
+    .with_header("X-CorrelationID", "7b2a8e0a-4c1f-4a53-9f57-3a0f7c6c2d11")
+    .with_body_from_file("tests/fixtures/error_response.json")
+    .create();
+
+    let client = TransactionService::new(SERVER_URL).unwrap();
+
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let err = client
+      .get_transaction("8D3B8D0C-0C34-4C5B-9A36-2C4A0C1D5B7E")
+      .await
+      .unwrap_err();
+
+    match err {
+      MVolaError::Api(err) => {
+        assert_eq!(err.status, 400);
+        assert_eq!(
+          err.correlation_id.as_deref(),
+          Some("7b2a8e0a-4c1f-4a53-9f57-3a0f7c6c2d11")
+        );
+        assert_eq!(err.description(), Some("Insufficient funds"));
+        let error = err.error.unwrap();
+        assert_eq!(error.error_category, "businessRule");
+        assert_eq!(error.error_code, "genericError");
+        assert_eq!(error.error_parameters[0].key, "mmErrorCode");
+        assert_eq!(error.error_parameters[0].value, "4001");
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_rejected_token_is_refreshed() {
+    let path =
+      "/retry/mvola/mm/transactions/type/merchantpay/1.0.0/4F1C7E9A-3B2D-4E8F-A6C5-D9B0E1F2A3B4";
+    let _token = mock("POST", "/retry/token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body(
+        r#"{
+            "access_token": "fresh_token",
+            "expires_in": 3600,
+            "token_type": "Bearer",
+            "scope": "EXT_INT_MVOLA_SCOPE"
+        }"#,
+      )
+      .expect(1)
+      .create();
+    let rejected = mock("GET", path)
+      .match_header("Authorization", "Bearer stale_token")
+      .with_status(401)
+      .with_header("Content-Type", "application/json")
+      .with_body(r#"{"fault":{"code":900901,"message":"Invalid Credentials"}}"#)
+      .expect(1)
+      .create();
+    let accepted = mock("GET", path)
+      .match_header("Authorization", "Bearer fresh_token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_details.json")
+      .expect(1)
+      .create();
+
+    let base_url = format!("{}/retry", SERVER_URL);
+    let token_manager = Arc::new(TokenManager::new(
+      AuthService::new(&base_url).unwrap(),
+      "consumer_key",
+      "consumer_secret",
+    ));
+    token_manager
+      .set_cached("stale_token", Duration::from_secs(3600))
+      .await;
+
+    let client = TransactionService::new(&base_url).unwrap();
+    client.set_token_manager(token_manager);
+    client.set_options(options());
+
+    let response = client
+      .get_transaction("4F1C7E9A-3B2D-4E8F-A6C5-D9B0E1F2A3B4")
+      .await
+      .unwrap();
+
+    assert_eq!(response.amount, Money::ariary(10000));
+    rejected.assert();
+    accepted.assert();
+  }
+
+  #[tokio::test]
+  async fn test_missing_configuration() {
+    let client = TransactionService::new(SERVER_URL).unwrap();
+
+    let err = client
+      .get_transaction_status("05AB2C4F-E0E6-42AD-8FA4-9807BDF348BE")
+      .await
+      .unwrap_err();
+
+    match err {
+      MVolaError::MissingConfiguration(fields) => {
+        assert_eq!(fields, vec!["authorization", "user_account_identifier"]);
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_invalid_amount() {
+    let client = TransactionService::new(SERVER_URL).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let tx = TransactionRequest {
+      amount: Money::from_hundredths(1050),
+      currency: String::from("Ar"),
+      description_text: String::from("test"),
+      request_date: String::from("2022-03-10T10:00:00.000Z"),
+      debit_party: Party::default(),
+      credit_party: Party::default(),
+      metadata: TransactionMetadata::default(),
+      requesting_organisation_transaction_reference: Uuid::new_v4().to_string(),
+      original_transaction_reference: Uuid::new_v4().to_string(),
+    };
+
+    let err = client.send_payment(tx).await.unwrap_err();
+    assert!(matches!(err, MVolaError::InvalidAmount(_)));
+  }
+
+  #[tokio::test]
+  async fn test_optional_headers_are_left_out() {
+    let _m = mock(
+      "GET",
+      "/mvola/mm/transactions/type/merchantpay/1.0.0/status/9C1E5F3A-7D2B-4A6E-8F0C-1B3D5E7A9C2F",
+    )
+    .match_header("UserLanguage", Matcher::Missing)
+    .match_header("PartnerName", Matcher::Missing)
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+
+    let client = TransactionService::new(SERVER_URL).unwrap();
+
+    client.set_authorization("access token");
+    client.set_options(options());
+
+    let response = client
+      .get_transaction_status("9C1E5F3A-7D2B-4A6E-8F0C-1B3D5E7A9C2F")
+      .await
+      .unwrap();
+
+    assert_eq!(response.status, PaymentStatus::Completed);
+  }
+
+  #[tokio::test]
+  async fn test_with_context() {
+    let path = "/context/mvola/mm/transactions/type/merchantpay/1.0.0/status/4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F";
+    let generated = mock("GET", path)
+      .match_header(
+        "X-CorrelationID",
+        Matcher::Regex(String::from("^[0-9a-f]{8}-[0-9a-f]{4}-4[0-9a-f]{3}-")),
+      )
+      .match_header("UserLanguage", "FR")
+      .match_header("UserAccountIdentifier", "msisdn;0343500003")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_status.json")
+      .expect(2)
+      .create();
+    let overridden = mock("GET", path)
+      .match_header("X-CorrelationID", "order-42")
+      .match_header("UserLanguage", "MG")
+      .match_header("UserAccountIdentifier", "msisdn;0343500005")
+      .match_header("PartnerName", "TestMVola")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_status.json")
+      .create();
+
+    // One client per request: the mock server closes each connection.
+    let client = || {
+      let client = TransactionService::new(&format!("{}/context", SERVER_URL)).unwrap();
+      client.set_authorization("access token");
+      client.set_options(Options {
+        user_language: Some(String::from("FR")),
+        partner_name: Some(String::from("TestMVola")),
+        ..options()
+      });
+      client
+    };
+
+    let id = "4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F";
+    client().get_transaction_status(id).await.unwrap();
+    client().get_transaction_status(id).await.unwrap();
+    let context = RequestContext::new()
+      .correlation_id("order-42")
+      .user_language("MG")
+      .user_account_identifier("msisdn;0343500005");
+    client()
+      .with_context(context)
+      .get_transaction_status(id)
+      .await
+      .unwrap();
+
+    generated.assert();
+    overridden.assert();
+  }
+
+  #[tokio::test]
+  async fn test_transaction_types() {
+    let status = mock(
+      "GET",
+      "/types/mvola/mm/transactions/type/disbursement/2.0.0/status/4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+    let transfer = mock("POST", "/types/mvola/mm/transactions/type/transfer/2.0.0/")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_response.json")
+      .create();
+    let merchantpay = mock(
+      "GET",
+      "/types/mvola/mm/transactions/type/merchantpay/1.0.0/status/4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F",
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .create();
+
+    // One client per request: the mock server closes each connection.
+    let client = || {
+      let client = TransactionService::new(&format!("{}/types", SERVER_URL)).unwrap();
+      client.set_authorization("access token");
+      client.set_options(Options {
+        user_account_identifier: String::from("msisdn;0343500004"),
+        ..options()
+      });
+      client.set_transaction_api(TransactionApi::new(
+        TransactionType::Disbursement,
+        ApiVersion::new(2, 0, 0),
+      ));
+      client
+    };
+
+    let id = "4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F";
+    client().get_transaction_status(id).await.unwrap();
+    let context = RequestContext::new()
+      .transaction_type(TransactionType::MerchantPay)
+      .api_version(ApiVersion::V1);
+    client()
+      .with_context(context)
+      .get_transaction_status(id)
+      .await
+      .unwrap();
+    let tx = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0343500004").unwrap())
+      .credit(Msisdn::new("0343500003").unwrap())
+      .description("test")
+      .build()
+      .unwrap();
+    client()
+      .send_transaction(TransferRequest(tx))
+      .await
+      .unwrap();
+
+    status.assert();
+    transfer.assert();
+    merchantpay.assert();
+  }
+
+  #[tokio::test]
+  async fn test_refund() {
+    let refund = mock("POST", "/refund/mvola/mm/transactions/type/merchantpay/1.0.0/")
+      .match_body(Matcher::Regex(String::from(
+        r#""debitParty":\[\{"key":"msisdn","value":"0343500004"\}\],"creditParty":\[\{"key":"msisdn","value":"0343500003"\}\].*"originalTransactionReference":"636085941""#,
+      )))
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_response.json")
+      .create();
+    let rejected = mock(
+      "POST",
+      "/refund-rejected/mvola/mm/transactions/type/merchantpay/1.0.0/",
+    )
+    .with_status(400)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/error_response.json")
+    .create();
+    let server_error = mock(
+      "POST",
+      "/refund-error/mvola/mm/transactions/type/merchantpay/1.0.0/",
+    )
+    .with_status(500)
+    .create();
+
+    let new_client = |path: &str| {
+      let client = TransactionService::new(&format!("{}/{}", SERVER_URL, path)).unwrap();
+      client.set_authorization("access token");
+      client.set_options(Options {
+        user_account_identifier: String::from("msisdn;0343500004"),
+        ..options()
+      });
+      client
+    };
+    let original: TransactionDetails =
+      serde_json::from_str(include_str!("../tests/fixtures/transaction_details.json")).unwrap();
+
+    let client = new_client("refund");
+    client.refund(&original, Money::ariary(4000)).await.unwrap();
+    assert_eq!(client.refunds().remaining(&original), Money::ariary(6000));
+    let err = client
+      .refund(&original, Money::ariary(7000))
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::RefundExceedsOriginal { .. }),
+      "{:?}",
+      err
+    );
+    refund.assert();
+
+    // A rejected refund can be sent again.
+    let client = new_client("refund-rejected");
+    let err = client
+      .refund(&original, Money::ariary(10000))
+      .await
+      .unwrap_err();
+    assert!(matches!(err, MVolaError::Api(_)), "{:?}", err);
+    assert_eq!(
+      client.refunds().refunded(&original.transaction_reference),
+      Money::ZERO
+    );
+    rejected.assert();
+
+    // A refund failing with a server error may have been executed.
+    let client = new_client("refund-error");
+    let err = client
+      .refund(&original, Money::ariary(10000))
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::Api(ref e) if e.status == 500),
+      "{:?}",
+      err
+    );
+    assert_eq!(client.refunds().remaining(&original), Money::ZERO);
+    let err = client
+      .refund(&original, Money::ariary(1000))
+      .await
+      .unwrap_err();
+    assert!(
+      matches!(err, MVolaError::RefundExceedsOriginal { .. }),
+      "{:?}",
+      err
+    );
+    server_error.assert();
+  }
+
+  #[tokio::test]
+  async fn test_idempotent_payments() {
+    let transactions = "mvola/mm/transactions/type/merchantpay/1.0.0";
+    let accepted = mock("POST", format!("/idempotent/{}/", transactions).as_str())
+      .with_status(202)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_response.json")
+      .expect(1)
+      .create();
+    let status = mock(
+      "GET",
+      format!(
+        "/idempotent/{}/status/a6b5569b-6181-4fc9-bee3-b9f928dd7ae3",
+        transactions
+      )
+      .as_str(),
+    )
+    .with_status(200)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_status.json")
+    .expect(1)
+    .create();
+    let lost = mock(
+      "POST",
+      format!("/idempotent-lost/{}/", transactions).as_str(),
+    )
+    .with_status(503)
+    .expect(1)
+    .create();
+    let rejected = mock(
+      "POST",
+      format!("/idempotent-rejected/{}/", transactions).as_str(),
+    )
+    .with_status(400)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/error_response.json")
+    .expect(2)
+    .create();
+
+    // The store outlives the clients, like a database shared by restarts.
+    let store = Arc::new(InMemoryIdempotencyStore::new());
+    let new_client = |path: &str| {
+      let client = TransactionService::new(&format!("{}/{}", SERVER_URL, path)).unwrap();
+      client.set_authorization("access token");
+      client.set_options(Options {
+        user_account_identifier: String::from("msisdn;0343500004"),
+        ..options()
+      });
+      client.set_idempotency_store(store.clone());
+      client
+    };
+    let payment = |reference: &str| {
+      TransactionRequest::builder()
+        .amount(Money::ariary(1000))
+        .debit(Msisdn::new("0343500003").unwrap())
+        .credit(Msisdn::new("0343500004").unwrap())
+        .description("test")
+        .reference(reference)
+        .build()
+        .unwrap()
+    };
+
+    // An accepted payment is not sent again, its status is returned.
+    let context = RequestContext::new().idempotency_key("order-42");
+    let response = new_client("idempotent")
+      .with_context(context.clone())
+      .send_payment(payment("first attempt"))
+      .await
+      .unwrap();
+    assert_eq!(response.status, PaymentStatus::Pending);
+    let response = new_client("idempotent")
+      .with_context(context)
+      .send_payment(payment("second attempt"))
+      .await
+      .unwrap();
+    assert_eq!(response.status, PaymentStatus::Completed);
+    accepted.assert();
+    status.assert();
+
+    // A server error may hide an executed payment.
+    let err = new_client("idempotent-lost")
+      .send_payment(payment("lost"))
+      .await
+      .unwrap_err();
+    assert!(matches!(err, MVolaError::Api(_)), "{:?}", err);
+    let err = new_client("idempotent-lost")
+      .send_payment(payment("lost"))
+      .await
+      .unwrap_err();
+    match err {
+      MVolaError::IndeterminatePayment { idempotency_key } => assert_eq!(idempotency_key, "lost"),
+      err => panic!("unexpected error: {:?}", err),
+    }
+    lost.assert();
+
+    // A rejected payment can be sent again.
+    for _ in 0..2 {
+      let err = new_client("idempotent-rejected")
+        .send_payment(payment("rejected"))
+        .await
+        .unwrap_err();
+      assert!(matches!(err, MVolaError::Api(_)), "{:?}", err);
+    }
+    rejected.assert();
+  }
+
+  /// Times out the payments, and answers the status requests.
+  #[derive(Default)]
+  struct TimeoutTransport {
+    requests: Mutex<Vec<HttpRequest>>,
+  }
+
+  #[async_trait]
+  impl HttpTransport for TimeoutTransport {
+    async fn send(&self, request: HttpRequest) -> Result<HttpResponse> {
+      let method = request.method;
+      self.requests.lock().unwrap().push(request);
+      match method {
+        Method::Post => Err(MVolaError::Timeout),
+        _ => Ok(HttpResponse {
+          status: 200,
+          headers: vec![(
+            String::from("content-type"),
+            String::from("application/json"),
+          )],
+          body: include_bytes!("../tests/fixtures/transaction_status.json").to_vec(),
+        }),
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn test_resolve_timed_out_payment() {
+    let transport = Arc::new(TimeoutTransport::default());
+    let store = Arc::new(InMemoryIdempotencyStore::new());
+    let client =
+      TransactionService::with_transport("https://devapi.mvola.mg", transport.clone()).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+    client.set_idempotency_store(store.clone());
+    let payment = || {
+      TransactionRequest::builder()
+        .amount(Money::ariary(1000))
+        .debit(Msisdn::new("0343500003").unwrap())
+        .credit(Msisdn::new("0343500004").unwrap())
+        .description("test")
+        .reference("timed out")
+        .build()
+        .unwrap()
+    };
+
+    let err = client.send_payment(payment()).await.unwrap_err();
+    assert!(matches!(err, MVolaError::Timeout), "{:?}", err);
+    let err = client.send_payment(payment()).await.unwrap_err();
+    assert!(
+      matches!(err, MVolaError::IndeterminatePayment { .. }),
+      "{:?}",
+      err
+    );
+    assert_eq!(transport.requests.lock().unwrap().len(), 1);
+
+    // MVola did not execute it: the payment is sent again.
+    store.resolve("timed out", None).unwrap();
+    let err = client.send_payment(payment()).await.unwrap_err();
+    assert!(matches!(err, MVolaError::Timeout), "{:?}", err);
+    assert_eq!(transport.requests.lock().unwrap().len(), 2);
+
+    // MVola received it: its status is requested instead.
+    store
+      .resolve("timed out", Some("2ba1d66a-25cf-4c12-8a6f-4cb01255148e"))
+      .unwrap();
+    let response = client.send_payment(payment()).await.unwrap();
+    assert_eq!(response.status, PaymentStatus::Completed);
+    let requests = transport.requests.lock().unwrap();
+    assert_eq!(requests.len(), 3);
+    assert_eq!(requests[2].method, Method::Get);
+  }
+
+  /// Fails to record the accepted payments.
+  struct FailingStore;
+
+  impl IdempotencyStore for FailingStore {
+    fn begin(&self, _key: &str) -> Result<Option<Attempt>> {
+      Ok(None)
+    }
+
+    fn accept(&self, _key: &str, _server_correlation_id: &str) -> Result<()> {
+      Err(MVolaError::IdempotencyStore("database is locked".into()))
+    }
+
+    fn remove(&self, _key: &str) -> Result<()> {
+      Ok(())
+    }
+  }
+
+  #[tokio::test]
+  async fn test_store_failure_keeps_response() {
+    let accepted = mock(
+      "POST",
+      "/store-failure/mvola/mm/transactions/type/merchantpay/1.0.0/",
+    )
+    .with_status(202)
+    .with_header("Content-Type", "application/json")
+    .with_body_from_file("tests/fixtures/transaction_response.json")
+    .create();
+
+    let client = TransactionService::new(&format!("{}/store-failure", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(options());
+    client.set_idempotency_store(Arc::new(FailingStore));
+    let payment = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0343500003").unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test")
+      .reference("store failure")
+      .build()
+      .unwrap();
+    let response = client.send_payment(payment).await.unwrap();
+    assert_eq!(
+      response.server_correlation_id,
+      "a6b5569b-6181-4fc9-bee3-b9f928dd7ae3"
+    );
+    accepted.assert();
+  }
+
+  #[tokio::test]
+  async fn test_shared_across_tasks() {
+    fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+    assert_shareable::<crate::MVola>();
+    assert_shareable::<TransactionService>();
+    #[cfg(feature = "blocking")]
+    assert_shareable::<crate::blocking::MVola>();
+
+    let path = "/shared/mvola/mm/transactions/type/merchantpay/1.0.0/status/4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F";
+    let m = mock("GET", path)
+      .match_header("Authorization", "Bearer refreshed token")
+      .with_status(200)
+      .with_header("Content-Type", "application/json")
+      .with_body_from_file("tests/fixtures/transaction_status.json")
+      .expect(4)
+      .create();
+
+    let client = TransactionService::new(&format!("{}/shared", SERVER_URL)).unwrap();
+    client.set_authorization("access token");
+    client.set_options(Options {
+      user_language: Some(String::from("FR")),
+      partner_name: Some(String::from("TestMVola")),
+      ..options()
+    });
+    // The clones share the settings of the service.
+    client.clone().set_authorization("refreshed token");
+
+    let tasks: Vec<_> = (0..4)
+      .map(|_| {
+        let client = client.clone();
+        tokio::spawn(async move {
+          client
+            .get_transaction_status("4E2F8B1C-3A5D-4C7E-9B0F-6D8A2C4E1B3F")
+            .await
+        })
+      })
+      .collect();
+    for task in tasks {
+      let status = task.await.unwrap().unwrap();
+      assert_eq!(status.status, PaymentStatus::Completed);
+    }
+
+    m.assert();
+  }
+
+  #[test]
+  fn test_invalid_base_url() {
+    assert!(matches!(
+      TransactionService::new("not a url"),
+      Err(MVolaError::InvalidUrl(_))
+    ));
   }
 }
@@ -1,8 +1,17 @@
+use crate::error::{MVolaError, Result};
+use crate::money::Money;
+use crate::msisdn::Msisdn;
+use crate::party::{Party, TransactionMetadata};
+use crate::routes::TransactionType;
+use crate::status::{NotificationMethod, PaymentStatus};
+use chrono::{DateTime, SecondsFormat, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
+/// The settings of a service, shared by its clones.
 pub trait Service {
-  fn set_authorization(&mut self, token: &str);
-  fn set_options(&mut self, options: Options);
+  fn set_authorization(&self, token: &str);
+  fn set_options(&self, options: Options);
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,70 +28,430 @@ pub struct AuthRequest {
   pub scope: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionRequest {
-  pub amount: String,
+  pub amount: Money,
   pub currency: String,
   pub description_text: String,
   pub request_date: String,
-  pub debit_party: Vec<KeyValue>,
-  pub credit_party: Vec<KeyValue>,
-  pub metadata: Vec<KeyValue>,
+  pub debit_party: Party,
+  pub credit_party: Party,
+  pub metadata: TransactionMetadata,
   pub requesting_organisation_transaction_reference: String,
   pub original_transaction_reference: String,
 }
 
+impl TransactionRequest {
+  /// Build a request without writing the dates and references by hand.
+  /// # Example
+  /// ```no_run
+  /// let tx = TransactionRequest::builder()
+  ///   .amount(Money::ariary(1000))
+  ///   .debit(Msisdn::new("0343500003").unwrap())
+  ///   .credit(Msisdn::new("0343500004").unwrap())
+  ///   .description("test")
+  ///   .partner_name("TestMVola")
+  ///   .build()
+  ///   .unwrap();
+  /// ```
+  pub fn builder() -> TransactionRequestBuilder {
+    TransactionRequestBuilder::default()
+  }
+}
+
+/// Builder for a `TransactionRequest`, see `TransactionRequest::builder`.
+#[derive(Debug, Clone, Default)]
+pub struct TransactionRequestBuilder {
+  amount: Option<Money>,
+  debit: Option<Msisdn>,
+  credit: Option<Msisdn>,
+  description: Option<String>,
+  foreign_currency: Option<(String, Money)>,
+  partner_name: Option<String>,
+  reference: Option<String>,
+  original_reference: Option<String>,
+  request_date: Option<DateTime<Utc>>,
+}
+
+impl TransactionRequestBuilder {
+  /// The amount in Ariary.
+  pub fn amount(mut self, amount: Money) -> Self {
+    self.amount = Some(amount);
+    self
+  }
+
+  /// The number that pays, usually the customer.
+  pub fn debit(mut self, msisdn: Msisdn) -> Self {
+    self.debit = Some(msisdn);
+    self
+  }
+
+  /// The number that is paid, usually the merchant.
+  pub fn credit(mut self, msisdn: Msisdn) -> Self {
+    self.credit = Some(msisdn);
+    self
+  }
+
+  /// The description shown to the customer.
+  pub fn description(mut self, description: &str) -> Self {
+    self.description = Some(String::from(description));
+    self
+  }
+
+  /// The amount in a foreign currency, e.g. `("USD", Money::ariary(1))`.
+  pub fn foreign_currency(mut self, code: &str, amount: Money) -> Self {
+    self.foreign_currency = Some((String::from(code), amount));
+    self
+  }
+
+  /// The `partnerName` metadata.
+  pub fn partner_name(mut self, partner_name: &str) -> Self {
+    self.partner_name = Some(String::from(partner_name));
+    self
+  }
+
+  /// The reference of the transaction on the merchant side. Defaults to a new UUID.
+  pub fn reference(mut self, reference: &str) -> Self {
+    self.reference = Some(String::from(reference));
+    self
+  }
+
+  /// The reference of the original transaction. Defaults to the reference.
+  pub fn original_reference(mut self, reference: &str) -> Self {
+    self.original_reference = Some(String::from(reference));
+    self
+  }
+
+  /// The date of the request. Defaults to now.
+  pub fn request_date(mut self, request_date: DateTime<Utc>) -> Self {
+    self.request_date = Some(request_date);
+    self
+  }
+
+  /// Build the request.
+  /// # Errors
+  /// * `MVolaError::IncompleteRequest` - If the amount, a party or the description is missing
+  /// * `MVolaError::InvalidAmount` - If the amount is not a positive, whole amount of Ariary
+  pub fn build(self) -> Result<TransactionRequest> {
+    let missing: Vec<&'static str> = [
+      ("amount", self.amount.is_none()),
+      ("debit", self.debit.is_none()),
+      ("credit", self.credit.is_none()),
+      ("description", self.description.is_none()),
+    ]
+    .iter()
+    .filter(|(_, missing)| *missing)
+    .map(|(field, _)| *field)
+    .collect();
+
+    let (Some(amount), Some(debit), Some(credit), Some(description)) =
+      (self.amount, self.debit, self.credit, self.description)
+    else {
+      return Err(MVolaError::IncompleteRequest(missing));
+    };
+    amount.validate_payment()?;
+
+    let (foreign_currency, amount_foreign_currency) = match self.foreign_currency {
+      Some((code, amount)) => (Some(code), Some(amount.to_string())),
+      None => (None, None),
+    };
+    let mut metadata = TransactionMetadata::default();
+    metadata.partner_name = self.partner_name;
+    metadata.foreign_currency = foreign_currency;
+    metadata.amount_foreign_currency = amount_foreign_currency;
+
+    let reference = self.reference.unwrap_or_else(|| Uuid::new_v4().to_string());
+    Ok(TransactionRequest {
+      amount,
+      currency: String::from("Ar"),
+      description_text: description,
+      request_date: self
+        .request_date
+        .unwrap_or_else(Utc::now)
+        .to_rfc3339_opts(SecondsFormat::Millis, true),
+      debit_party: debit.to_party(),
+      credit_party: credit.to_party(),
+      metadata,
+      original_transaction_reference: self.original_reference.unwrap_or_else(|| reference.clone()),
+      requesting_organisation_transaction_reference: reference,
+    })
+  }
+}
+
+/// A transaction request sent to the endpoints of its transaction type.
+///
+/// The body is the same `TransactionRequest` for every type, see
+/// `TransactionService::send_transaction`.
+pub trait TypedRequest: Send + Sync {
+  fn transaction_type(&self) -> TransactionType;
+  fn request(&self) -> &TransactionRequest;
+}
+
+/// A payment from a customer to a merchant.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct MerchantPayRequest(pub TransactionRequest);
+
+impl TypedRequest for MerchantPayRequest {
+  fn transaction_type(&self) -> TransactionType {
+    TransactionType::MerchantPay
+  }
+
+  fn request(&self) -> &TransactionRequest {
+    &self.0
+  }
+}
+
+impl From<TransactionRequest> for MerchantPayRequest {
+  fn from(request: TransactionRequest) -> Self {
+    Self(request)
+  }
+}
+
+/// A payment from a merchant to a customer.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct DisbursementRequest(pub TransactionRequest);
+
+impl TypedRequest for DisbursementRequest {
+  fn transaction_type(&self) -> TransactionType {
+    TransactionType::Disbursement
+  }
+
+  fn request(&self) -> &TransactionRequest {
+    &self.0
+  }
+}
+
+impl From<TransactionRequest> for DisbursementRequest {
+  fn from(request: TransactionRequest) -> Self {
+    Self(request)
+  }
+}
+
+/// A transfer between two accounts.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(transparent)]
+pub struct TransferRequest(pub TransactionRequest);
+
+impl TypedRequest for TransferRequest {
+  fn transaction_type(&self) -> TransactionType {
+    TransactionType::Transfer
+  }
+
+  fn request(&self) -> &TransactionRequest {
+    &self.0
+  }
+}
+
+impl From<TransactionRequest> for TransferRequest {
+  fn from(request: TransactionRequest) -> Self {
+    Self(request)
+  }
+}
+
+/// A transaction of a type this version of the crate does not know.
+#[derive(Debug)]
+pub struct CustomRequest {
+  pub transaction_type: TransactionType,
+  pub request: TransactionRequest,
+}
+
+impl TypedRequest for CustomRequest {
+  fn transaction_type(&self) -> TransactionType {
+    self.transaction_type.clone()
+  }
+
+  fn request(&self) -> &TransactionRequest {
+    &self.request
+  }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionResponse {
-  pub status: String,
+  pub status: PaymentStatus,
   pub server_correlation_id: String,
-  pub notification_method: String,
+  pub notification_method: NotificationMethod,
+}
+
+impl From<TransactionStatus> for TransactionResponse {
+  /// The response of a payment sent earlier, from its current status.
+  fn from(status: TransactionStatus) -> Self {
+    Self {
+      status: status.status,
+      server_correlation_id: status.server_correlation_id,
+      notification_method: status.notification_method,
+    }
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionDetails {
-  pub amount: String,
+  pub amount: Money,
   pub currency: String,
   pub transaction_reference: String,
-  pub transaction_status: String,
+  pub transaction_status: PaymentStatus,
   pub creation_date: String,
   pub request_date: String,
-  pub debit_party: Vec<KeyValue>,
-  pub credit_party: Vec<KeyValue>,
-  pub metadata: Vec<KeyValue>,
+  pub debit_party: Party,
+  pub credit_party: Party,
+  pub metadata: TransactionMetadata,
   pub fees: Vec<Fee>,
 }
 
+impl TransactionDetails {
+  /// The sum of the fees of the transaction.
+  pub fn total_fees(&self) -> Money {
+    self.fees.iter().map(|fee| fee.fee_amount).sum()
+  }
+
+  /// The amount of the transaction plus its fees.
+  pub fn total_with_fees(&self) -> Money {
+    self.amount + self.total_fees()
+  }
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct TransactionStatus {
-  pub status: String,
+  pub status: PaymentStatus,
   pub server_correlation_id: String,
-  pub notification_method: String,
+  pub notification_method: NotificationMethod,
+  /// The reference used by `get_transaction`, empty while the transaction is pending.
+  #[serde(default)]
   pub object_reference: String,
 }
 
-#[derive(Serialize, Deserialize)]
+/// The default headers of the transaction service.
+///
+/// The `X-CorrelationID` is generated for each request, and the defaults can
+/// be overridden per call with a `RequestContext`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Options {
   pub version: String,
-  pub correlation_id: String,
   pub user_language: Option<String>,
   pub user_account_identifier: String,
   pub partner_name: Option<String>,
   pub callback_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct KeyValue {
   pub key: String,
   pub value: String,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+impl KeyValue {
+  pub fn new(key: &str, value: &str) -> Self {
+    Self {
+      key: String::from(key),
+      value: String::from(value),
+    }
+  }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 #[serde(rename_all = "camelCase")]
 pub struct Fee {
-  pub fee_amount: String,
+  pub fee_amount: Money,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorResponse {
+  pub error_category: String,
+  pub error_code: String,
+  pub error_description: Option<String>,
+  pub error_date_time: Option<String>,
+  #[serde(default)]
+  pub error_parameters: Vec<KeyValue>,
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::error::MVolaError;
+  use crate::money::Money;
+  use crate::msisdn::Msisdn;
+  use crate::types::TransactionRequest;
+  use chrono::{TimeZone, Utc};
+
+  #[test]
+  fn test_transaction_request_builder() {
+    let tx = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0343500003").unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test")
+      .foreign_currency("USD", Money::ariary(1))
+      .partner_name("TestMVola")
+      .request_date(Utc.with_ymd_and_hms(2022, 3, 10, 8, 30, 0).unwrap())
+      .build()
+      .unwrap();
+
+    assert_eq!(tx.request_date, "2022-03-10T08:30:00.000Z");
+    assert_eq!(tx.currency, "Ar");
+    assert_eq!(tx.debit_party.msisdn.as_deref(), Some("0343500003"));
+    assert_eq!(tx.credit_party.msisdn.as_deref(), Some("0343500004"));
+    let metadata: Vec<(String, String)> = tx
+      .metadata
+      .to_key_values()
+      .into_iter()
+      .map(|kv| (kv.key, kv.value))
+      .collect();
+    assert_eq!(
+      metadata,
+      vec![
+        (String::from("partnerName"), String::from("TestMVola")),
+        (String::from("fc"), String::from("USD")),
+        (String::from("amountFc"), String::from("1")),
+      ]
+    );
+    assert_eq!(tx.requesting_organisation_transaction_reference.len(), 36);
+    assert_eq!(
+      tx.original_transaction_reference,
+      tx.requesting_organisation_transaction_reference
+    );
+  }
+
+  #[test]
+  fn test_transaction_request_builder_references() {
+    let builder = TransactionRequest::builder()
+      .amount(Money::ariary(1000))
+      .debit(Msisdn::new("0343500003").unwrap())
+      .credit(Msisdn::new("0343500004").unwrap())
+      .description("test");
+
+    let first = builder.clone().build().unwrap();
+    let second = builder.clone().build().unwrap();
+    assert_ne!(
+      first.requesting_organisation_transaction_reference,
+      second.requesting_organisation_transaction_reference
+    );
+    assert!(first.metadata.is_empty());
+
+    let tx = builder
+      .reference("order-42")
+      .original_reference("order-41")
+      .build()
+      .unwrap();
+    assert_eq!(tx.requesting_organisation_transaction_reference, "order-42");
+    assert_eq!(tx.original_transaction_reference, "order-41");
+  }
+
+  #[test]
+  fn test_incomplete_transaction_request() {
+    let err = TransactionRequest::builder()
+      .debit(Msisdn::new("0343500003").unwrap())
+      .build()
+      .unwrap_err();
+    match err {
+      MVolaError::IncompleteRequest(fields) => {
+        assert_eq!(fields, vec!["amount", "credit", "description"]);
+      }
+      err => panic!("unexpected error: {:?}", err),
+    }
+  }
 }